@@ -24,6 +24,14 @@ pub enum Commands {
         #[clap(short, long)]
         pretty_print: bool,
 
+        /// Show the object type instead of its content.
+        #[clap(short = 't', conflicts_with_all = ["pretty_print", "show_size"])]
+        show_type: bool,
+
+        /// Show the object size instead of its content.
+        #[clap(short = 's', conflicts_with = "pretty_print")]
+        show_size: bool,
+
         /// The hash of the object to display.
         object_hash: String,
     },
@@ -36,14 +44,49 @@ pub enum Commands {
 
     /// Lists the contents of a tree object in a Git repository.
     LsTree {
-        /// The hash of the object to list.
+        /// Recurse into subtrees.
+        #[clap(short = 'r')]
+        recursive: bool,
+
+        /// Show tree entries even when recursing.
+        #[clap(short = 't')]
+        show_trees: bool,
+
+        /// List only the path of each entry.
+        #[clap(long)]
+        name_only: bool,
+
+        /// Show the size of blob entries.
+        #[clap(short, long)]
+        long: bool,
+
+        /// The hash of the tree (or commit) to list.
         object_hash: String,
     },
 
-    /// Writes a tree object to the Git repository.
-    WriteTree {
-        /// The tree to write.
+    /// Writes a tree object from the current index.
+    WriteTree,
+
+    /// Creates a new commit object from a tree.
+    CommitTree {
+        /// The tree object the commit records.
         tree: String,
+
+        /// Parent commit, may be given more than once.
+        #[clap(short = 'p', value_name = "PARENT")]
+        parents: Vec<String>,
+
+        /// Commit message, each -m becomes its own paragraph. Read from stdin if omitted.
+        #[clap(short, long = "message", value_name = "MESSAGE")]
+        messages: Vec<String>,
+    },
+
+    /// Records the staged changes as a new commit on the current branch.
+    Commit {
+        /// Commit message, each -m becomes its own paragraph. Required
+        /// unless concluding a merge, which has a message prepared.
+        #[clap(short, long = "message", value_name = "MESSAGE")]
+        messages: Vec<String>,
     },
 
     /// Adds a file to the staging area.
@@ -62,6 +105,536 @@ pub enum Commands {
         objectfile: String,
     },
     
+    /// Resolves revisions (`HEAD~2`, `main^{tree}`, `HEAD:path`, ...) to object ids.
+    RevParse {
+        /// Print abbreviated object ids.
+        #[clap(long)]
+        short: bool,
+
+        /// Print the short name of the ref instead of its object id.
+        #[clap(long)]
+        abbrev_ref: bool,
+
+        /// The revisions to resolve.
+        #[clap(required = true)]
+        revisions: Vec<String>,
+    },
+
+    /// Updates the object a ref points at, optionally only if it currently has an expected value.
+    UpdateRef {
+        /// Delete the ref instead of updating it.
+        #[clap(short)]
+        delete: bool,
+
+        /// Update a symbolic ref itself instead of the ref it points at.
+        #[clap(long)]
+        no_deref: bool,
+
+        /// Reflog message for the update.
+        #[clap(short)]
+        message: Option<String>,
+
+        /// The ref to update, e.g. refs/heads/main.
+        refname: String,
+
+        /// The new value followed by the optional expected old value (only the old value with -d).
+        values: Vec<String>,
+    },
+
+    /// Reads, or with a target sets, the ref a symbolic ref like HEAD points at.
+    SymbolicRef {
+        /// Exit with status 1 without an error if the ref isn't symbolic.
+        #[clap(short, long)]
+        quiet: bool,
+
+        /// Print the target without its refs/heads/ (or similar) prefix.
+        #[clap(long)]
+        short: bool,
+
+        /// Reflog message for the update.
+        #[clap(short)]
+        message: Option<String>,
+
+        /// The symbolic ref, usually HEAD.
+        name: String,
+
+        /// The ref it should point at.
+        target: Option<String>,
+    },
+
+    /// Lists refs and the objects they point at.
+    ShowRef {
+        /// Only show branches.
+        #[clap(long)]
+        heads: bool,
+
+        /// Only show tags.
+        #[clap(long)]
+        tags: bool,
+
+        /// Only print the object ids.
+        #[clap(long = "hash")]
+        hash_only: bool,
+
+        /// Only show refs whose name ends with one of these patterns.
+        patterns: Vec<String>,
+    },
+
+    /// Lists, creates, deletes or renames branches.
+    Branch {
+        /// Delete a branch that is merged into its upstream or HEAD.
+        #[clap(short = 'd', long)]
+        delete: bool,
+
+        /// Delete a branch even if it is not merged.
+        #[clap(short = 'D')]
+        force_delete: bool,
+
+        /// Rename a branch, along with its reflog and config.
+        #[clap(short = 'm', long = "move")]
+        rename: bool,
+
+        /// Rename a branch even if the new name already exists.
+        #[clap(short = 'M')]
+        force_rename: bool,
+
+        /// Create or rename over an existing branch.
+        #[clap(short, long)]
+        force: bool,
+
+        /// Show the tip commit of each branch (twice to also show upstreams).
+        #[clap(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
+        /// Set the upstream of a branch to a local or remote-tracking branch.
+        #[clap(short = 'u', long, value_name = "UPSTREAM")]
+        set_upstream_to: Option<String>,
+
+        /// Remove the upstream of a branch.
+        #[clap(long)]
+        unset_upstream: bool,
+
+        /// Branch names, followed by a start point when creating a branch.
+        args: Vec<String>,
+    },
+
+    /// Lists, creates or deletes tags.
+    Tag {
+        /// Make an annotated tag object.
+        #[clap(short, long)]
+        annotate: bool,
+
+        /// The tag message (implies `-a`); several become separate paragraphs.
+        #[clap(short, long)]
+        message: Vec<String>,
+
+        /// Replace an existing tag.
+        #[clap(short, long)]
+        force: bool,
+
+        /// Delete tags.
+        #[clap(short, long)]
+        delete: bool,
+
+        /// List tags, only those matching the given patterns if any.
+        #[clap(short, long)]
+        list: bool,
+
+        /// Sort listed tags by `refname` or `version:refname` (`-` reverses).
+        #[clap(long, value_name = "KEY", allow_hyphen_values = true)]
+        sort: Option<String>,
+
+        /// The tag name and the object to tag, or patterns with `-l`.
+        args: Vec<String>,
+    },
+
+    /// Switches branches or detaches HEAD, or restores files with `-- <paths>`.
+    Checkout {
+        /// Create a new branch and switch to it.
+        #[clap(short = 'b', value_name = "NEW_BRANCH")]
+        new_branch: Option<String>,
+
+        /// Detach HEAD even if the target is a branch.
+        #[clap(long)]
+        detach: bool,
+
+        /// Throw away local changes that are in the way.
+        #[clap(short, long)]
+        force: bool,
+
+        /// The branch or commit to check out (or to restore paths from).
+        target: Option<String>,
+
+        /// Paths to restore, given after `--`.
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Switches to a branch.
+    Switch {
+        /// Create a new branch and switch to it.
+        #[clap(short = 'c', long, value_name = "NEW_BRANCH")]
+        create: Option<String>,
+
+        /// Switch to a commit with a detached HEAD.
+        #[clap(short, long)]
+        detach: bool,
+
+        /// Throw away local changes that are in the way.
+        #[clap(short, long)]
+        force: bool,
+
+        /// The branch to switch to (or the start point with `-c`).
+        target: Option<String>,
+    },
+
+    /// Shows staged, unstaged and untracked changes.
+    Status {
+        /// Give the output in the short format.
+        #[clap(short, long)]
+        short: bool,
+
+        /// Give the output in a stable format for scripts (`v1` or `v2`).
+        #[clap(long, value_name = "VERSION", num_args = 0..=1, default_missing_value = "v1")]
+        porcelain: Option<String>,
+
+        /// Show the branch and its upstream in the short and porcelain formats.
+        #[clap(short, long)]
+        branch: bool,
+    },
+
+    /// Shows the commit history.
+    Log {
+        /// Show each commit on one line, with an abbreviated id.
+        #[clap(long)]
+        oneline: bool,
+
+        /// Draw the commit graph next to the log.
+        #[clap(long)]
+        graph: bool,
+
+        /// oneline, short, medium, full, fuller, or a `format:` string with placeholders.
+        #[clap(long, visible_alias = "pretty", value_name = "FORMAT")]
+        format: Option<String>,
+
+        /// Show at most this many commits.
+        #[clap(short = 'n', long, value_name = "NUMBER")]
+        max_count: Option<usize>,
+
+        /// Only follow the first parent of merge commits.
+        #[clap(long)]
+        first_parent: bool,
+
+        /// Show children before parents, one line of history at a time.
+        #[clap(long, conflicts_with_all = ["date_order", "author_date_order"])]
+        topo_order: bool,
+
+        /// Show children before parents, otherwise by commit date.
+        #[clap(long, conflicts_with = "author_date_order")]
+        date_order: bool,
+
+        /// Show children before parents, otherwise by author date.
+        #[clap(long)]
+        author_date_order: bool,
+
+        /// Only show commits after this date.
+        #[clap(long, visible_alias = "after", value_name = "DATE")]
+        since: Option<String>,
+
+        /// Only show commits before this date.
+        #[clap(long, visible_alias = "before", value_name = "DATE")]
+        until: Option<String>,
+
+        /// Only show commits whose author contains this text.
+        #[clap(long, value_name = "PATTERN")]
+        author: Option<String>,
+
+        /// Only show commits whose message contains this text.
+        #[clap(long, value_name = "PATTERN")]
+        grep: Option<String>,
+
+        /// Revisions and ranges (`A..B`, `A...B`, `^A`) to show; HEAD by default.
+        revisions: Vec<String>,
+
+        /// Only show commits that change these paths, given after `--`.
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Shows changes between the work tree, the index and commits.
+    Diff {
+        /// Compare the index with HEAD (or the given commit).
+        #[clap(long, visible_alias = "staged")]
+        cached: bool,
+
+        /// Show this many lines of context around each change.
+        #[clap(short = 'U', long, value_name = "LINES", default_value_t = 3)]
+        unified: usize,
+
+        /// myers (the default), patience or histogram.
+        #[clap(long, value_name = "ALGORITHM", conflicts_with_all = ["patience", "histogram"])]
+        diff_algorithm: Option<String>,
+
+        /// Use the patience diff algorithm.
+        #[clap(long, conflicts_with = "histogram")]
+        patience: bool,
+
+        /// Use the histogram diff algorithm.
+        #[clap(long)]
+        histogram: bool,
+
+        /// Only show the names of changed files.
+        #[clap(long, conflicts_with_all = ["name_status", "stat"])]
+        name_only: bool,
+
+        /// Show the names and status letters of changed files.
+        #[clap(long, conflicts_with = "stat")]
+        name_status: bool,
+
+        /// Show how many lines changed in each file.
+        #[clap(long)]
+        stat: bool,
+
+        /// Detect renames of files at least this similar (50% by default).
+        #[clap(short = 'M', long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "50%")]
+        find_renames: Option<String>,
+
+        /// Detect copies as well as renames.
+        #[clap(short = 'C', long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "50%")]
+        find_copies: Option<String>,
+
+        /// Don't pair up deleted and added files as renames.
+        #[clap(long, conflicts_with_all = ["find_renames", "find_copies"])]
+        no_renames: bool,
+
+        /// Commits (or an `A..B` range) to compare.
+        revisions: Vec<String>,
+
+        /// Only show changes to these paths, given after `--`.
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Compares two trees, or a commit with its parent.
+    DiffTree {
+        /// Compare subtrees entry by entry instead of showing them as changed.
+        #[clap(short)]
+        recursive: bool,
+
+        /// Show a patch (implies `-r`).
+        #[clap(short = 'p', long)]
+        patch: bool,
+
+        /// Only show the names of changed files.
+        #[clap(long, conflicts_with_all = ["name_status", "stat"])]
+        name_only: bool,
+
+        /// Show the names and status letters of changed files.
+        #[clap(long, conflicts_with = "stat")]
+        name_status: bool,
+
+        /// Show how many lines changed in each file.
+        #[clap(long)]
+        stat: bool,
+
+        /// Detect renames of files at least this similar (50% by default).
+        #[clap(short = 'M', long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "50%")]
+        find_renames: Option<String>,
+
+        /// Detect copies as well as renames.
+        #[clap(short = 'C', long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "50%")]
+        find_copies: Option<String>,
+
+        /// Show a root commit as adding all of its files.
+        #[clap(long)]
+        root: bool,
+
+        /// A commit, or two trees (or commits) to compare.
+        #[clap(required = true, num_args = 1..=2)]
+        tree_ishes: Vec<String>,
+
+        /// Only show changes to these paths, given after `--`.
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Joins another branch's history into the current branch.
+    Merge {
+        /// Create a merge commit even when a fast-forward is possible.
+        #[clap(long, conflicts_with = "ff_only")]
+        no_ff: bool,
+
+        /// Refuse to merge unless it is a fast-forward.
+        #[clap(long)]
+        ff_only: bool,
+
+        /// Stop before committing a clean merge.
+        #[clap(long)]
+        no_commit: bool,
+
+        /// Message for the merge commit.
+        #[clap(short, long)]
+        message: Option<String>,
+
+        /// How to mark conflicts: merge (the default) or diff3.
+        #[clap(long, value_name = "STYLE")]
+        conflict: Option<String>,
+
+        /// Give up on a conflicted merge, going back to HEAD.
+        #[clap(long, conflicts_with = "continue_merge")]
+        abort: bool,
+
+        /// Commit a merge once its conflicts are resolved.
+        #[clap(long = "continue")]
+        continue_merge: bool,
+
+        /// The branch (or commit) to merge.
+        #[clap(required_unless_present_any = ["abort", "continue_merge"])]
+        commit: Option<String>,
+    },
+
+    /// Finds the best common ancestor of commits.
+    MergeBase {
+        /// Show every best common ancestor, not just one.
+        #[clap(short, long)]
+        all: bool,
+
+        /// Exit with status 0 if the first commit is an ancestor of the second, 1 if not.
+        #[clap(long, conflicts_with = "all")]
+        is_ancestor: bool,
+
+        /// The commits; the first is compared with all the others.
+        #[clap(required = true, num_args = 2..)]
+        commits: Vec<String>,
+    },
+
+    /// Applies the changes introduced by existing commits.
+    CherryPick {
+        /// For a merge, the parent (counting from 1) to take the changes against.
+        #[clap(short, long, value_name = "PARENT")]
+        mainline: Option<usize>,
+
+        /// Apply the changes without committing them.
+        #[clap(short, long)]
+        no_commit: bool,
+
+        /// Append "(cherry picked from commit ...)" to the message.
+        #[clap(short = 'x')]
+        record_origin: bool,
+
+        /// Carry on once conflicts are resolved.
+        #[clap(long = "continue", conflicts_with = "abort")]
+        continue_pick: bool,
+
+        /// Give up, going back to where it started.
+        #[clap(long)]
+        abort: bool,
+
+        /// The commits, or ranges such as `A..B`.
+        #[clap(required_unless_present_any = ["continue_pick", "abort"])]
+        commits: Vec<String>,
+    },
+
+    /// Undoes the changes introduced by existing commits with new commits.
+    Revert {
+        /// For a merge, the parent (counting from 1) to take the changes against.
+        #[clap(short, long, value_name = "PARENT")]
+        mainline: Option<usize>,
+
+        /// Apply the changes without committing them.
+        #[clap(short, long)]
+        no_commit: bool,
+
+        /// Carry on once conflicts are resolved.
+        #[clap(long = "continue", conflicts_with = "abort")]
+        continue_pick: bool,
+
+        /// Give up, going back to where it started.
+        #[clap(long)]
+        abort: bool,
+
+        /// The commits, or ranges such as `A..B`.
+        #[clap(required_unless_present_any = ["continue_pick", "abort"])]
+        commits: Vec<String>,
+    },
+
+    /// Replays the current branch's commits on top of another branch.
+    Rebase {
+        /// Edit the list of commits to replay first.
+        #[clap(short, long)]
+        interactive: bool,
+
+        /// Carry on once conflicts are resolved or a commit has been edited.
+        #[clap(long = "continue", conflicts_with_all = ["skip", "abort"])]
+        continue_rebase: bool,
+
+        /// Carry on without the commit the rebase stopped at.
+        #[clap(long, conflicts_with = "abort")]
+        skip: bool,
+
+        /// Give up, putting the branch back the way it was.
+        #[clap(long)]
+        abort: bool,
+
+        /// The branch to rebase onto; defaults to the current branch's upstream.
+        upstream: Option<String>,
+    },
+
     /// Lists the index file
-    LsFiles {},
+    LsFiles {
+        /// Show each entry's mode, object and stage.
+        #[clap(short, long)]
+        stage: bool,
+
+        /// Only show the entries of paths with merge conflicts.
+        #[clap(short, long)]
+        unmerged: bool,
+    },
+
+    /// Writes the objects listed on stdin into a pack and its index.
+    PackObjects {
+        /// How many neighbouring objects to try as delta bases.
+        #[clap(long, default_value_t = 10)]
+        window: usize,
+
+        /// The longest delta chain allowed.
+        #[clap(long, default_value_t = 50)]
+        depth: usize,
+
+        /// Where to write `<base-name>-<checksum>.pack` and `.idx`.
+        base_name: String,
+    },
+
+    /// Packs reachable objects that aren't packed yet.
+    Repack {
+        /// Pack everything reachable into a single pack.
+        #[clap(short = 'a')]
+        all: bool,
+
+        /// Delete the packs and loose objects the new pack makes redundant.
+        #[clap(short = 'd')]
+        delete: bool,
+
+        /// How many neighbouring objects to try as delta bases.
+        #[clap(long, default_value_t = 10)]
+        window: usize,
+
+        /// The longest delta chain allowed.
+        #[clap(long, default_value_t = 50)]
+        depth: usize,
+    },
+
+    /// Packs the repository and prunes old unreachable loose objects.
+    Gc {
+        /// Prune unreachable objects older than this (default `2.weeks.ago`).
+        #[clap(long, value_name = "DATE")]
+        prune: Option<String>,
+
+        /// Keep all unreachable objects.
+        #[clap(long, conflicts_with = "prune")]
+        no_prune: bool,
+    },
+
+    /// Checks the integrity of objects, refs and the index.
+    Fsck,
 }
@@ -0,0 +1,338 @@
+use anyhow::{Context, Result};
+
+use crate::repository::{
+    ahead_behind, check_ref_format, is_ancestor, peel, resolve_revision, shorten_ref_name, Config,
+    ObjectDatabase, ObjectId, ObjectKind, RefStore, RefTarget,
+};
+
+/// Options for `branch`. Without any, the branches are listed.
+#[derive(Debug, Default, Clone)]
+pub struct BranchOptions {
+    /// Delete branches that are merged into their upstream (or HEAD).
+    pub delete: bool,
+    /// Delete branches whether merged or not.
+    pub force_delete: bool,
+    /// Rename a branch.
+    pub rename: bool,
+    /// Rename a branch even if the new name already exists.
+    pub force_rename: bool,
+    /// Create or rename over an existing branch.
+    pub force: bool,
+    /// `1`: show each branch's tip commit, `2`: also its upstream.
+    pub verbose: u8,
+    /// Set the upstream of a branch to this local or remote-tracking branch.
+    pub set_upstream_to: Option<String>,
+    /// Forget the upstream of a branch.
+    pub unset_upstream: bool,
+}
+
+pub fn branch(options: BranchOptions, args: Vec<String>) -> Result<()> {
+    let refs = RefStore::default();
+
+    if let Some(upstream) = &options.set_upstream_to {
+        anyhow::ensure!(args.len() <= 1, "too many arguments to set new upstream");
+        let name = branch_or_current(&refs, args.first())?;
+        set_upstream(&refs, &name, upstream)
+    } else if options.unset_upstream {
+        anyhow::ensure!(args.len() <= 1, "too many arguments to unset upstream");
+        let name = branch_or_current(&refs, args.first())?;
+        unset_upstream(&name)
+    } else if options.delete || options.force_delete {
+        anyhow::ensure!(!args.is_empty(), "branch name required");
+        for name in &args {
+            delete_branch(&refs, name, options.force_delete || options.force)?;
+        }
+        Ok(())
+    } else if options.rename || options.force_rename {
+        let force = options.force_rename || options.force;
+        match args.as_slice() {
+            [new] => rename_branch(&refs, &branch_or_current(&refs, None)?, new, force),
+            [old, new] => rename_branch(&refs, old, new, force),
+            _ => anyhow::bail!("usage: ugit branch -m [<old-branch>] <new-branch>"),
+        }
+    } else {
+        match args.as_slice() {
+            [] => list_branches(&refs, options.verbose),
+            [name] => create_branch(&refs, name, "HEAD", options.force),
+            [name, start] => create_branch(&refs, name, start, options.force),
+            _ => anyhow::bail!("too many arguments"),
+        }
+    }
+}
+
+/// The branch HEAD points at, or `None` when detached.
+pub fn current_branch(refs: &RefStore) -> Result<Option<String>> {
+    Ok(match refs.read_raw("HEAD")? {
+        Some(RefTarget::Symbolic(target)) => target.strip_prefix("refs/heads/").map(str::to_string),
+        _ => None,
+    })
+}
+
+fn branch_or_current(refs: &RefStore, name: Option<&String>) -> Result<String> {
+    match name {
+        Some(name) => Ok(name.clone()),
+        None => current_branch(refs)?.context("HEAD is detached; name a branch"),
+    }
+}
+
+fn check_branch_name(name: &str) -> Result<String> {
+    anyhow::ensure!(
+        name != "HEAD" && !name.starts_with('-'),
+        "'{}' is not a valid branch name",
+        name
+    );
+    let full = format!("refs/heads/{}", name);
+    check_ref_format(&full).with_context(|| format!("'{}' is not a valid branch name", name))?;
+    Ok(full)
+}
+
+/// The full name of the upstream configured for `name`, e.g.
+/// `refs/remotes/origin/main`, or `refs/heads/main` for a local upstream.
+pub fn upstream_of(config: &Config, name: &str) -> Option<String> {
+    let section = format!("branch.{}", name);
+    let remote = config.get(&section, "remote")?;
+    let merge = config.get(&section, "merge")?;
+    Some(match remote {
+        "." => merge.to_string(),
+        remote => format!(
+            "refs/remotes/{}/{}",
+            remote,
+            merge.strip_prefix("refs/heads/").unwrap_or(merge)
+        ),
+    })
+}
+
+fn list_branches(refs: &RefStore, verbose: u8) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let config = Config::load()?;
+    let current = current_branch(refs)?;
+
+    let mut branches: Vec<(String, ObjectId, bool)> = refs
+        .list("refs/heads/")?
+        .into_iter()
+        .map(|(name, oid)| {
+            let name = shorten_ref_name(&name).to_string();
+            let is_current = current.as_deref() == Some(name.as_str());
+            (name, oid, is_current)
+        })
+        .collect();
+    if current.is_none() {
+        if let Some(head) = refs.resolve("HEAD")? {
+            branches.insert(
+                0,
+                (format!("(HEAD detached at {})", head.short()), head, true),
+            );
+        }
+    }
+
+    let width = branches
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, oid, is_current) in branches {
+        let marker = if is_current { '*' } else { ' ' };
+        if verbose == 0 {
+            println!("{} {}", marker, name);
+            continue;
+        }
+
+        let tracking = match upstream_of(&config, &name) {
+            Some(upstream) => tracking_info(refs, &odb, oid, &upstream, verbose > 1)?,
+            None => String::new(),
+        };
+        let commit = odb.read_commit(&oid)?;
+        let subject = commit.message.lines().next().unwrap_or_default();
+        println!(
+            "{} {:<width$} {} {}{}",
+            marker,
+            name,
+            oid.short(),
+            tracking,
+            subject,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// `[origin/main: ahead 1, behind 2] ` as shown by `branch -v`; the
+/// upstream's name is only included with `-vv`.
+fn tracking_info(
+    refs: &RefStore,
+    odb: &ObjectDatabase,
+    oid: ObjectId,
+    upstream: &str,
+    show_name: bool,
+) -> Result<String> {
+    let short = shorten_ref_name(upstream);
+    let Some(upstream_oid) = refs.resolve(upstream)? else {
+        return Ok(match show_name {
+            true => format!("[{}: gone] ", short),
+            false => String::new(),
+        });
+    };
+
+    let (ahead, behind) = ahead_behind(odb, oid, upstream_oid)?;
+    let mut counts = Vec::new();
+    if ahead > 0 {
+        counts.push(format!("ahead {}", ahead));
+    }
+    if behind > 0 {
+        counts.push(format!("behind {}", behind));
+    }
+    Ok(match (show_name, counts.is_empty()) {
+        (true, true) => format!("[{}] ", short),
+        (true, false) => format!("[{}: {}] ", short, counts.join(", ")),
+        (false, true) => String::new(),
+        (false, false) => format!("[{}] ", counts.join(", ")),
+    })
+}
+
+pub fn create_branch(refs: &RefStore, name: &str, start: &str, force: bool) -> Result<()> {
+    let full = check_branch_name(name)?;
+    let odb = ObjectDatabase::default();
+    let oid = resolve_revision(&odb, start)
+        .with_context(|| format!("not a valid object name: '{}'", start))?;
+    let oid = peel(&odb, oid, Some(ObjectKind::Commit))
+        .with_context(|| format!("'{}' is not a commit", start))?;
+
+    let exists = refs.resolve(&full)?.is_some();
+    anyhow::ensure!(!exists || force, "a branch named '{}' already exists", name);
+    anyhow::ensure!(
+        !exists || current_branch(refs)?.as_deref() != Some(name),
+        "cannot force update the current branch"
+    );
+
+    let expected = if exists { None } else { Some(ObjectId::NULL) };
+    let message = match exists {
+        true => format!("branch: Reset to {}", start),
+        false => format!("branch: Created from {}", start),
+    };
+    refs.update(&full, oid, expected, false, &message)
+}
+
+fn delete_branch(refs: &RefStore, name: &str, force: bool) -> Result<()> {
+    let full = format!("refs/heads/{}", name);
+    anyhow::ensure!(
+        current_branch(refs)?.as_deref() != Some(name),
+        "cannot delete branch '{}' checked out",
+        name
+    );
+    let oid = refs
+        .resolve(&full)?
+        .with_context(|| format!("branch '{}' not found", name))?;
+
+    let mut config = Config::load()?;
+    if !force {
+        // like git, merged means merged into the upstream if there is one
+        let upstream = upstream_of(&config, name).and_then(|up| refs.resolve(&up).transpose());
+        let base = match upstream {
+            Some(upstream) => Some(upstream?),
+            None => refs.resolve("HEAD")?,
+        };
+        let odb = ObjectDatabase::default();
+        let merged = match base {
+            Some(base) => is_ancestor(&odb, oid, base)?,
+            None => false,
+        };
+        anyhow::ensure!(
+            merged,
+            "the branch '{}' is not fully merged.\n\
+             If you are sure you want to delete it, run 'ugit branch -D {}'.",
+            name,
+            name
+        );
+    }
+
+    refs.delete(&full, Some(oid), false)?;
+    config.remove_section(&format!("branch.{}", name));
+    config.save()?;
+    println!("Deleted branch {} (was {}).", name, oid.short());
+    Ok(())
+}
+
+fn rename_branch(refs: &RefStore, old: &str, new: &str, force: bool) -> Result<()> {
+    let old_full = format!("refs/heads/{}", old);
+    let new_full = check_branch_name(new)?;
+    let current = current_branch(refs)?;
+
+    if refs.resolve(&old_full)?.is_none() {
+        // an unborn current branch only needs HEAD to point elsewhere
+        anyhow::ensure!(current.as_deref() == Some(old), "no branch named '{}'", old);
+        return refs.set_symbolic("HEAD", &new_full, "");
+    }
+
+    if old != new && refs.resolve(&new_full)?.is_some() {
+        anyhow::ensure!(force, "a branch named '{}' already exists", new);
+        anyhow::ensure!(
+            current.as_deref() != Some(new),
+            "cannot force update the current branch"
+        );
+        refs.delete(&new_full, None, false)?;
+    }
+    if old == new {
+        return Ok(());
+    }
+
+    let message = format!("Branch: renamed {} to {}", old_full, new_full);
+    refs.rename(&old_full, &new_full, &message)?;
+
+    let mut config = Config::load()?;
+    config.remove_section(&format!("branch.{}", new));
+    config.rename_section(&format!("branch.{}", old), &format!("branch.{}", new));
+    config.save()
+}
+
+fn set_upstream(refs: &RefStore, name: &str, upstream: &str) -> Result<()> {
+    anyhow::ensure!(
+        refs.resolve(&format!("refs/heads/{}", name))?.is_some(),
+        "branch '{}' does not exist",
+        name
+    );
+    let full = refs.dwim(upstream)?.with_context(|| {
+        format!(
+            "the requested upstream branch '{}' does not exist",
+            upstream
+        )
+    })?;
+
+    let (remote, merge) = if full.starts_with("refs/heads/") {
+        (".".to_string(), full.clone())
+    } else if let Some((remote, branch)) = full
+        .strip_prefix("refs/remotes/")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        (remote.to_string(), format!("refs/heads/{}", branch))
+    } else {
+        anyhow::bail!(
+            "'{}' is not a branch; can't use it as an upstream",
+            upstream
+        );
+    };
+
+    let mut config = Config::load()?;
+    let section = format!("branch.{}", name);
+    config.set(&section, "remote", &remote);
+    config.set(&section, "merge", &merge);
+    config.save()?;
+    println!(
+        "branch '{}' set up to track '{}'.",
+        name,
+        shorten_ref_name(&full)
+    );
+    Ok(())
+}
+
+fn unset_upstream(name: &str) -> Result<()> {
+    let mut config = Config::load()?;
+    let section = format!("branch.{}", name);
+    anyhow::ensure!(
+        config.get(&section, "merge").is_some(),
+        "branch '{}' has no upstream information",
+        name
+    );
+    config.unset(&section, "remote");
+    config.unset(&section, "merge");
+    config.save()
+}
@@ -1,83 +1,45 @@
 use anyhow::Context;
-use flate2::read::ZlibDecoder;
-use std::{
-    ffi::CStr,
-    fs,
-    io::{BufRead, BufReader, Read, Write},
-};
+use std::io::Write;
 
-use crate::get_full_path_from_hash;
+use crate::repository::{resolve_revision, ObjectDatabase, ObjectKind};
+use crate::Tree;
 
-pub fn cat_file(pretty_print: bool, object_hash: String) -> anyhow::Result<()> {
-    let path = get_full_path_from_hash(&object_hash)?;
-    let mut parts = path.split('/');
+pub fn cat_file(
+    pretty_print: bool,
+    show_type: bool,
+    show_size: bool,
+    object_hash: String,
+) -> anyhow::Result<()> {
+    let odb = ObjectDatabase::default();
+    let oid = resolve_revision(&odb, &object_hash)?;
 
-    let hash = parts.next().expect("hash is missing");
-    let file = parts.next().expect("file is missing");
-
-    let path = format!("./ugit/objects/{}/{}", hash, file);
-
-    let f = fs::File::open(path).context("couldn't open ugit/objects file")?;
-    let z = ZlibDecoder::new(f);
-    let mut z = BufReader::new(z);
-    let mut buf = Vec::new();
-    z.read_until(0, &mut buf)
-        .context("read header from ugit/objects")?;
-    let header = CStr::from_bytes_with_nul(&buf).expect("there is one null at the end.");
-    let header = header
-        .to_str()
-        .context("ugit/objects file header isn't valid UTF-8")?;
-    // support for both blob and tree objects
-    if header.starts_with("blob") {
-        let mut content = Vec::new();
-        z.read_to_end(&mut content)
-            .context("read content from ugit/objects")?;
-        if pretty_print {
-            let content = String::from_utf8(content).context("content isn't valid UTF-8")?;
-            println!("{}", content);
+    if show_type || show_size {
+        let (kind, size) = odb.read_header(&oid)?;
+        if show_type {
+            println!("{}", kind);
         } else {
-            std::io::stdout()
-                .write_all(&content)
-                .context("write content to stdout")?;
+            println!("{}", size);
         }
-    } else if header.starts_with("tree") {
-        let Some(size) = header.strip_prefix("tree ") else {
-            anyhow::bail!("ugit/object header didn't start with 'tree ': '{header}'")
-        };
-        let size: usize = size.parse().context("couldn't parse size")?;
-        // println!("size: {}", size);
-        let mut content = Vec::with_capacity(size);
-        z.read_to_end(&mut content)
-            .context("read content from ugit/objects")?;
-
-        if pretty_print {
-            let content = String::from_utf8(content).context("content isn't valid UTF-8")?;
-            println!("{}", content);
-        } else {
-            std::io::stdout()
-                .write_all(&content)
-                .context("write content to stdout")?;
-        }
-    } else if header.starts_with("commit") {
-        let Some(size) = header.strip_prefix("commit ") else {
-            anyhow::bail!("ugit/object header didn't start with 'commit ': '{header}'")
-        };
-        let size: usize = size.parse().context("couldn't parse size")?;
-        // println!("size: {}", size);
-        let mut content = Vec::with_capacity(size);
-        z.read_to_end(&mut content)
-            .context("read content from ugit/objects")?;
+        return Ok(());
+    }
 
-        if pretty_print {
-            let content = String::from_utf8(content).context("content isn't valid UTF-8")?;
-            println!("{}", content);
-        } else {
-            std::io::stdout()
-                .write_all(&content)
-                .context("write content to stdout")?;
+    let (kind, content) = odb.read_raw(&oid)?;
+    // trees are binary, so pretty printing lists their entries instead
+    if pretty_print && kind == ObjectKind::Tree {
+        let tree = Tree::parse(&content)?;
+        for entry in &tree.entries {
+            println!(
+                "{:06o} {} {}\t{}",
+                entry.mode,
+                entry.kind(),
+                entry.hash,
+                entry.name
+            );
         }
     } else {
-        panic!("unknown object type: {}", header);
+        std::io::stdout()
+            .write_all(&content)
+            .context("write content to stdout")?;
     }
     Ok(())
 }
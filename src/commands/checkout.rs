@@ -0,0 +1,367 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+use crate::repository::{
+    check_file, check_path, checkout_file, flatten_tree, peel, previous_branch, read_worktree_file,
+    remove_file, resolve_revision, FileState, ObjectDatabase, ObjectId, ObjectKind, RefStore,
+};
+use crate::Index;
+
+use super::branch::{create_branch, current_branch};
+
+/// Where HEAD should end up after a switch.
+pub enum Target {
+    /// A branch name, e.g. `main` for `refs/heads/main`.
+    Branch(String),
+    /// A branch that was just created for the switch.
+    NewBranch(String),
+    /// A commit, checked out with a detached HEAD.
+    Detached(ObjectId),
+}
+
+pub fn checkout(
+    target: Option<String>,
+    new_branch: Option<String>,
+    detach: bool,
+    force: bool,
+    paths: Vec<String>,
+) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    if !paths.is_empty() {
+        return checkout_paths(&odb, target.as_deref(), &paths);
+    }
+    if let Some(name) = new_branch {
+        return create_and_switch(&name, target.as_deref().unwrap_or("HEAD"), force);
+    }
+    let Some(target) = target else {
+        return Ok(()); // like git, nothing to do
+    };
+
+    let name = expand_previous(&target)?;
+    let refs = RefStore::default();
+    if !detach && refs.resolve(&format!("refs/heads/{}", name))?.is_some() {
+        return switch_to(Target::Branch(name.clone()), &name, force);
+    }
+    match resolve_revision(&odb, &name) {
+        Ok(oid) => {
+            let commit = peel(&odb, oid, Some(ObjectKind::Commit))
+                .with_context(|| format!("reference is not a tree: {}", name))?;
+            switch_to(Target::Detached(commit), &name, force)
+        }
+        // `checkout <file>` restores a file from the index, as git allows
+        Err(_)
+            if Index::default()
+                .read()?
+                .entries
+                .iter()
+                .any(|e| e.path == name) =>
+        {
+            checkout_paths(&odb, None, &[name])
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// `-` and `@{-N}` name the N-th previously checked out branch.
+pub fn expand_previous(target: &str) -> Result<String> {
+    let n = match target {
+        "-" => "1",
+        target => match target.strip_prefix("@{-").and_then(|t| t.strip_suffix('}')) {
+            Some(n) => n,
+            None => return Ok(target.to_string()),
+        },
+    };
+    let n = n
+        .parse()
+        .with_context(|| format!("invalid previous branch: '{}'", target))?;
+    previous_branch(n)
+}
+
+/// Creates the branch `name` at `start` and switches to it.
+pub fn create_and_switch(name: &str, start: &str, force: bool) -> Result<()> {
+    let refs = RefStore::default();
+    if start == "HEAD" && refs.resolve("HEAD")?.is_none() {
+        // nothing to branch from yet; the new branch is born with its first commit
+        refs.set_symbolic("HEAD", &format!("refs/heads/{}", name), "")?;
+        println!("Switched to a new branch '{}'", name);
+        return Ok(());
+    }
+
+    create_branch(&refs, name, start, false)?;
+    let full = format!("refs/heads/{}", name);
+    if let Err(err) = switch_to(Target::NewBranch(name.to_string()), name, force) {
+        let _ = refs.delete(&full, None, false);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Updates the work tree and index from HEAD's tree to the target's tree,
+/// then moves HEAD, logging the move in HEAD's reflog.
+pub fn switch_to(target: Target, label: &str, force: bool) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let refs = RefStore::default();
+
+    let current_branch = current_branch(&refs)?;
+    let head = refs.resolve("HEAD")?;
+    let target_oid = match &target {
+        Target::Branch(name) | Target::NewBranch(name) => refs
+            .resolve(&format!("refs/heads/{}", name))?
+            .with_context(|| format!("invalid reference: {}", name))?,
+        Target::Detached(oid) => *oid,
+    };
+
+    let current_files = match head {
+        Some(head) => flatten_tree(&odb, &odb.read_commit(&head)?.tree)?,
+        None => BTreeMap::new(),
+    };
+    let target_commit = odb.read_commit(&target_oid)?;
+    let target_files = flatten_tree(&odb, &target_commit.tree)?;
+
+    let mut index = Index::default().read()?;
+    update_worktree(
+        &odb,
+        &mut index,
+        &current_files,
+        &target_files,
+        force,
+        "checkout",
+    )?;
+    index
+        .write()
+        .context("Failed to write updated index file")?;
+
+    // the reflog names where we came from, which `@{-N}` reads back
+    let from = match (&current_branch, head) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(head)) => head.to_string(),
+        (None, None) => "HEAD".to_string(),
+    };
+    let to = match &target {
+        Target::Branch(name) | Target::NewBranch(name) => name.as_str(),
+        Target::Detached(_) => label,
+    };
+    let message = format!("checkout: moving from {} to {}", from, to);
+
+    match target {
+        Target::Branch(name) => {
+            refs.set_symbolic("HEAD", &format!("refs/heads/{}", name), &message)?;
+            if current_branch.as_deref() == Some(name.as_str()) {
+                println!("Already on '{}'", name);
+            } else {
+                println!("Switched to branch '{}'", name);
+            }
+        }
+        Target::NewBranch(name) => {
+            refs.set_symbolic("HEAD", &format!("refs/heads/{}", name), &message)?;
+            println!("Switched to a new branch '{}'", name);
+        }
+        Target::Detached(oid) => {
+            refs.update("HEAD", oid, None, false, &message)?;
+            let subject = target_commit.message.lines().next().unwrap_or_default();
+            println!("HEAD is now at {} {}", oid.short(), subject);
+        }
+    }
+    Ok(())
+}
+
+/// Brings the work tree and index from `current` to `target`, touching only
+/// paths that differ between the two. Paths with staged or unstaged changes
+/// (or untracked files in the way) abort the switch unless `force` is set,
+/// in which case every difference from `target` is discarded. `operation`
+/// names the command in those errors.
+pub fn update_worktree(
+    odb: &ObjectDatabase,
+    index: &mut Index,
+    current: &BTreeMap<String, (u32, ObjectId)>,
+    target: &BTreeMap<String, (u32, ObjectId)>,
+    force: bool,
+    operation: &str,
+) -> Result<()> {
+    let mut updates: Vec<(String, Option<(u32, ObjectId)>)> = Vec::new();
+    let mut changed = Vec::new();
+    let mut untracked = Vec::new();
+    {
+        let staged: HashMap<&str, _> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let mut paths: BTreeSet<&str> = current
+            .keys()
+            .chain(target.keys())
+            .map(String::as_str)
+            .collect();
+        if force {
+            paths.extend(staged.keys());
+        }
+
+        for path in paths {
+            let ours = current.get(path);
+            let theirs = target.get(path);
+            let entry = staged.get(path);
+            let staged_value = entry.map(|entry| (entry.mode, entry.sha1));
+
+            if force {
+                let clean = match entry {
+                    Some(entry) => check_file(entry)? == FileState::Unchanged,
+                    None => true,
+                };
+                if staged_value.as_ref() != theirs || !clean {
+                    updates.push((path.to_string(), theirs.copied()));
+                }
+                continue;
+            }
+            // nothing changes, or the index already has what the target has
+            if ours == theirs || staged_value.as_ref() == theirs {
+                continue;
+            }
+            if staged_value.as_ref() != ours {
+                changed.push(path.to_string());
+                continue;
+            }
+            match entry {
+                Some(entry) if check_file(entry)? == FileState::Modified => {
+                    changed.push(path.to_string())
+                }
+                None if untracked_in_the_way(path, theirs)? => untracked.push(path.to_string()),
+                _ => updates.push((path.to_string(), theirs.copied())),
+            }
+        }
+    }
+
+    let before = match operation {
+        "checkout" => "switch branches",
+        operation => operation,
+    };
+    let mut errors = Vec::new();
+    if !changed.is_empty() {
+        errors.push(format!(
+            "Your local changes to the following files would be overwritten by {}:\n\t{}\n\
+             Please commit your changes or stash them before you {}.",
+            operation,
+            changed.join("\n\t"),
+            before
+        ));
+    }
+    if !untracked.is_empty() {
+        errors.push(format!(
+            "The following untracked working tree files would be overwritten by {}:\n\t{}\n\
+             Please move or remove them before you {}.",
+            operation,
+            untracked.join("\n\t"),
+            before
+        ));
+    }
+    anyhow::ensure!(errors.is_empty(), "{}\nAborting", errors.join("\n"));
+    // nothing is touched if any path would escape the work tree
+    for (path, _) in &updates {
+        check_path(path)?;
+    }
+
+    // removals first, so a file can take the place of a directory and vice versa
+    for (path, _) in updates.iter().filter(|(_, file)| file.is_none()) {
+        remove_file(path)?;
+        index.remove_entry(path);
+    }
+    let mut written = Vec::new();
+    for (path, file) in &updates {
+        if let Some((mode, oid)) = file {
+            written.push(checkout_file(odb, path, *mode, oid)?);
+        }
+    }
+    index.add_entries(written);
+    Ok(())
+}
+
+/// Makes the index and work tree match `commit`, throwing away changes to
+/// tracked files (such as the conflicts of a pick that failed).
+pub fn reset_hard(odb: &ObjectDatabase, commit: ObjectId) -> Result<()> {
+    let current = match RefStore::default().resolve("HEAD")? {
+        Some(head) => flatten_tree(odb, &odb.read_commit(&head)?.tree)?,
+        None => BTreeMap::new(),
+    };
+    let target = flatten_tree(odb, &odb.read_commit(&commit)?.tree)?;
+    let mut index = Index::default().read()?;
+    update_worktree(odb, &mut index, &current, &target, true, "reset")?;
+    index.write().context("Failed to write updated index file")
+}
+
+/// Whether an untracked file sits where the target would write `path`.
+/// A file that already has the target's content is not in the way.
+fn untracked_in_the_way(path: &str, target: Option<&(u32, ObjectId)>) -> Result<bool> {
+    let Some((_, oid)) = target else {
+        return Ok(false);
+    };
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => {
+            let content = read_worktree_file(path, &metadata)?;
+            Ok(ObjectDatabase::hash(ObjectKind::Blob, &content) != *oid)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// `checkout [<rev>] -- <paths>`: overwrites the given files (or everything
+/// under the given directories) with their version in `rev`, or in the
+/// index when no revision is given. HEAD doesn't move.
+fn checkout_paths(odb: &ObjectDatabase, rev: Option<&str>, paths: &[String]) -> Result<()> {
+    let mut index = Index::default().read()?;
+    let (source, source_name) = match rev {
+        Some(rev) => {
+            let oid = resolve_revision(odb, rev)?;
+            let tree = peel(odb, oid, Some(ObjectKind::Tree))
+                .with_context(|| format!("reference is not a tree: {}", rev))?;
+            (flatten_tree(odb, &tree)?, oid.short())
+        }
+        None => (
+            index
+                .entries
+                .iter()
+                .map(|entry| (entry.path.clone(), (entry.mode, entry.sha1)))
+                .collect(),
+            "the index".to_string(),
+        ),
+    };
+
+    let mut selected = BTreeMap::new();
+    for pathspec in paths {
+        let spec = pathspec.trim_start_matches("./").trim_end_matches('/');
+        let mut matched = false;
+        for (path, file) in &source {
+            let is_match = spec.is_empty()
+                || spec == "."
+                || path == spec
+                || path
+                    .strip_prefix(spec)
+                    .is_some_and(|rest| rest.starts_with('/'));
+            if is_match {
+                selected.insert(path.clone(), *file);
+                matched = true;
+            }
+        }
+        anyhow::ensure!(
+            matched,
+            "pathspec '{}' did not match any file(s) known to ugit",
+            pathspec
+        );
+    }
+
+    for path in selected.keys() {
+        check_path(path)?;
+    }
+    let mut written = Vec::new();
+    for (path, (mode, oid)) in &selected {
+        written.push(checkout_file(odb, path, *mode, oid)?);
+    }
+    index.add_entries(written);
+    index
+        .write()
+        .context("Failed to write updated index file")?;
+
+    let count = selected.len();
+    let plural = if count == 1 { "" } else { "s" };
+    println!("Updated {} path{} from {}", count, plural, source_name);
+    Ok(())
+}
@@ -0,0 +1,524 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::repository::{
+    diff_files, flatten_tree, merge_trees, peel, resolve_revision, Commit, Config, MergeLabels,
+    ObjectDatabase, ObjectId, ObjectKind, RefStore, RevWalk, Signature, Sort, Tree, TreeMerge,
+};
+use crate::Index;
+
+use super::branch::current_branch;
+use super::checkout::reset_hard;
+use super::commit::{cleanup_message, create_commit};
+use super::merge::{checkout_merge, conflict_style, MERGE_MSG};
+use super::writetree::write_tree_from_index;
+
+const SEQUENCER_DIR: &str = "./ugit/sequencer";
+
+pub struct PickOptions {
+    /// The parent (counting from 1) whose side of a merge is kept.
+    pub mainline: Option<usize>,
+    /// Apply the changes to the index and work tree without committing.
+    pub no_commit: bool,
+    /// Note the picked commit in the message, as `(cherry picked from ...)`.
+    pub record_origin: bool,
+    /// Carry on once conflicts are resolved.
+    pub continue_pick: bool,
+    /// Give up, going back to where the sequence started.
+    pub abort: bool,
+}
+
+/// Whether a commit's changes are applied or undone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    CherryPick,
+    Revert,
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::CherryPick => "cherry-pick",
+            Operation::Revert => "revert",
+        }
+    }
+
+    /// The word for it in `sequencer/todo`.
+    fn todo_word(&self) -> &'static str {
+        match self {
+            Operation::CherryPick => "pick",
+            Operation::Revert => "revert",
+        }
+    }
+
+    /// The file naming the commit whose conflicts are being resolved.
+    fn head_file(&self) -> &'static str {
+        match self {
+            Operation::CherryPick => "./ugit/CHERRY_PICK_HEAD",
+            Operation::Revert => "./ugit/REVERT_HEAD",
+        }
+    }
+}
+
+/// Three-way merges the changes `commit` made relative to `parent` (none
+/// for a root commit) into the tree `ours`; with `revert`, the undoing of
+/// those changes instead.
+pub fn merge_changes(
+    odb: &ObjectDatabase,
+    ours: ObjectId,
+    oid: ObjectId,
+    commit: &Commit,
+    parent: Option<ObjectId>,
+    revert: bool,
+) -> Result<TreeMerge> {
+    let parent_tree = match parent {
+        Some(parent) => odb.read_commit(&parent)?.tree,
+        None => odb.write(ObjectKind::Tree, &Tree::default().serialize())?,
+    };
+    let name = format!("{} ({})", oid.short(), commit.subject());
+    let parent_name = format!("parent of {}", name);
+    let (base, theirs, labels) = match revert {
+        false => (parent_tree, commit.tree, [parent_name, name]),
+        true => (commit.tree, parent_tree, [name, parent_name]),
+    };
+    let [base_label, theirs_label] = labels;
+    let labels = MergeLabels {
+        base: base_label,
+        ours: "HEAD".to_string(),
+        theirs: theirs_label,
+    };
+    merge_trees(odb, base, ours, theirs, &labels, conflict_style(None)?)
+}
+
+/// The steps left of a cherry-pick or revert that stopped, kept in
+/// `ugit/sequencer`.
+struct Sequence {
+    /// Where HEAD was when it started, for `--abort`.
+    head: ObjectId,
+    todo: Vec<(Operation, ObjectId)>,
+    mainline: Option<usize>,
+    no_commit: bool,
+    record_origin: bool,
+}
+
+impl Sequence {
+    fn load() -> Result<Option<Sequence>> {
+        let head = match fs::read_to_string(format!("{}/head", SEQUENCER_DIR)) {
+            Ok(head) => ObjectId::from_hex(head.trim())?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("couldn't read sequencer/head"),
+        };
+        let todo = fs::read_to_string(format!("{}/todo", SEQUENCER_DIR))
+            .context("couldn't read sequencer/todo")?;
+        let todo = todo
+            .lines()
+            .map(|line| {
+                let mut words = line.split_whitespace();
+                let operation = match words.next() {
+                    Some("pick") => Operation::CherryPick,
+                    Some("revert") => Operation::Revert,
+                    _ => anyhow::bail!("corrupt sequencer/todo line: '{}'", line),
+                };
+                let oid = ObjectId::from_hex(words.next().unwrap_or_default())?;
+                Ok((operation, oid))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let opts = fs::read_to_string(format!("{}/opts", SEQUENCER_DIR)).unwrap_or_default();
+        let opts = Config::parse(&opts);
+        Ok(Some(Sequence {
+            head,
+            todo,
+            mainline: match opts.get("options", "mainline") {
+                Some(mainline) => Some(mainline.parse().context("corrupt sequencer/opts")?),
+                None => None,
+            },
+            no_commit: opts.get("options", "no-commit") == Some("true"),
+            record_origin: opts.get("options", "record-origin") == Some("true"),
+        }))
+    }
+
+    fn save(&self, odb: &ObjectDatabase) -> Result<()> {
+        fs::create_dir_all(SEQUENCER_DIR).context("couldn't create ugit/sequencer")?;
+        let mut todo = String::new();
+        for (operation, oid) in &self.todo {
+            let commit = odb.read_commit(oid)?;
+            todo.push_str(&format!(
+                "{} {} {}\n",
+                operation.todo_word(),
+                oid,
+                commit.subject()
+            ));
+        }
+        let mut opts = String::from("[options]\n");
+        if let Some(mainline) = self.mainline {
+            opts.push_str(&format!("\tmainline = {}\n", mainline));
+        }
+        if self.no_commit {
+            opts.push_str("\tno-commit = true\n");
+        }
+        if self.record_origin {
+            opts.push_str("\trecord-origin = true\n");
+        }
+        fs::write(
+            format!("{}/head", SEQUENCER_DIR),
+            format!("{}\n", self.head),
+        )
+        .context("couldn't write sequencer/head")?;
+        fs::write(format!("{}/todo", SEQUENCER_DIR), todo)
+            .context("couldn't write sequencer/todo")?;
+        fs::write(format!("{}/opts", SEQUENCER_DIR), opts).context("couldn't write sequencer/opts")
+    }
+}
+
+/// Applies the changes each commit introduced, committing each one with
+/// its original author and message.
+pub fn cherry_pick(options: PickOptions, commits: Vec<String>) -> Result<()> {
+    sequence(Operation::CherryPick, options, commits)
+}
+
+/// Undoes the changes each commit introduced, committing each undoing.
+pub fn revert(options: PickOptions, commits: Vec<String>) -> Result<()> {
+    sequence(Operation::Revert, options, commits)
+}
+
+fn sequence(operation: Operation, options: PickOptions, commits: Vec<String>) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    if options.abort {
+        return abort(&odb);
+    }
+    if options.continue_pick {
+        let sequence = resume(&odb)?;
+        return run(&odb, sequence);
+    }
+    anyhow::ensure!(
+        Sequence::load()?.is_none(),
+        "a cherry-pick or revert is already in progress\n\
+         hint: try \"ugit {} (--continue | --abort)\"",
+        operation.name()
+    );
+
+    let head = RefStore::default()
+        .resolve("HEAD")?
+        .context("can't cherry-pick or revert before the first commit")?;
+    let todo = resolve_commits(&odb, &commits)?
+        .into_iter()
+        .map(|oid| (operation, oid))
+        .collect();
+    run(
+        &odb,
+        Sequence {
+            head,
+            todo,
+            mainline: options.mainline,
+            no_commit: options.no_commit,
+            record_origin: options.record_origin,
+        },
+    )
+}
+
+/// The commits to pick, in order. Ranges such as `A..B` stand for the
+/// commits they contain, oldest first.
+fn resolve_commits(odb: &ObjectDatabase, revs: &[String]) -> Result<Vec<ObjectId>> {
+    if revs
+        .iter()
+        .any(|rev| rev.contains("..") || rev.starts_with('^'))
+    {
+        let mut walk = RevWalk::new(odb);
+        walk.sort = Sort::Topo;
+        for rev in revs {
+            walk.push_revision(rev)?;
+        }
+        let commits: Vec<ObjectId> = walk.run()?.into_iter().rev().map(|c| c.oid).collect();
+        anyhow::ensure!(!commits.is_empty(), "empty commit set passed");
+        return Ok(commits);
+    }
+    revs.iter()
+        .map(|rev| {
+            let oid = resolve_revision(odb, rev)?;
+            peel(odb, oid, Some(ObjectKind::Commit))
+                .with_context(|| format!("bad revision '{}'", rev))
+        })
+        .collect()
+}
+
+/// Picks the commits one after another. On conflicts, the rest is saved in
+/// `ugit/sequencer` for `--continue` to pick up.
+fn run(odb: &ObjectDatabase, mut sequence: Sequence) -> Result<()> {
+    while !sequence.todo.is_empty() {
+        let (operation, oid) = sequence.todo.remove(0);
+        if let Some(stopped) = pick(odb, &sequence, operation, oid)? {
+            sequence.save(odb)?;
+            anyhow::bail!(stopped);
+        }
+    }
+    match fs::remove_dir_all(SEQUENCER_DIR) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).context("couldn't remove ugit/sequencer")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Applies (or undoes) one commit on top of HEAD and commits the result.
+/// When that fails with conflicts, they are left to resolve, with the
+/// commit's message waiting in `MERGE_MSG`, and the reason to stop is
+/// returned.
+fn pick(
+    odb: &ObjectDatabase,
+    sequence: &Sequence,
+    operation: Operation,
+    oid: ObjectId,
+) -> Result<Option<String>> {
+    let refs = RefStore::default();
+    let commit = odb.read_commit(&oid)?;
+    let parent = match (commit.parent_commits.len(), sequence.mainline) {
+        (n, None) if n > 1 => {
+            anyhow::bail!("commit {} is a merge but no -m option was given.", oid)
+        }
+        // like git, `-m 1` is fine for a commit with a single parent
+        (_, Some(mainline)) => Some(
+            *mainline
+                .checked_sub(1)
+                .and_then(|i| commit.parent_commits.get(i))
+                .with_context(|| format!("commit {} does not have parent {}", oid, mainline))?,
+        ),
+        (_, None) => commit.parent_commits.first().copied(),
+    };
+
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let head_tree = odb.read_commit(&head)?.tree;
+    let mut index = Index::default().read()?;
+    // without committing, the changes pile up in the index
+    let ours = match sequence.no_commit {
+        true => write_tree_from_index(&index)?,
+        false => head_tree,
+    };
+    let current = flatten_tree(odb, &ours)?;
+    if !sequence.no_commit {
+        let staged = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.clone(), (entry.mode, entry.sha1)))
+            .collect();
+        anyhow::ensure!(
+            diff_files(&current, &staged).is_empty(),
+            "your local changes would be overwritten by {}.\n\
+             hint: commit your changes or stash them to proceed.",
+            operation.name()
+        );
+    }
+
+    let merged = merge_changes(
+        odb,
+        ours,
+        oid,
+        &commit,
+        parent,
+        operation == Operation::Revert,
+    )?;
+    checkout_merge(odb, &mut index, &current, &merged, operation.name())?;
+    for message in &merged.messages {
+        println!("{}", message);
+    }
+
+    let message = match operation {
+        Operation::CherryPick if sequence.record_origin => with_origin(&commit.message, oid),
+        Operation::CherryPick => commit.message.clone(),
+        Operation::Revert => revert_message(&commit, oid, parent),
+    };
+    if !merged.conflicts.is_empty() {
+        let mut message = message;
+        message.push_str("\n# Conflicts:\n");
+        for conflict in &merged.conflicts {
+            message.push_str(&format!("#\t{}\n", conflict.path));
+        }
+        stop(operation, oid, &message, sequence.no_commit)?;
+        return Ok(Some(format!(
+            "could not {} {}... {}\n\
+             hint: After resolving the conflicts, mark them with\n\
+             hint: \"ugit add/rm <pathspec>\", then run\n\
+             hint: \"ugit {} --continue\".\n\
+             hint: To abort and get back to the state before \"ugit {}\",\n\
+             hint: run \"ugit {} --abort\".",
+            match operation {
+                Operation::CherryPick => "apply",
+                Operation::Revert => "revert",
+            },
+            oid.short(),
+            commit.subject(),
+            operation.name(),
+            operation.name(),
+            operation.name()
+        )));
+    }
+    if sequence.no_commit {
+        return Ok(None);
+    }
+
+    let tree = write_tree_from_index(&index)?;
+    if tree == head_tree {
+        stop(operation, oid, &message, false)?;
+        return Ok(Some(format!(
+            "The previous {} is now empty, possibly due to conflict resolution.\n\
+             Run \"ugit {} --continue\" to go on without it.",
+            operation.name(),
+            operation.name()
+        )));
+    }
+    let author = match operation {
+        Operation::CherryPick => Some(commit.author.clone()),
+        Operation::Revert => None,
+    };
+    commit_pick(&refs, head, tree, message, author, operation.name())?;
+    Ok(None)
+}
+
+/// Records the commit being resolved and its message for `--continue`.
+fn stop(operation: Operation, oid: ObjectId, message: &str, no_commit: bool) -> Result<()> {
+    if !no_commit {
+        fs::write(operation.head_file(), format!("{}\n", oid))
+            .with_context(|| format!("couldn't write {}", operation.head_file()))?;
+    }
+    fs::write(MERGE_MSG, message).context("couldn't write MERGE_MSG")
+}
+
+/// Commits `tree` on top of HEAD, printing `[<branch> <commit>] <subject>`.
+fn commit_pick(
+    refs: &RefStore,
+    head: ObjectId,
+    tree: ObjectId,
+    message: String,
+    author: Option<Signature>,
+    reflog_action: &str,
+) -> Result<()> {
+    let subject = message.lines().next().unwrap_or_default().to_string();
+    let new = create_commit(tree, vec![head], message, author)?;
+    refs.update(
+        "HEAD",
+        new,
+        Some(head),
+        true,
+        &format!("{}: {}", reflog_action, subject),
+    )?;
+    let branch = current_branch(refs)?;
+    println!(
+        "[{} {}] {}",
+        branch.as_deref().unwrap_or("detached HEAD"),
+        new.short(),
+        subject
+    );
+    Ok(())
+}
+
+/// `--continue`: commits the resolved conflicts of the commit that stopped
+/// the sequence, with the message prepared for it, and returns what's left.
+fn resume(odb: &ObjectDatabase) -> Result<Sequence> {
+    let sequence = Sequence::load()?.context("no cherry-pick or revert in progress")?;
+    let index = Index::default().read()?;
+    anyhow::ensure!(
+        index.conflicts().is_empty(),
+        "Committing is not possible because you have unmerged files.\n\
+         Fix them up in the work tree, and then use 'ugit add/rm <file>'\n\
+         as appropriate to mark resolution."
+    );
+
+    let refs = RefStore::default();
+    for operation in [Operation::CherryPick, Operation::Revert] {
+        let picked = match fs::read_to_string(operation.head_file()) {
+            Ok(picked) => ObjectId::from_hex(picked.trim())?,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).context("couldn't read the picked commit"),
+        };
+        let head = refs
+            .resolve("HEAD")?
+            .context("HEAD does not point at a commit")?;
+        let tree = write_tree_from_index(&index)?;
+        // nothing left to commit means the change was dropped
+        if tree != odb.read_commit(&head)?.tree {
+            let message = fs::read_to_string(MERGE_MSG).unwrap_or_default();
+            let message = cleanup_message(&message);
+            anyhow::ensure!(
+                !message.is_empty(),
+                "Aborting commit due to empty commit message."
+            );
+            let author = match operation {
+                Operation::CherryPick => Some(odb.read_commit(&picked)?.author),
+                Operation::Revert => None,
+            };
+            let reflog_action = format!("commit ({})", operation.name());
+            commit_pick(&refs, head, tree, message, author, &reflog_action)?;
+        }
+        forget_pick(operation)?;
+    }
+    Ok(sequence)
+}
+
+fn forget_pick(operation: Operation) -> Result<()> {
+    for file in [operation.head_file(), MERGE_MSG] {
+        match fs::remove_file(file) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("couldn't remove {}", file))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `--abort`: puts HEAD, the index and the work tree back to where the
+/// sequence started.
+fn abort(odb: &ObjectDatabase) -> Result<()> {
+    let sequence = Sequence::load()?.context("no cherry-pick or revert in progress")?;
+    reset_hard(odb, sequence.head)?;
+    RefStore::default().update(
+        "HEAD",
+        sequence.head,
+        None,
+        true,
+        &format!("reset: moving to {}", sequence.head),
+    )?;
+    forget_pick(Operation::CherryPick)?;
+    forget_pick(Operation::Revert)?;
+    fs::remove_dir_all(SEQUENCER_DIR).context("couldn't remove ugit/sequencer")
+}
+
+/// The message with a `(cherry picked from commit ...)` line added, in the
+/// trailer block if the message ends with one.
+fn with_origin(message: &str, oid: ObjectId) -> String {
+    let message = message.trim_end();
+    let is_trailer = |line: &str| {
+        line.starts_with("(cherry picked from commit ")
+            || line.split_once(": ").is_some_and(|(key, _)| {
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+    };
+    let trailers = match message.rsplit_once("\n\n") {
+        Some((_, last)) => last.lines().all(is_trailer),
+        None => false,
+    };
+    let separator = if trailers { "\n" } else { "\n\n" };
+    format!(
+        "{}{}(cherry picked from commit {})\n",
+        message, separator, oid
+    )
+}
+
+/// `Revert "<subject>"`, saying which commit it reverts and, for a merge,
+/// which parent's changes it keeps.
+fn revert_message(commit: &Commit, oid: ObjectId, parent: Option<ObjectId>) -> String {
+    let mut message = format!(
+        "Revert \"{}\"\n\nThis reverts commit {}",
+        commit.subject(),
+        oid
+    );
+    match parent {
+        Some(parent) if commit.parent_commits.len() > 1 => {
+            message.push_str(&format!(", reversing\nchanges made to {}.\n", parent))
+        }
+        _ => message.push_str(".\n"),
+    }
+    message
+}
@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::process::Command;
+
+use crate::repository::{
+    Commit, Config, ObjectDatabase, ObjectId, ObjectKind, RefStore, Signature,
+};
+use crate::Index;
+
+use super::merge::{finish_merge, merge_in_progress};
+use super::writetree::write_tree_from_index;
+
+pub fn commit(messages: Vec<String>) -> Result<()> {
+    let index = Index::default().read()?; // Read the index
+
+    let refs = RefStore::default();
+    // the ref HEAD names (`refs/heads/<branch>`), or HEAD itself when detached
+    let (head_ref, parent) = refs.follow("HEAD")?;
+
+    if parent.is_none() && index.entries.is_empty() {
+        anyhow::bail!("nothing to commit (create/copy files and use \"ugit add\" to track)");
+    }
+    anyhow::ensure!(
+        index.entries.iter().all(|entry| entry.stage() == 0),
+        "Committing is not possible because you have unmerged files.\n\
+         Fix them up in the work tree, and then use 'ugit add/rm <file>'\n\
+         as appropriate to mark resolution and make a commit."
+    );
+
+    // concluding a merge records the merged commit as a second parent
+    let merge = merge_in_progress()?;
+    let mut message = match (&merge, messages.is_empty()) {
+        (Some((_, merge_message)), true) => merge_message.clone(),
+        (_, true) => anyhow::bail!("Aborting commit due to empty commit message."),
+        (_, false) => messages.join("\n\n"),
+    };
+    message.push('\n');
+
+    let tree = write_tree_from_index(&index)?;
+    let mut parents: Vec<ObjectId> = parent.into_iter().collect();
+    parents.extend(merge.as_ref().map(|(merge_head, _)| *merge_head));
+    let subject = message.lines().next().unwrap_or_default().to_string();
+    let hash = create_commit(tree, parents, message, None)?;
+
+    let reflog_message = match parent {
+        Some(_) if merge.is_some() => format!("commit (merge): {}", subject),
+        Some(_) => format!("commit: {}", subject),
+        None => format!("commit (initial): {}", subject),
+    };
+    // Advance the branch HEAD names, failing if someone else moved it meanwhile
+    refs.update(
+        "HEAD",
+        hash,
+        Some(parent.unwrap_or(ObjectId::NULL)),
+        true,
+        &reflog_message,
+    )?;
+    if merge.is_some() {
+        finish_merge()?;
+    }
+
+    let branch = head_ref
+        .strip_prefix("refs/heads/")
+        .unwrap_or("detached HEAD");
+    let root = if parent.is_none() {
+        " (root-commit)"
+    } else {
+        ""
+    };
+    println!("[{}{} {}] {}", branch, root, hash.short(), subject);
+
+    Ok(())
+}
+
+/// Writes a commit of `tree` with the given parents, committed by the
+/// configured user and authored by them too unless `author` is given (as
+/// when replaying someone else's commit), and returns its hash.
+pub fn create_commit(
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    message: String,
+    author: Option<Signature>,
+) -> Result<ObjectId> {
+    let config = Config::load()?;
+    let author = match author {
+        Some(author) => author,
+        None => Signature::from_env("AUTHOR", &config)?,
+    };
+    let commit = Commit {
+        tree,
+        parent_commits: parents,
+        author,
+        committer: Signature::from_env("COMMITTER", &config)?,
+        extra_headers: Vec::new(),
+        message,
+    };
+    ObjectDatabase::default().write(ObjectKind::Commit, &commit.serialize())
+}
+
+/// Opens `path` in the user's editor (`core.editor`, `$VISUAL`, `$EDITOR`,
+/// or else `vi`) and waits for it to exit.
+pub fn launch_editor(path: &str) -> Result<()> {
+    let editor = match Config::load()?.get("core", "editor") {
+        Some(editor) => editor.to_string(),
+        None => std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string()),
+    };
+    // like git, the editor is a shell command that may carry arguments
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status()
+        .with_context(|| format!("unable to start editor '{}'", editor))?;
+    anyhow::ensure!(
+        status.success(),
+        "There was a problem with the editor '{}'.",
+        editor
+    );
+    Ok(())
+}
+
+/// Lets the user edit `message` in `ugit/COMMIT_EDITMSG` and returns the
+/// result cleaned up, failing if nothing is left.
+pub fn edit_message(message: &str) -> Result<String> {
+    const PATH: &str = "./ugit/COMMIT_EDITMSG";
+    let template = format!(
+        "{}\n\n# Please enter the commit message for your changes. Lines starting\n\
+         # with '#' will be ignored, and an empty message aborts the commit.\n",
+        message.trim_end()
+    );
+    fs::write(PATH, template).context("couldn't write COMMIT_EDITMSG")?;
+    launch_editor(PATH)?;
+    let edited = fs::read_to_string(PATH).context("couldn't read COMMIT_EDITMSG")?;
+    let message = cleanup_message(&edited);
+    anyhow::ensure!(
+        !message.is_empty(),
+        "Aborting commit due to empty commit message."
+    );
+    Ok(message)
+}
+
+/// Strips `#` comment lines, trailing whitespace and surplus blank lines
+/// from an edited message, ending it with a newline unless it's empty.
+pub fn cleanup_message(text: &str) -> String {
+    let mut message = String::new();
+    let mut blank = false;
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = !message.is_empty();
+            continue;
+        }
+        if blank {
+            message.push('\n');
+            blank = false;
+        }
+        message.push_str(line);
+        message.push('\n');
+    }
+    message
+}
@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+
+use crate::repository::{peel, resolve_revision};
+use crate::repository::{Commit, Config, ObjectDatabase, ObjectId, ObjectKind, Signature};
+
+pub fn commit_tree(tree: String, parents: Vec<String>, messages: Vec<String>) -> Result<ObjectId> {
+    let odb = ObjectDatabase::default();
+    let tree = peel(&odb, resolve_hash(&odb, &tree)?, Some(ObjectKind::Tree))?;
+    let parent_commits = parents
+        .iter()
+        .map(|parent| resolve_hash(&odb, parent))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Like git, each -m is its own paragraph; without -m the message is read from stdin.
+    let message = if messages.is_empty() {
+        let mut message = String::new();
+        std::io::stdin()
+            .read_to_string(&mut message)
+            .context("couldn't read commit message from stdin")?;
+        message
+    } else {
+        let mut message = messages.join("\n\n");
+        message.push('\n');
+        message
+    };
+
+    let config = Config::load()?;
+    let commit = Commit {
+        tree,
+        parent_commits,
+        author: Signature::from_env("AUTHOR", &config)?,
+        committer: Signature::from_env("COMMITTER", &config)?,
+        extra_headers: Vec::new(),
+        message,
+    };
+
+    odb.write(ObjectKind::Commit, &commit.serialize())
+}
+
+/// Resolves a revision given on the command line into a full object id.
+fn resolve_hash(odb: &ObjectDatabase, hash: &str) -> Result<ObjectId> {
+    resolve_revision(odb, hash).with_context(|| format!("not a valid object name: {}", hash))
+}
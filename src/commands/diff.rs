@@ -0,0 +1,577 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+
+use crate::repository::{
+    check_file, detect_renames, diff_files, diff_lines, diff_trees, flatten_tree, is_binary,
+    merge_bases, peel, read_worktree_file, resolve_revision, split_lines, unified_diff, Change,
+    DiffAlgorithm, DiffEntry, FileState, Index, IndexEntry, ObjectDatabase, ObjectId, ObjectKind,
+    RefStore, RenameOptions,
+};
+
+/// How `diff` and `diff-tree` show the changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// A unified diff for each file.
+    #[default]
+    Patch,
+    /// One `:<old mode> <new mode> <old id> <new id> <status>` line per file.
+    Raw,
+    NameOnly,
+    NameStatus,
+    /// A histogram of added and removed lines per file.
+    Stat,
+}
+
+/// Options for `diff` and `diff-tree`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffOptions {
+    /// Compare the index with HEAD (or the given commit) instead of the work tree.
+    pub cached: bool,
+    pub format: DiffFormat,
+    pub algorithm: DiffAlgorithm,
+    /// Unchanged lines shown around each change.
+    pub context: usize,
+    pub renames: RenameOptions,
+}
+
+/// The files on one side of a diff, by path, with their mode and blob id.
+type Files = BTreeMap<String, (u32, ObjectId)>;
+
+/// Blob contents, from the object database or, for work tree files that
+/// don't match the index, from disk.
+pub struct Blobs<'a> {
+    odb: &'a ObjectDatabase,
+    worktree: HashMap<ObjectId, Vec<u8>>,
+}
+
+impl<'a> Blobs<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> Blobs<'a> {
+        Blobs {
+            odb,
+            worktree: HashMap::new(),
+        }
+    }
+
+    pub fn read(&self, oid: &ObjectId) -> Result<Vec<u8>> {
+        match self.worktree.get(oid) {
+            Some(content) => Ok(content.clone()),
+            None => Ok(self.odb.read_raw(oid)?.1),
+        }
+    }
+
+    /// The content diffed for a file: submodules show the commit they are at.
+    fn content(&self, (mode, oid): (u32, ObjectId)) -> Result<Vec<u8>> {
+        match mode {
+            0o160000 => Ok(format!("Subproject commit {}\n", oid).into_bytes()),
+            _ => self.read(&oid),
+        }
+    }
+}
+
+pub fn diff(options: DiffOptions, revisions: Vec<String>, paths: Vec<String>) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let mut blobs = Blobs::new(&odb);
+
+    let entries = match (revisions.as_slice(), options.cached) {
+        ([], false) => diff_files(&index_files()?, &worktree_files(&mut blobs)?),
+        ([], true) => diff_files(&head_files(&odb)?, &index_files()?),
+        ([range], false) if range.contains("...") => {
+            let (a, b) = range.split_once("...").unwrap();
+            let base = merge_base_tree(&odb, range, a, b)?;
+            diff_trees(&odb, Some(base), Some(tree_id(&odb, b)?), true)?
+        }
+        ([range], false) if range.contains("..") => {
+            let (a, b) = range.split_once("..").unwrap();
+            diff_trees(&odb, Some(tree_id(&odb, a)?), Some(tree_id(&odb, b)?), true)?
+        }
+        ([rev], false) => {
+            let tree = flatten_tree(&odb, &tree_id(&odb, rev)?)?;
+            diff_files(&tree, &worktree_files(&mut blobs)?)
+        }
+        ([rev], true) => diff_files(&flatten_tree(&odb, &tree_id(&odb, rev)?)?, &index_files()?),
+        ([a, b], false) => {
+            diff_trees(&odb, Some(tree_id(&odb, a)?), Some(tree_id(&odb, b)?), true)?
+        }
+        _ => anyhow::bail!("usage: ugit diff [--cached] [<commit> [<commit>]] [-- <path>...]"),
+    };
+
+    let entries = entries
+        .into_iter()
+        .filter(|entry| in_paths(&entry.path, &paths))
+        .collect();
+    let entries = detect_renames(entries, options.renames, |oid| blobs.read(oid))?;
+    print_diff(&mut io::stdout().lock(), &blobs, &entries, options)
+}
+
+/// Whether `path` is one of `paths`, inside one of them, or a directory
+/// holding one of them; everything matches when there are none.
+pub fn in_paths(path: &str, paths: &[String]) -> bool {
+    let inside = |path: &str, dir: &str| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+    };
+    paths.is_empty()
+        || paths.iter().any(|prefix| {
+            let prefix = prefix.trim_start_matches("./").trim_end_matches('/');
+            prefix.is_empty()
+                || prefix == "."
+                || path == prefix
+                || inside(path, prefix)
+                || inside(prefix, path)
+        })
+}
+
+/// The tree `rev` names (an empty revision meaning HEAD).
+pub fn tree_id(odb: &ObjectDatabase, rev: &str) -> Result<ObjectId> {
+    let rev = if rev.is_empty() { "HEAD" } else { rev };
+    peel(odb, resolve_revision(odb, rev)?, Some(ObjectKind::Tree))
+        .with_context(|| format!("'{}' is not a tree-ish", rev))
+}
+
+/// The tree of where `a` and `b` forked, for `diff A...B`. Like git, the
+/// first merge base is used when there are several.
+fn merge_base_tree(odb: &ObjectDatabase, range: &str, a: &str, b: &str) -> Result<ObjectId> {
+    let commit = |rev: &str| {
+        let rev = if rev.is_empty() { "HEAD" } else { rev };
+        peel(odb, resolve_revision(odb, rev)?, Some(ObjectKind::Commit))
+            .with_context(|| format!("'{}' is not a commit", rev))
+    };
+    let bases = merge_bases(odb, commit(a)?, &[commit(b)?])?;
+    let base = bases
+        .first()
+        .with_context(|| format!("{}: no merge base", range))?;
+    if bases.len() > 1 {
+        eprintln!("warning: {}: multiple merge bases, using {}", range, base);
+    }
+    Ok(odb.read_commit(base)?.tree)
+}
+
+/// The HEAD tree's files, or none before the first commit.
+fn head_files(odb: &ObjectDatabase) -> Result<Files> {
+    match RefStore::default().resolve("HEAD")? {
+        Some(head) => flatten_tree(odb, &odb.read_commit(&head)?.tree),
+        None => Ok(Files::new()),
+    }
+}
+
+fn index_files() -> Result<Files> {
+    let index = Index::default().read()?;
+    Ok(index
+        .entries
+        .into_iter()
+        .map(|entry| (entry.path, (entry.mode, entry.sha1)))
+        .collect())
+}
+
+/// The tracked files as they are on disk. Changed files are hashed and their
+/// content kept in `blobs`, since it isn't in the object database.
+fn worktree_files(blobs: &mut Blobs) -> Result<Files> {
+    let mut files = Files::new();
+    for entry in Index::default().read()?.entries {
+        match check_file(&entry)? {
+            FileState::Deleted => {}
+            FileState::Unchanged => {
+                files.insert(entry.path, (entry.mode, entry.sha1));
+            }
+            FileState::Modified => {
+                let metadata = fs::symlink_metadata(&entry.path)
+                    .with_context(|| format!("couldn't stat {}", entry.path))?;
+                let content = read_worktree_file(&entry.path, &metadata)?;
+                let oid = ObjectDatabase::hash(ObjectKind::Blob, &content);
+                blobs.worktree.insert(oid, content);
+                let mode = IndexEntry::mode_from_metadata(&metadata);
+                files.insert(entry.path, (mode, oid));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Prints `entries` in the chosen format.
+pub fn print_diff(
+    out: &mut impl Write,
+    blobs: &Blobs,
+    entries: &[DiffEntry],
+    options: DiffOptions,
+) -> Result<()> {
+    match options.format {
+        DiffFormat::Patch => {
+            for entry in entries {
+                write_patch(out, blobs, entry, options)?;
+            }
+        }
+        DiffFormat::Raw => {
+            for entry in entries {
+                let (old_mode, old_oid) = entry.old.unwrap_or((0, ObjectId::NULL));
+                let (new_mode, new_oid) = entry.new.unwrap_or((0, ObjectId::NULL));
+                write!(
+                    out,
+                    ":{:06o} {:06o} {} {} {}\t",
+                    old_mode,
+                    new_mode,
+                    old_oid,
+                    new_oid,
+                    status(entry)
+                )?;
+                write_paths(out, entry)?;
+            }
+        }
+        DiffFormat::NameOnly => {
+            for entry in entries {
+                writeln!(out, "{}", entry.path)?;
+            }
+        }
+        DiffFormat::NameStatus => {
+            for entry in entries {
+                write!(out, "{}\t", status(entry))?;
+                write_paths(out, entry)?;
+            }
+        }
+        DiffFormat::Stat => write_stat(out, blobs, entries, options)?,
+    }
+    Ok(())
+}
+
+/// The status letter, followed by the similarity for renames and copies.
+fn status(entry: &DiffEntry) -> String {
+    match entry.similarity {
+        Some(similarity) => format!("{}{:03}", entry.change.letter(), similarity),
+        None => entry.change.letter().to_string(),
+    }
+}
+
+fn write_paths(out: &mut impl Write, entry: &DiffEntry) -> Result<()> {
+    match entry.change {
+        Change::Renamed | Change::Copied => writeln!(out, "{}\t{}", entry.old_path, entry.path)?,
+        _ => writeln!(out, "{}", entry.path)?,
+    }
+    Ok(())
+}
+
+/// Writes the `diff --git` header and hunks for one file. A file that
+/// changed type is shown as a deletion followed by an addition.
+pub fn write_patch(
+    out: &mut impl Write,
+    blobs: &Blobs,
+    entry: &DiffEntry,
+    options: DiffOptions,
+) -> Result<()> {
+    if entry.change == Change::TypeChanged {
+        let deleted = DiffEntry {
+            change: Change::Deleted,
+            new: None,
+            ..entry.clone()
+        };
+        let added = DiffEntry {
+            change: Change::Added,
+            old: None,
+            ..entry.clone()
+        };
+        write_patch(out, blobs, &deleted, options)?;
+        return write_patch(out, blobs, &added, options);
+    }
+
+    let (old, new) = (entry.old, entry.new);
+    writeln!(out, "diff --git a/{} b/{}", entry.old_path, entry.path)?;
+    match (old, new) {
+        (None, Some((mode, _))) => writeln!(out, "new file mode {:06o}", mode)?,
+        (Some((mode, _)), None) => writeln!(out, "deleted file mode {:06o}", mode)?,
+        (Some((old_mode, _)), Some((new_mode, _))) if old_mode != new_mode => {
+            writeln!(out, "old mode {:06o}", old_mode)?;
+            writeln!(out, "new mode {:06o}", new_mode)?;
+        }
+        _ => {}
+    }
+    let verb = match entry.change {
+        Change::Renamed => Some("rename"),
+        Change::Copied => Some("copy"),
+        _ => None,
+    };
+    if let Some(verb) = verb {
+        writeln!(out, "similarity index {}%", entry.similarity.unwrap_or(0))?;
+        writeln!(out, "{} from {}", verb, entry.old_path)?;
+        writeln!(out, "{} to {}", verb, entry.path)?;
+    }
+
+    let old_oid = old.map_or(ObjectId::NULL, |(_, oid)| oid);
+    let new_oid = new.map_or(ObjectId::NULL, |(_, oid)| oid);
+    if old_oid == new_oid {
+        return Ok(()); // only the mode or the name changed
+    }
+    write!(out, "index {}..{}", old_oid.short(), new_oid.short())?;
+    match (old, new) {
+        (Some((old_mode, _)), Some((new_mode, _))) if old_mode == new_mode => {
+            writeln!(out, " {:06o}", old_mode)?
+        }
+        _ => writeln!(out)?,
+    }
+
+    let old_content = old.map(|file| blobs.content(file)).transpose()?;
+    let new_content = new.map(|file| blobs.content(file)).transpose()?;
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", entry.old_path));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", entry.path));
+    let old_content = old_content.unwrap_or_default();
+    let new_content = new_content.unwrap_or_default();
+
+    if is_binary(&old_content) || is_binary(&new_content) {
+        writeln!(out, "Binary files {} and {} differ", old_name, new_name)?;
+        return Ok(());
+    }
+    let hunks = unified_diff(
+        &old_content,
+        &new_content,
+        options.algorithm,
+        options.context,
+    );
+    if hunks.is_empty() {
+        return Ok(()); // e.g. a new empty file
+    }
+    writeln!(out, "--- {}", old_name)?;
+    writeln!(out, "+++ {}", new_name)?;
+    for hunk in hunks {
+        write!(out, "{}", hunk.header())?;
+        if let Some(heading) = hunk.heading {
+            out.write_all(b" ")?;
+            out.write_all(heading)?;
+        }
+        out.write_all(b"\n")?;
+        for (sign, line) in hunk.lines {
+            write!(out, "{}", sign)?;
+            out.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                out.write_all(b"\n\\ No newline at end of file\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What `--stat` shows for a file: lines added and removed, or for binary
+/// files the size before and after.
+enum Stat {
+    Text { added: usize, removed: usize },
+    Binary { old_size: usize, new_size: usize },
+}
+
+/// `--stat`, laid out like git's for an 80 column terminal.
+fn write_stat(
+    out: &mut impl Write,
+    blobs: &Blobs,
+    entries: &[DiffEntry],
+    options: DiffOptions,
+) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut stats = Vec::new();
+    for entry in entries {
+        let old = entry.old.map(|file| blobs.content(file)).transpose()?;
+        let new = entry.new.map(|file| blobs.content(file)).transpose()?;
+        let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+        let stat = if is_binary(&old) || is_binary(&new) {
+            Stat::Binary {
+                old_size: old.len(),
+                new_size: new.len(),
+            }
+        } else {
+            let edits = diff_lines(&split_lines(&old), &split_lines(&new), options.algorithm);
+            Stat::Text {
+                added: edits.iter().map(|edit| edit.new.len()).sum(),
+                removed: edits.iter().map(|edit| edit.old.len()).sum(),
+            }
+        };
+        let name = match entry.change {
+            Change::Renamed | Change::Copied => rename_name(&entry.old_path, &entry.path),
+            _ => entry.path.clone(),
+        };
+        stats.push((name, stat));
+    }
+
+    let digits = |n: usize| n.to_string().len();
+    let mut max_change = 0;
+    let mut number_width = 0;
+    let mut bin_width = 0;
+    for (_, stat) in &stats {
+        match *stat {
+            Stat::Text { added, removed } => max_change = max_change.max(added + removed),
+            Stat::Binary { old_size, new_size } => {
+                // "Bin XXX -> YYY bytes", lined up with the counts
+                bin_width = bin_width.max(14 + digits(old_size) + digits(new_size));
+                number_width = 3;
+            }
+        }
+    }
+    let number_width = number_width.max(digits(max_change));
+    let width = 80.max(16 + 6 + number_width);
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = stats
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    if name_width + number_width + 6 + graph_width > width {
+        if graph_width + number_width + 6 > width * 3 / 8 {
+            graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for (name, stat) in &stats {
+        // names that don't fit lose their start, up to a `/` if possible
+        let length = name.chars().count();
+        let name = if length > name_width {
+            let keep = name_width.saturating_sub(3);
+            let tail: String = name.chars().skip(length - keep).collect();
+            let tail = match tail.find('/') {
+                Some(slash) => tail[slash..].to_string(),
+                None => tail,
+            };
+            format!("...{}", tail)
+        } else {
+            name.clone()
+        };
+        write!(out, " {:<width$} | ", name, width = name_width)?;
+
+        let (added, removed) = match *stat {
+            Stat::Binary { old_size, new_size } => {
+                write!(out, "{:>width$}", "Bin", width = number_width)?;
+                if old_size != 0 || new_size != 0 {
+                    write!(out, " {} -> {} bytes", old_size, new_size)?;
+                }
+                writeln!(out)?;
+                continue;
+            }
+            Stat::Text { added, removed } => (added, removed),
+        };
+        insertions += added;
+        deletions += removed;
+        let total = added + removed;
+        write!(out, "{:>width$}", total, width = number_width)?;
+        if total > 0 {
+            write!(out, " ")?;
+        }
+
+        let (mut plus, mut minus) = (added, removed);
+        if graph_width <= max_change {
+            let scale = |n: usize| match n {
+                0 => 0,
+                n => 1 + n * (graph_width - 1) / max_change,
+            };
+            let mut total = scale(total);
+            if total < 2 && added > 0 && removed > 0 {
+                total = 2;
+            }
+            if added < removed {
+                plus = scale(added);
+                minus = total - plus;
+            } else {
+                minus = scale(removed);
+                plus = total - minus;
+            }
+        }
+        writeln!(out, "{}{}", "+".repeat(plus), "-".repeat(minus))?;
+    }
+
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    write!(
+        out,
+        " {}",
+        plural(stats.len(), "file changed", "files changed")
+    )?;
+    if insertions > 0 || deletions == 0 {
+        write!(
+            out,
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        )?;
+    }
+    if deletions > 0 || insertions == 0 {
+        write!(
+            out,
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// `--summary`: the files created, deleted, renamed or copied, and mode changes.
+pub fn write_summary(out: &mut impl Write, entries: &[DiffEntry]) -> Result<()> {
+    let mode = |side: Option<(u32, ObjectId)>| side.map_or(0, |(mode, _)| mode);
+    for entry in entries {
+        let (old, new) = (mode(entry.old), mode(entry.new));
+        match entry.change {
+            Change::Added => writeln!(out, " create mode {:06o} {}", new, entry.path)?,
+            Change::Deleted => writeln!(out, " delete mode {:06o} {}", old, entry.path)?,
+            Change::Renamed | Change::Copied => {
+                let kind = match entry.change {
+                    Change::Renamed => "rename",
+                    _ => "copy",
+                };
+                let similarity = entry.similarity.unwrap_or(100);
+                let name = rename_name(&entry.old_path, &entry.path);
+                writeln!(out, " {} {} ({}%)", kind, name, similarity)?;
+                if old != new {
+                    writeln!(out, " mode change {:06o} => {:06o}", old, new)?;
+                }
+            }
+            _ if old != new => {
+                writeln!(out, " mode change {:06o} => {:06o} {}", old, new, entry.path)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `old => new`, with a common leading and trailing directory written once
+/// as in `src/{a => b}/lib.rs`.
+fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // the suffix starts at a `/` and may share that slash with the prefix
+    let mut suffix = 0;
+    let shared_slash = usize::from(prefix > 0);
+    for k in 1..=a.len().min(b.len()) + shared_slash - prefix {
+        let (x, y) = (a[a.len() - k], b[b.len() - k]);
+        if x != y {
+            break;
+        }
+        if x == b'/' {
+            suffix = k;
+        }
+    }
+    let a_mid = &old[prefix..old.len().saturating_sub(suffix).max(prefix)];
+    let b_mid = &new[prefix..new.len().saturating_sub(suffix).max(prefix)];
+    if prefix + suffix == 0 {
+        return format!("{} => {}", a_mid, b_mid);
+    }
+    format!(
+        "{}{{{} => {}}}{}",
+        &old[..prefix],
+        a_mid,
+        b_mid,
+        &old[old.len() - suffix..]
+    )
+}
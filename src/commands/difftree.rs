@@ -0,0 +1,55 @@
+use anyhow::Result;
+use std::io::{self, Write};
+
+use crate::repository::{
+    detect_renames, diff_trees, peel, resolve_revision, ObjectDatabase, ObjectKind,
+};
+
+use super::diff::{in_paths, print_diff, tree_id, Blobs, DiffFormat, DiffOptions};
+
+/// Compares two trees, or a commit with its parent. Subtrees are only
+/// compared entry by entry with `recursive` (implied by a patch).
+pub fn diff_tree(
+    options: DiffOptions,
+    recursive: bool,
+    root: bool,
+    args: Vec<String>,
+    paths: Vec<String>,
+) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let recursive = recursive || options.format != DiffFormat::Raw;
+
+    let (commit, old, new) = match args.as_slice() {
+        [rev] => {
+            let oid = peel(&odb, resolve_revision(&odb, rev)?, Some(ObjectKind::Commit))?;
+            let commit = odb.read_commit(&oid)?;
+            let old = match commit.parent_commits.as_slice() {
+                [parent] => Some(odb.read_commit(parent)?.tree),
+                [] if root => None,
+                // root commits need --root, and merges have no single parent
+                _ => return Ok(()),
+            };
+            (Some(oid), old, commit.tree)
+        }
+        [a, b] => (None, Some(tree_id(&odb, a)?), tree_id(&odb, b)?),
+        _ => {
+            anyhow::bail!("usage: ugit diff-tree [-r] [-p] <tree-ish> [<tree-ish>] [-- <path>...]")
+        }
+    };
+
+    let entries = diff_trees(&odb, old, Some(new), recursive)?
+        .into_iter()
+        .filter(|entry| in_paths(&entry.path, &paths))
+        .collect();
+    let blobs = Blobs::new(&odb);
+    let entries = detect_renames(entries, options.renames, |oid| blobs.read(oid))?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut out = io::stdout().lock();
+    if let Some(commit) = commit {
+        writeln!(out, "{}", commit)?;
+    }
+    print_diff(&mut out, &blobs, &entries, options)
+}
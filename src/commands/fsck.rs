@@ -0,0 +1,308 @@
+use anyhow::{Context, Result};
+use crypto_hash::{digest, Algorithm};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::repository::{
+    check_object, check_root_tree, shorten_ref_name, Object, ObjectDatabase, ObjectId, ObjectKind, RefStore,
+    Severity,
+};
+use crate::{find_index, Index};
+
+/// What fsck learnt about the objects in the database: each one's kind, and
+/// every link from one object to another along with the kind it should be.
+#[derive(Default)]
+struct Objects {
+    kinds: HashMap<ObjectId, ObjectKind>,
+    links: Vec<(ObjectId, ObjectId, ObjectKind)>,
+    /// The trees at the top of commits.
+    root_trees: HashSet<ObjectId>,
+    failed: bool,
+}
+
+/// Checks the object database, refs and index: every object's hash and
+/// syntax, that everything referenced exists and is the right kind, and that
+/// the index is sorted and only names blobs we have. Reports unreachable
+/// objects nothing points at as dangling. Exits with status 1 if anything is
+/// broken; dangling objects and warnings alone are fine.
+pub fn fsck() -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let mut objects = Objects::default();
+
+    for oid in odb.loose_objects()? {
+        let path = odb.object_path(&oid);
+        objects.check(oid, odb.read_raw(&oid), &path);
+    }
+    for pack in odb.packs()? {
+        let path = pack.index().path().with_extension("pack");
+        objects.failed |= !verify_pack_checksum(&path)?;
+        for oid in pack.index().oids() {
+            let offset = pack
+                .index()
+                .offset(oid)
+                .expect("listed objects are in the index");
+            objects.check(*oid, pack.read(&odb, offset), &path);
+        }
+    }
+    // only at the top can a tree clash with our repository directory
+    for tree in &objects.root_trees {
+        let Ok((ObjectKind::Tree, content)) = odb.read_raw(tree) else {
+            continue;
+        };
+        if let Some(problem) = check_root_tree(&content) {
+            eprintln!("error in tree {}: {}", tree, problem);
+            objects.failed = true;
+        }
+    }
+
+    let mut roots = check_refs(&odb, &mut objects)?;
+    let staged = match find_index() {
+        Ok(_) => check_index(&mut objects)?,
+        Err(_) => Vec::new(),
+    };
+    roots.extend(&staged);
+
+    let mut reachable: HashSet<ObjectId> = HashSet::new();
+    let mut children: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+    for (from, to, _) in &objects.links {
+        children.entry(*from).or_default().push(*to);
+    }
+    while let Some(oid) = roots.pop() {
+        if reachable.insert(oid) {
+            roots.extend(children.get(&oid).into_iter().flatten());
+        }
+    }
+
+    // like git, only what's reachable has to be complete
+    let mut missing = BTreeMap::new();
+    for (from, to, kind) in &objects.links {
+        match objects.kinds.get(to) {
+            None if reachable.contains(from) => {
+                println!(
+                    "broken link from {:>7} {}",
+                    objects.kinds[from].to_string(),
+                    from
+                );
+                println!("              to {:>7} {}", kind.to_string(), to);
+                missing.insert(*to, *kind);
+            }
+            Some(actual) if actual != kind => {
+                eprintln!("error: object {} is a {}, not a {}", to, actual, kind);
+                objects.failed = true;
+            }
+            _ => {}
+        }
+    }
+    for oid in staged {
+        if !objects.kinds.contains_key(&oid) {
+            missing.insert(oid, ObjectKind::Blob);
+        }
+    }
+    objects.failed |= !missing.is_empty();
+    for (oid, kind) in missing {
+        println!("missing {} {}", kind, oid);
+    }
+
+    // unreachable objects that no other object points at
+    let referenced: HashSet<&ObjectId> = objects.links.iter().map(|(_, to, _)| to).collect();
+    let mut dangling: Vec<(&ObjectId, &ObjectKind)> = objects
+        .kinds
+        .iter()
+        .filter(|(oid, _)| !reachable.contains(oid) && !referenced.contains(oid))
+        .collect();
+    dangling.sort_by_key(|(oid, _)| **oid);
+    for (oid, kind) in dangling {
+        println!("dangling {} {}", kind, oid);
+    }
+
+    if objects.failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+impl Objects {
+    /// Checks one object read from `path`, recording its kind and links.
+    fn check(&mut self, oid: ObjectId, read: Result<(ObjectKind, Vec<u8>)>, path: &Path) {
+        let Ok((kind, content)) = read else {
+            eprintln!(
+                "error: {}: object corrupt or missing: {}",
+                oid,
+                path.display()
+            );
+            self.failed = true;
+            return;
+        };
+        let actual = ObjectDatabase::hash(kind, &content);
+        if actual != oid {
+            eprintln!(
+                "error: {}: hash-path mismatch, found at: {}",
+                actual,
+                path.display()
+            );
+            self.failed = true;
+            return;
+        }
+        if self.kinds.insert(oid, kind).is_some() {
+            return; // both loose and packed, and the other copy was fine
+        }
+
+        let problems = check_object(kind, &content);
+        for problem in &problems {
+            match problem.severity {
+                Severity::Error => eprintln!("error in {} {}: {}", kind, oid, problem),
+                Severity::Warning => eprintln!("warning in {} {}: {}", kind, oid, problem),
+            }
+        }
+        let broken = problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error);
+        self.failed |= broken;
+
+        // links are still recorded when what they come from is broken, so
+        // what it points at isn't mistaken for dangling
+        let Ok(object) = Object::parse(kind, content) else {
+            if !broken {
+                eprintln!(
+                    "error: {}: object could not be parsed: {}",
+                    oid,
+                    path.display()
+                );
+            }
+            self.failed = true;
+            return;
+        };
+        match object {
+            Object::Blob(_) => {}
+            Object::Tree(tree) => {
+                for entry in tree.entries {
+                    match entry.mode {
+                        0o160000 => {} // submodule commits live in another repository
+                        0o040000 => self.links.push((oid, entry.hash, ObjectKind::Tree)),
+                        _ => self.links.push((oid, entry.hash, ObjectKind::Blob)),
+                    }
+                }
+            }
+            Object::Commit(commit) => {
+                self.links.push((oid, commit.tree, ObjectKind::Tree));
+                self.root_trees.insert(commit.tree);
+                for parent in commit.parent_commits {
+                    self.links.push((oid, parent, ObjectKind::Commit));
+                }
+            }
+            Object::Tag(tag) => self.links.push((oid, tag.object, tag.kind)),
+        }
+    }
+
+    /// Whether `oid` exists, complaining with `what` as the culprit if not.
+    fn expect(&mut self, what: &str, oid: &ObjectId) -> Option<ObjectKind> {
+        let kind = self.kinds.get(oid).copied();
+        if kind.is_none() {
+            eprintln!("error: {}: invalid sha1 pointer {}", what, oid);
+            self.failed = true;
+        }
+        kind
+    }
+}
+
+/// Checks that refs point at objects we have, and branches at commits.
+/// Returns the objects they point at, along with those in the reflogs.
+fn check_refs(odb: &ObjectDatabase, objects: &mut Objects) -> Result<Vec<ObjectId>> {
+    let refs = RefStore::default();
+    let mut roots = Vec::new();
+    for (name, oid) in refs.list("refs/")? {
+        match objects.expect(&name, &oid) {
+            Some(kind) if kind != ObjectKind::Commit && name.starts_with("refs/heads/") => {
+                eprintln!("error: {}: not a commit", name);
+                objects.failed = true;
+            }
+            _ => {}
+        }
+        roots.push(oid);
+    }
+
+    match refs.follow("HEAD")? {
+        (name, None) if name != "HEAD" => {
+            eprintln!(
+                "notice: HEAD points to an unborn branch ({})",
+                shorten_ref_name(&name)
+            );
+        }
+        (_, None) => {
+            eprintln!("error: HEAD: not a valid ref");
+            objects.failed = true;
+        }
+        (_, Some(oid)) => {
+            objects.expect("HEAD", &oid);
+            roots.push(oid);
+        }
+    }
+
+    // old reflog entries may name objects that are long gone
+    roots.extend(
+        refs.reflog_objects()?
+            .into_iter()
+            .filter(|oid| odb.exists(oid)),
+    );
+    Ok(roots)
+}
+
+/// Checks that the index is sorted by path and stage, has no duplicate
+/// entries, and doesn't name anything but blobs. Returns the blobs it names.
+fn check_index(objects: &mut Objects) -> Result<Vec<ObjectId>> {
+    let index = Index::default().read()?;
+    let mut seen = HashSet::new();
+    for (i, entry) in index.entries.iter().enumerate() {
+        if !seen.insert((&entry.path, entry.stage())) {
+            eprintln!("error: duplicate index entries for '{}'", entry.path);
+            objects.failed = true;
+        }
+        let Some(next) = index.entries.get(i + 1) else {
+            continue;
+        };
+        match entry.path.as_bytes().cmp(next.path.as_bytes()) {
+            Ordering::Greater => eprintln!("error: unordered stage entries in index"),
+            // reported as a duplicate when we get to it
+            Ordering::Equal if entry.stage() == next.stage() => continue,
+            Ordering::Equal if entry.stage() == 0 || next.stage() == 0 => {
+                eprintln!(
+                    "error: multiple stage entries for merged file '{}'",
+                    entry.path
+                )
+            }
+            Ordering::Equal if entry.stage() > next.stage() => {
+                eprintln!("error: unordered stage entries for '{}'", entry.path)
+            }
+            _ => continue,
+        }
+        objects.failed = true;
+    }
+
+    let mut blobs = Vec::new();
+    for entry in &index.entries {
+        if entry.mode == 0o160000 {
+            continue;
+        }
+        if let Some(kind) = objects.kinds.get(&entry.sha1) {
+            if *kind != ObjectKind::Blob {
+                eprintln!("error: object {} is a {}, not a blob", entry.sha1, kind);
+                objects.failed = true;
+            }
+        }
+        blobs.push(entry.sha1);
+    }
+    Ok(blobs)
+}
+
+/// Whether a pack's trailing checksum matches its contents.
+fn verify_pack_checksum(path: &Path) -> Result<bool> {
+    let data = fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let (content, trailer) = data.split_at(data.len().saturating_sub(20));
+    if digest(Algorithm::SHA1, content) != trailer {
+        eprintln!("error: {}: pack checksum mismatch", path.display());
+        return Ok(false);
+    }
+    Ok(true)
+}
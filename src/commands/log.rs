@@ -0,0 +1,411 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+use crate::repository::{
+    parse_approxidate, Commit, ObjectDatabase, ObjectId, RevWalk, Signature, Sort, WalkedCommit,
+};
+
+/// Options for `log`.
+#[derive(Debug, Default, Clone)]
+pub struct LogOptions {
+    /// Show each commit as `<short id> <subject>`.
+    pub oneline: bool,
+    /// Draw the commit graph next to the log.
+    pub graph: bool,
+    /// A pretty format name (`oneline`, `short`, `medium`, `full`, `fuller`)
+    /// or a `format:`/`tformat:` string with `%` placeholders.
+    pub format: Option<String>,
+    pub max_count: Option<usize>,
+    pub first_parent: bool,
+    pub sort: Sort,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub author: Option<String>,
+    pub grep: Option<String>,
+}
+
+/// How each commit is printed.
+enum Pretty {
+    Oneline {
+        abbrev: bool,
+    },
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    /// A `format:`/`tformat:` string; every entry ends with a newline.
+    Format(String),
+}
+
+impl Pretty {
+    fn parse(format: &str) -> Result<Pretty> {
+        Ok(match format {
+            "oneline" => Pretty::Oneline { abbrev: false },
+            "short" => Pretty::Short,
+            "medium" => Pretty::Medium,
+            "full" => Pretty::Full,
+            "fuller" => Pretty::Fuller,
+            format => {
+                let template = format
+                    .strip_prefix("format:")
+                    .or_else(|| format.strip_prefix("tformat:"))
+                    .or_else(|| format.contains('%').then_some(format))
+                    .with_context(|| format!("invalid --pretty format: {}", format))?;
+                Pretty::Format(template.to_string())
+            }
+        })
+    }
+
+    /// Whether entries are separated by a blank line.
+    fn blank_line_between(&self) -> bool {
+        matches!(
+            self,
+            Pretty::Short | Pretty::Medium | Pretty::Full | Pretty::Fuller
+        )
+    }
+}
+
+pub fn log(options: LogOptions, revisions: Vec<String>, paths: Vec<String>) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let mut walk = RevWalk::new(&odb);
+    if revisions.is_empty() {
+        walk.push_revision("HEAD")?;
+    }
+    for revision in &revisions {
+        walk.push_revision(revision)?;
+    }
+
+    walk.sort = match (options.graph, options.sort) {
+        // the graph can only be drawn if children come before parents
+        (true, Sort::Chronological) => Sort::Topo,
+        (_, sort) => sort,
+    };
+    walk.first_parent = options.first_parent;
+    walk.max_count = options.max_count;
+    walk.since = options
+        .since
+        .as_deref()
+        .map(parse_approxidate)
+        .transpose()?;
+    walk.until = options
+        .until
+        .as_deref()
+        .map(parse_approxidate)
+        .transpose()?;
+    walk.author = options.author;
+    walk.grep = options.grep;
+    walk.paths = paths;
+
+    let pretty = match (&options.format, options.oneline) {
+        (Some(format), _) => Pretty::parse(format)?,
+        (None, true) => Pretty::Oneline { abbrev: true },
+        (None, false) => Pretty::Medium,
+    };
+
+    let mut graph = options.graph.then(Graph::default);
+    for (n, entry) in walk.run()?.iter().enumerate() {
+        let lines = format_entry(&pretty, entry);
+        let Some(graph) = graph.as_mut() else {
+            if n > 0 && pretty.blank_line_between() {
+                println!();
+            }
+            for line in &lines {
+                println!("{}", line);
+            }
+            continue;
+        };
+
+        let step = graph.next(entry.oid, &entry.parents);
+        if n > 0 && pretty.blank_line_between() {
+            println!("{}", step.before);
+        }
+        for i in 0..step.rows.len().max(lines.len()) {
+            let prefix = step.rows.get(i).unwrap_or(&step.after);
+            println!("{}{}", prefix, lines.get(i).map_or("", String::as_str));
+        }
+    }
+    Ok(())
+}
+
+/// The subject (first paragraph, joined into one line) and body of a message.
+fn split_message(message: &str) -> (String, &str) {
+    let message = message.trim_start_matches('\n');
+    let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+    let subject = subject.lines().collect::<Vec<_>>().join(" ");
+    (subject, body.trim_start_matches('\n'))
+}
+
+fn default_date(signature: &Signature) -> String {
+    signature
+        .datetime()
+        .format("%a %b %-d %H:%M:%S %Y %z")
+        .to_string()
+}
+
+/// `3 days ago` style dates, as `%ar` shows them.
+fn relative_date(signature: &Signature) -> String {
+    let seconds = (Utc::now().timestamp() - signature.time).max(0);
+    let (amount, unit) = match seconds {
+        s if s < 90 => (s, "second"),
+        s if s < 90 * 60 => (s / 60, "minute"),
+        s if s < 36 * 60 * 60 => (s / (60 * 60), "hour"),
+        s if s < 14 * 24 * 60 * 60 => (s / (24 * 60 * 60), "day"),
+        s if s < 10 * 7 * 24 * 60 * 60 => (s / (7 * 24 * 60 * 60), "week"),
+        s if s < 365 * 24 * 60 * 60 => (s / (30 * 24 * 60 * 60), "month"),
+        s => (s / (365 * 24 * 60 * 60), "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{} ago", amount, unit, plural)
+}
+
+/// The lines printed for one commit, without graph prefixes.
+fn format_entry(pretty: &Pretty, entry: &WalkedCommit) -> Vec<String> {
+    let commit = &entry.commit;
+    let (subject, _) = split_message(&commit.message);
+    let mut lines = Vec::new();
+
+    let header = |lines: &mut Vec<String>| {
+        lines.push(format!("commit {}", entry.oid));
+        if commit.parent_commits.len() > 1 {
+            let parents: Vec<String> = commit.parent_commits.iter().map(|p| p.short()).collect();
+            lines.push(format!("Merge: {}", parents.join(" ")));
+        }
+    };
+    let message = |lines: &mut Vec<String>, whole: bool| {
+        lines.push(String::new());
+        if whole {
+            lines.extend(
+                commit
+                    .message
+                    .trim_end()
+                    .lines()
+                    .map(|l| format!("    {}", l)),
+            );
+        } else {
+            lines.push(format!("    {}", subject));
+        }
+    };
+    let who = |signature: &Signature| format!("{} <{}>", signature.name, signature.email);
+
+    match pretty {
+        Pretty::Oneline { abbrev } => {
+            let id = if *abbrev {
+                entry.oid.short()
+            } else {
+                entry.oid.to_string()
+            };
+            lines.push(format!("{} {}", id, subject));
+        }
+        Pretty::Short => {
+            header(&mut lines);
+            lines.push(format!("Author: {}", who(&commit.author)));
+            message(&mut lines, false);
+        }
+        Pretty::Medium => {
+            header(&mut lines);
+            lines.push(format!("Author: {}", who(&commit.author)));
+            lines.push(format!("Date:   {}", default_date(&commit.author)));
+            message(&mut lines, true);
+        }
+        Pretty::Full => {
+            header(&mut lines);
+            lines.push(format!("Author: {}", who(&commit.author)));
+            lines.push(format!("Commit: {}", who(&commit.committer)));
+            message(&mut lines, true);
+        }
+        Pretty::Fuller => {
+            header(&mut lines);
+            lines.push(format!("Author:     {}", who(&commit.author)));
+            lines.push(format!("AuthorDate: {}", default_date(&commit.author)));
+            lines.push(format!("Commit:     {}", who(&commit.committer)));
+            lines.push(format!("CommitDate: {}", default_date(&commit.committer)));
+            message(&mut lines, true);
+        }
+        Pretty::Format(template) => {
+            let expanded = expand_format(template, entry.oid, commit);
+            lines.extend(expanded.split('\n').map(str::to_string));
+        }
+    }
+    lines
+}
+
+/// Expands `%` placeholders in a `--format` string. Unknown placeholders are
+/// left as they are, like git does.
+fn expand_format(template: &str, oid: ObjectId, commit: &Commit) -> String {
+    let (subject, body) = split_message(&commit.message);
+    let ids = |oids: &[ObjectId], short: bool| {
+        oids.iter()
+            .map(|oid| if short { oid.short() } else { oid.to_string() })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let person = |signature: &Signature, field: char| -> Option<String> {
+        let date = signature.datetime();
+        Some(match field {
+            'n' => signature.name.clone(),
+            'e' => signature.email.clone(),
+            'd' => default_date(signature),
+            't' => signature.time.to_string(),
+            'i' => date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            'I' => date.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            'r' => relative_date(signature),
+            _ => return None,
+        })
+    };
+
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let Some(placeholder) = chars.next() else {
+            out.push('%');
+            break;
+        };
+        let expanded = match placeholder {
+            '%' => Some("%".to_string()),
+            'n' => Some("\n".to_string()),
+            'H' => Some(oid.to_string()),
+            'h' => Some(oid.short()),
+            'T' => Some(commit.tree.to_string()),
+            't' => Some(commit.tree.short()),
+            'P' => Some(ids(&commit.parent_commits, false)),
+            'p' => Some(ids(&commit.parent_commits, true)),
+            's' => Some(subject.clone()),
+            'b' => Some(body.to_string()),
+            'B' => Some(commit.message.clone()),
+            'a' | 'c' => {
+                let signature = if placeholder == 'a' {
+                    &commit.author
+                } else {
+                    &commit.committer
+                };
+                let field = chars.peek().copied();
+                match field.and_then(|field| person(signature, field)) {
+                    Some(value) => {
+                        chars.next();
+                        Some(value)
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        match expanded {
+            Some(value) => out.push_str(&value),
+            None => {
+                out.push('%');
+                out.push(placeholder);
+            }
+        }
+    }
+    out
+}
+
+/// Draws `log --graph`: one column per line of history that is still
+/// waiting for its next commit, each two characters wide.
+#[derive(Default)]
+struct Graph {
+    columns: Vec<ObjectId>,
+}
+
+/// The graph prefixes for one commit, all padded to the same width.
+struct GraphStep {
+    /// For a separator line before the commit.
+    before: String,
+    /// The commit's own row marked `*`, then rows that fork columns for its
+    /// other parents or join columns that now wait for the same commit.
+    rows: Vec<String>,
+    /// For any further lines of the commit's output.
+    after: String,
+}
+
+impl Graph {
+    fn next(&mut self, oid: ObjectId, parents: &[ObjectId]) -> GraphStep {
+        let before = Graph::row(self.columns.len(), |_| '|');
+        let index = match self.columns.iter().position(|&c| c == oid) {
+            Some(index) => index,
+            None => {
+                self.columns.push(oid);
+                self.columns.len() - 1
+            }
+        };
+        let mut width = self.columns.len();
+        let mut rows = vec![Graph::row(self.columns.len(), |i| {
+            if i == index {
+                '*'
+            } else {
+                '|'
+            }
+        })];
+
+        let new_parents: Vec<ObjectId> = parents
+            .iter()
+            .skip(1)
+            .filter(|parent| !self.columns.contains(parent))
+            .copied()
+            .collect();
+        match parents.first() {
+            None => {
+                // a root commit ends its column; later ones move left
+                self.columns.remove(index);
+                if index < self.columns.len() {
+                    rows.push(Graph::shift_row(self.columns.len() + 1, index, false));
+                }
+            }
+            Some(&first) => {
+                self.columns[index] = first;
+                if !new_parents.is_empty() {
+                    // `|\` at the merge, and the columns right of it move over
+                    let mut row = Graph::row(self.columns.len(), |_| '|').into_bytes();
+                    row[index * 2 + 1] = b'\\';
+                    for i in index + 1..self.columns.len() {
+                        row[i * 2] = b' ';
+                        row[i * 2 + 1] = b'\\';
+                    }
+                    rows.push(String::from_utf8(row).expect("graph rows are ASCII"));
+                    for (offset, parent) in new_parents.into_iter().enumerate() {
+                        self.columns.insert(index + 1 + offset, parent);
+                    }
+                    width = width.max(self.columns.len());
+                }
+            }
+        }
+
+        // columns that now wait for the same commit join up
+        while let Some(duplicate) =
+            (1..self.columns.len()).find(|&i| self.columns[..i].contains(&self.columns[i]))
+        {
+            rows.push(Graph::shift_row(self.columns.len(), duplicate, true));
+            self.columns.remove(duplicate);
+        }
+
+        let pad = |row: String| format!("{:<width$}", row, width = width * 2);
+        GraphStep {
+            before: pad(before),
+            rows: rows.into_iter().map(pad).collect(),
+            after: pad(Graph::row(self.columns.len(), |_| '|')),
+        }
+    }
+
+    fn row(columns: usize, cell: impl Fn(usize) -> char) -> String {
+        (0..columns).map(|i| format!("{} ", cell(i))).collect()
+    }
+
+    /// A row where the column at `removed` goes away and every column after
+    /// it moves one place to the left. With `joins`, the removed column is
+    /// drawn joining the one to its left.
+    fn shift_row(columns: usize, removed: usize, joins: bool) -> String {
+        let mut row = vec![b' '; columns * 2];
+        for i in 0..columns {
+            if i < removed {
+                row[i * 2] = b'|';
+            } else if i > removed || joins {
+                row[i * 2 - 1] = b'/';
+            }
+        }
+        String::from_utf8(row).expect("graph rows are ASCII")
+    }
+}
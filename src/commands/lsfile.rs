@@ -1,12 +1,28 @@
 use anyhow::Result;
 use crate::repository::Index;
 
-pub fn index_read() -> Result<()> {
+/// Lists the paths in the index. With `stage`, each entry is shown as
+/// `<mode> <object> <stage>\t<path>`; `unmerged` shows only the entries of
+/// paths with conflicts, in that format.
+pub fn index_read(stage: bool, unmerged: bool) -> Result<()> {
     let index = Index::default();
     let index = index.read()?;
 
     for entry in &index.entries {
-        println!("{}", entry.path);
+        if unmerged && entry.stage() == 0 {
+            continue;
+        }
+        if stage || unmerged {
+            println!(
+                "{:06o} {} {}\t{}",
+                entry.mode,
+                entry.sha1,
+                entry.stage(),
+                entry.path
+            );
+        } else {
+            println!("{}", entry.path);
+        }
     }
 
     Ok(())
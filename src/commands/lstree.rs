@@ -1,42 +1,75 @@
-use anyhow::Context;
-use flate2::read::ZlibDecoder;
-use std::{
-    ffi::CStr,
-    fs,
-    io::{BufRead, BufReader, Read, Write},
-};
+use crate::repository::{resolve_revision, Object, ObjectDatabase};
+use crate::Tree;
 
-use crate::get_full_path_from_hash;
+/// Output options for `ls-tree`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LsTreeOptions {
+    /// Recurse into subtrees.
+    pub recursive: bool,
+    /// Show tree entries even when recursing into them.
+    pub show_trees: bool,
+    /// Print only the path of each entry.
+    pub name_only: bool,
+    /// Include the size of blob entries.
+    pub long: bool,
+}
+
+pub fn ls_tree(object_hash: String, options: LsTreeOptions) -> anyhow::Result<()> {
+    let odb = ObjectDatabase::default();
 
-pub fn ls_tree (object_hash: String) -> anyhow::Result<()> {
-    let path = get_full_path_from_hash(&object_hash)?;
-    let mut parts = path.split('/');
+    // a commit is peeled to the tree it records
+    let oid = resolve_revision(&odb, &object_hash)?;
+    let tree = match odb.read(&oid)? {
+        Object::Tree(tree) => tree,
+        Object::Commit(commit) => odb.read_tree(&commit.tree)?,
+        object => anyhow::bail!("not a tree object: {} is a {}", object_hash, object.kind()),
+    };
+
+    list_tree(&odb, &tree, "", options)
+}
 
-    let hash = parts.next().expect("hash is missing");
-    let file = parts.next().expect("file is missing");
+fn list_tree(
+    odb: &ObjectDatabase,
+    tree: &Tree,
+    prefix: &str,
+    options: LsTreeOptions,
+) -> anyhow::Result<()> {
+    for entry in &tree.entries {
+        let path = format!("{}{}", prefix, entry.name);
+        let recurse = options.recursive && entry.is_tree();
 
-    let path = format!("./ugit/objects/{}/{}", hash, file);
+        if !recurse || options.show_trees {
+            if options.name_only {
+                println!("{}", path);
+            } else if options.long {
+                let size = if entry.kind() == "blob" {
+                    odb.read_header(&entry.hash)?.1.to_string()
+                } else {
+                    "-".to_string()
+                };
+                println!(
+                    "{:06o} {} {} {:>7}\t{}",
+                    entry.mode,
+                    entry.kind(),
+                    entry.hash,
+                    size,
+                    path
+                );
+            } else {
+                println!(
+                    "{:06o} {} {}\t{}",
+                    entry.mode,
+                    entry.kind(),
+                    entry.hash,
+                    path
+                );
+            }
+        }
 
-    let f = fs::File::open(path).context("couldn't open ugit/objects file")?;
-    let z = ZlibDecoder::new(f);
-    let mut z = BufReader::new(z);
-    let mut buf = Vec::new();
-    z.read_until(0, &mut buf)
-        .context("read header from ugit/objects")?;
-    let header = CStr::from_bytes_with_nul(&buf).expect("there is one null at the end.");
-    let header = header
-        .to_str()
-        .context("ugit/objects file header isn't valid UTF-8")?;
-    if header.starts_with("tree"){
-        let Some(size) = header.strip_prefix("tree ") else {
-            anyhow::bail!("ugit/object header didn't start with 'tree ': '{header}'")
-        };
-        let size: usize = size.parse().context("couldn't parse size")?;
-        // println!("size: {}", size);
-        let mut content = Vec::with_capacity(size);
-        z.read_to_end(&mut content)
-            .context("read content from ugit/objects")?;
-        std::io::stdout().write_all(&content).context("write content to stdout")?;
+        if recurse {
+            let subtree = odb.read_tree(&entry.hash)?;
+            list_tree(odb, &subtree, &format!("{}/", path), options)?;
+        }
     }
-        Ok(())
+    Ok(())
 }
@@ -0,0 +1,327 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+
+use crate::repository::{
+    check_path, checkout_file, detect_renames, diff_files, diff_trees, flatten_tree, is_ancestor,
+    merge_commits, peel, remove_file, resolve_revision, Config, ConflictStyle,
+    ObjectDatabase, ObjectId, ObjectKind, RefStore, RenameOptions, TreeMerge,
+};
+use crate::Index;
+
+use super::branch::current_branch;
+use super::checkout::{expand_previous, update_worktree};
+use super::commit::{self, create_commit};
+use super::diff::{print_diff, write_summary, Blobs, DiffFormat, DiffOptions};
+use super::writetree::write_tree_from_index;
+
+const MERGE_HEAD: &str = "./ugit/MERGE_HEAD";
+pub const MERGE_MSG: &str = "./ugit/MERGE_MSG";
+pub const ORIG_HEAD: &str = "./ugit/ORIG_HEAD";
+
+pub struct MergeOptions {
+    /// Create a merge commit even when the branch could fast-forward.
+    pub no_ff: bool,
+    /// Refuse to do anything but fast-forward.
+    pub ff_only: bool,
+    /// Leave a clean merge uncommitted, as if it had conflicts.
+    pub no_commit: bool,
+    pub message: Option<String>,
+    /// `merge` or `diff3`, overriding `merge.conflictStyle`.
+    pub conflict_style: Option<String>,
+    /// Throw away a conflicted merge.
+    pub abort: bool,
+    /// Commit a merge whose conflicts have been resolved.
+    pub continue_merge: bool,
+}
+
+/// Merges `commit` into the current branch: a fast-forward when HEAD is
+/// behind it, otherwise a three-way merge that is committed with both as
+/// parents. Conflicts are left in the work tree and index, with `MERGE_HEAD`
+/// and `MERGE_MSG` recording the merge for `commit` to finish.
+pub fn merge(options: MergeOptions, commit: Option<String>) -> Result<()> {
+    if options.abort {
+        return abort();
+    }
+    if options.continue_merge {
+        anyhow::ensure!(
+            merge_in_progress()?.is_some(),
+            "There is no merge in progress (MERGE_HEAD missing)."
+        );
+        return commit::commit(Vec::new());
+    }
+    anyhow::ensure!(
+        merge_in_progress()?.is_none(),
+        "You have not concluded your merge (MERGE_HEAD exists).\n\
+         Please, commit your changes before you merge."
+    );
+
+    let odb = ObjectDatabase::default();
+    let refs = RefStore::default();
+    let name = expand_previous(&commit.context("No commit specified")?)?;
+    let theirs = resolve_revision(&odb, &name)
+        .and_then(|oid| peel(&odb, oid, Some(ObjectKind::Commit)))
+        .with_context(|| format!("{} - not something we can merge", name))?;
+    let style = conflict_style(options.conflict_style.as_deref())?;
+
+    let Some(head) = refs.resolve("HEAD")? else {
+        // an unborn branch simply takes over the merged history
+        return fast_forward(&odb, &refs, None, theirs, &name);
+    };
+    if is_ancestor(&odb, theirs, head)? {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if !options.no_ff && is_ancestor(&odb, head, theirs)? {
+        return fast_forward(&odb, &refs, Some(head), theirs, &name);
+    }
+    anyhow::ensure!(!options.ff_only, "Not possible to fast-forward, aborting.");
+
+    // the merge is worked out in the index, so it mustn't hold anything new
+    let mut index = Index::default().read()?;
+    let head_tree = odb.read_commit(&head)?.tree;
+    let ours = flatten_tree(&odb, &head_tree)?;
+    let staged = index
+        .entries
+        .iter()
+        .map(|entry| (entry.path.clone(), (entry.mode, entry.sha1)))
+        .collect();
+    let changed: Vec<String> = diff_files(&ours, &staged)
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    anyhow::ensure!(
+        changed.is_empty(),
+        "Your local changes to the following files would be overwritten by merge:\n\t{}\n\
+         Please commit your changes or stash them before you merge.\nAborting",
+        changed.join("\n\t")
+    );
+
+    let merged = merge_commits(&odb, head, theirs, "HEAD", &name, style)?;
+    checkout_merge(&odb, &mut index, &ours, &merged, "merge")?;
+    for message in &merged.messages {
+        println!("{}", message);
+    }
+
+    fs::write(ORIG_HEAD, format!("{}\n", head)).context("couldn't write ORIG_HEAD")?;
+    let mut message = match options.message {
+        Some(message) => message,
+        None => merge_message(&refs, &name)?,
+    };
+    message.push('\n');
+    if !merged.conflicts.is_empty() {
+        message.push_str("\n# Conflicts:\n");
+        for conflict in &merged.conflicts {
+            message.push_str(&format!("#\t{}\n", conflict.path));
+        }
+        start_merge(theirs, &message)?;
+        anyhow::bail!("Automatic merge failed; fix conflicts and then commit the result.");
+    }
+    if options.no_commit {
+        start_merge(theirs, &message)?;
+        println!("Automatic merge went well; stopped before committing as requested");
+        return Ok(());
+    }
+
+    let tree = write_tree_from_index(&index)?;
+    let merge_commit = create_commit(tree, vec![head, theirs], message, None)?;
+    refs.update(
+        "HEAD",
+        merge_commit,
+        Some(head),
+        true,
+        &format!("merge {}: Merge made by the 'ort' strategy.", name),
+    )?;
+    println!("Merge made by the 'ort' strategy.");
+    print_changes(&odb, Some(head_tree), tree)
+}
+
+/// The conflict style asked for, or else `merge.conflictStyle`.
+pub fn conflict_style(option: Option<&str>) -> Result<ConflictStyle> {
+    let config = Config::load()?;
+    match option.or(config.get("merge", "conflictstyle")) {
+        Some(style) => ConflictStyle::parse(style),
+        None => Ok(ConflictStyle::default()),
+    }
+}
+
+/// Brings the work tree and index from `current` to the result of a tree
+/// merge, staging its conflicts.
+pub fn checkout_merge(
+    odb: &ObjectDatabase,
+    index: &mut Index,
+    current: &BTreeMap<String, (u32, ObjectId)>,
+    merged: &TreeMerge,
+    operation: &str,
+) -> Result<()> {
+    update_worktree(odb, index, current, &merged.files, false, operation)?;
+    for conflict in &merged.conflicts {
+        index.add_conflict(conflict);
+    }
+    index
+        .write()
+        .context("Failed to write updated index file")
+}
+
+/// Moves HEAD forward to `theirs`, checking out the difference.
+fn fast_forward(
+    odb: &ObjectDatabase,
+    refs: &RefStore,
+    head: Option<ObjectId>,
+    theirs: ObjectId,
+    name: &str,
+) -> Result<()> {
+    let old_tree = match head {
+        Some(head) => {
+            println!("Updating {}..{}", head.short(), theirs.short());
+            Some(odb.read_commit(&head)?.tree)
+        }
+        None => None,
+    };
+    let current = match old_tree {
+        Some(tree) => flatten_tree(odb, &tree)?,
+        None => BTreeMap::new(),
+    };
+    let new_tree = odb.read_commit(&theirs)?.tree;
+    let target = flatten_tree(odb, &new_tree)?;
+
+    let mut index = Index::default().read()?;
+    update_worktree(odb, &mut index, &current, &target, false, "merge")?;
+    index
+        .write()
+        .context("Failed to write updated index file")?;
+    if let Some(head) = head {
+        fs::write(ORIG_HEAD, format!("{}\n", head)).context("couldn't write ORIG_HEAD")?;
+    }
+    refs.update(
+        "HEAD",
+        theirs,
+        Some(head.unwrap_or(ObjectId::NULL)),
+        true,
+        &format!("merge {}: Fast-forward", name),
+    )?;
+    println!("Fast-forward");
+    print_changes(odb, old_tree, new_tree)
+}
+
+/// The default message for merging `name`, e.g. `Merge branch 'topic'`.
+/// Like git, it names the branch merged into unless that's main or master.
+fn merge_message(refs: &RefStore, name: &str) -> Result<String> {
+    let what = match refs.resolve(&format!("refs/heads/{}", name))? {
+        Some(_) => "branch",
+        None => "commit",
+    };
+    let into = match current_branch(refs)? {
+        Some(branch) if branch != "main" && branch != "master" => format!(" into {}", branch),
+        _ => String::new(),
+    };
+    Ok(format!("Merge {} '{}'{}", what, name, into))
+}
+
+/// The diffstat and summary git shows after a merge.
+fn print_changes(odb: &ObjectDatabase, old: Option<ObjectId>, new: ObjectId) -> Result<()> {
+    let renames = RenameOptions {
+        renames: Some(50),
+        copies: None,
+    };
+    let entries = diff_trees(odb, old, Some(new), true)?;
+    let entries = detect_renames(entries, renames, |oid| Ok(odb.read_raw(oid)?.1))?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let options = DiffOptions {
+        format: DiffFormat::Stat,
+        renames,
+        ..DiffOptions::default()
+    };
+    let mut out = io::stdout().lock();
+    print_diff(&mut out, &Blobs::new(odb), &entries, options)?;
+    write_summary(&mut out, &entries)
+}
+
+fn start_merge(theirs: ObjectId, message: &str) -> Result<()> {
+    fs::write(MERGE_HEAD, format!("{}\n", theirs)).context("couldn't write MERGE_HEAD")?;
+    fs::write(MERGE_MSG, message).context("couldn't write MERGE_MSG")
+}
+
+/// The commit being merged and the message prepared for the merge, if a
+/// merge is waiting to be committed.
+pub fn merge_in_progress() -> Result<Option<(ObjectId, String)>> {
+    let merge_head = match fs::read_to_string(MERGE_HEAD) {
+        Ok(contents) => ObjectId::from_hex(contents.trim())?,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("couldn't read MERGE_HEAD"),
+    };
+    let message = fs::read_to_string(MERGE_MSG).unwrap_or_default();
+    let message: Vec<&str> = message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    Ok(Some((
+        merge_head,
+        message.join("\n").trim_end().to_string(),
+    )))
+}
+
+/// Forgets the merge in progress, once it's committed or aborted.
+pub fn finish_merge() -> Result<()> {
+    for file in [MERGE_HEAD, MERGE_MSG] {
+        match fs::remove_file(file) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("couldn't remove {}", file))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `merge --abort`: puts every path the merge changed back the way HEAD has
+/// it, in the index and the work tree.
+fn abort() -> Result<()> {
+    anyhow::ensure!(
+        merge_in_progress()?.is_some(),
+        "There is no merge to abort (MERGE_HEAD missing)."
+    );
+    let odb = ObjectDatabase::default();
+    let head = RefStore::default()
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let files = flatten_tree(&odb, &odb.read_commit(&head)?.tree)?;
+
+    let mut index = Index::default().read()?;
+    let mut touched: BTreeSet<String> = index
+        .entries
+        .iter()
+        .filter(|entry| {
+            entry.stage() != 0 || files.get(&entry.path) != Some(&(entry.mode, entry.sha1))
+        })
+        .map(|entry| entry.path.clone())
+        .collect();
+    touched.extend(
+        files
+            .keys()
+            .filter(|path| !index.entries.iter().any(|entry| &entry.path == *path))
+            .cloned(),
+    );
+    for path in &touched {
+        check_path(path)?;
+    }
+    for path in &touched {
+        match files.get(path) {
+            Some((mode, oid)) => {
+                let entry = checkout_file(&odb, path, *mode, oid)?;
+                index.add_entries(vec![entry]);
+            }
+            None => {
+                remove_file(path)?;
+                index.remove_entry(path);
+            }
+        }
+    }
+    index
+        .write()
+        .context("Failed to write updated index file")?;
+    finish_merge()
+}
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+
+use crate::repository::{
+    is_ancestor, merge_bases, peel, resolve_revision, ObjectDatabase, ObjectId, ObjectKind,
+};
+
+/// Prints the best common ancestor of the first commit and the others (all
+/// of them with `all`), or with `is_ancestor` answers whether the first of
+/// two commits is an ancestor of the second. Like git, "no" is an exit
+/// status of 1 without output.
+pub fn merge_base(all: bool, is_ancestor_only: bool, commits: Vec<String>) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let commits = commits
+        .iter()
+        .map(|rev| {
+            let oid = resolve_revision(&odb, rev)?;
+            peel(&odb, oid, Some(ObjectKind::Commit))
+                .with_context(|| format!("Not a valid commit name {}", rev))
+        })
+        .collect::<Result<Vec<ObjectId>>>()?;
+
+    if is_ancestor_only {
+        anyhow::ensure!(
+            commits.len() == 2,
+            "--is-ancestor takes exactly two commits"
+        );
+        if !is_ancestor(&odb, commits[0], commits[1])? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let bases = merge_bases(&odb, commits[0], &commits[1..])?;
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    let shown = if all { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{}", base);
+    }
+    Ok(())
+}
@@ -1,17 +1,59 @@
+mod branch;
 mod catfile;
+mod checkout;
+mod cherrypick;
+mod commit;
+mod committree;
+mod diff;
+mod difftree;
+mod fsck;
 mod hashobject;
+mod log;
+mod merge;
+mod mergebase;
+mod rebase;
+mod repack;
 mod lstree;
 mod writetree;
 mod add;
+mod revparse;
 mod rm;
+mod showref;
+mod status;
+mod switch;
+mod symbolicref;
+mod tag;
+mod updateref;
 mod init;
 mod lsfile;
+mod packobjects;
 
 pub use lsfile::index_read;
 pub use add::add_to_index;
+pub use branch::{branch, BranchOptions};
 pub use catfile::cat_file;
+pub use checkout::checkout;
+pub use cherrypick::{cherry_pick, revert, PickOptions};
+pub use commit::commit;
+pub use committree::commit_tree;
+pub use diff::{diff, DiffFormat, DiffOptions};
+pub use difftree::diff_tree;
+pub use fsck::fsck;
 pub use hashobject::hash_objects;
-pub use lstree::ls_tree;
+pub use log::{log, LogOptions};
+pub use merge::{merge, MergeOptions};
+pub use mergebase::merge_base;
+pub use rebase::{rebase, RebaseOptions};
+pub use repack::{gc, repack, RepackOptions};
+pub use packobjects::pack_objects;
+pub use lstree::{ls_tree, LsTreeOptions};
 pub use writetree::write_tree;
+pub use revparse::rev_parse;
 pub use rm::rm;
+pub use showref::show_ref;
+pub use status::status;
+pub use switch::switch;
+pub use symbolicref::symbolic_ref;
+pub use tag::{tag, TagOptions};
+pub use updateref::update_ref;
 pub use init::{init_git, clear_git};
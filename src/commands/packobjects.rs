@@ -0,0 +1,28 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::repository::{write_pack, ObjectDatabase, ObjectId, PackObject, PackOptions};
+
+/// Packs the objects listed on stdin, one `<oid> [<path>]` per line as
+/// `rev-list --objects` prints them, into `<base_name>-<checksum>.pack` and
+/// `.idx`, and prints the checksum.
+pub fn pack_objects(options: PackOptions, base_name: String) -> Result<()> {
+    let mut objects = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line.context("couldn't read object list from stdin")?;
+        let (hex, path) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+        if hex.is_empty() {
+            continue;
+        }
+        objects.push(PackObject {
+            oid: ObjectId::from_hex(hex).with_context(|| format!("expected object ID, got garbage:\n {}", line))?,
+            path: path.to_string(),
+        });
+    }
+
+    let odb = ObjectDatabase::default();
+    let checksum = write_pack(&odb, &objects, options, Path::new(&base_name))?;
+    println!("{}", checksum);
+    Ok(())
+}
@@ -0,0 +1,710 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::ErrorKind;
+use std::process::Command;
+
+use crate::repository::{
+    flatten_tree, is_ancestor, peel, resolve_revision, Commit, Config, ObjectDatabase, ObjectId,
+    ObjectKind, RefStore, RevWalk, Sort, Status,
+};
+use crate::Index;
+
+use super::branch::{current_branch, upstream_of};
+use super::checkout::{reset_hard, update_worktree};
+use super::cherrypick::merge_changes;
+use super::commit::{cleanup_message, create_commit, edit_message, launch_editor};
+use super::merge::{checkout_merge, ORIG_HEAD};
+use super::writetree::write_tree_from_index;
+
+const REBASE_DIR: &str = "./ugit/rebase-merge";
+
+pub struct RebaseOptions {
+    /// Let the user edit the list of commits to replay.
+    pub interactive: bool,
+    /// Carry on after conflicts are resolved or a commit has been edited.
+    pub continue_rebase: bool,
+    /// Carry on without the commit the rebase stopped at.
+    pub skip: bool,
+    /// Give up, putting the branch back the way it was.
+    pub abort: bool,
+}
+
+/// What a line of the todo list does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Pick,
+    /// Pick, then edit the commit message.
+    Reword,
+    /// Pick, then stop so the commit can be amended.
+    Edit,
+    /// Meld into the previous commit, combining the messages.
+    Squash,
+    /// Meld into the previous commit, keeping its message.
+    Fixup,
+    Drop,
+    /// Run a shell command.
+    Exec,
+}
+
+impl Action {
+    fn parse(word: &str) -> Option<Action> {
+        Some(match word {
+            "p" | "pick" => Action::Pick,
+            "r" | "reword" => Action::Reword,
+            "e" | "edit" => Action::Edit,
+            "s" | "squash" => Action::Squash,
+            "f" | "fixup" => Action::Fixup,
+            "d" | "drop" => Action::Drop,
+            "x" | "exec" => Action::Exec,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Reword => "reword",
+            Action::Edit => "edit",
+            Action::Squash => "squash",
+            Action::Fixup => "fixup",
+            Action::Drop => "drop",
+            Action::Exec => "exec",
+        }
+    }
+}
+
+/// A line of the todo list.
+#[derive(Clone)]
+struct Step {
+    action: Action,
+    /// The commit acted on; `exec` has none.
+    commit: Option<ObjectId>,
+    /// The commit's subject, or the command `exec` runs.
+    text: String,
+}
+
+impl Step {
+    /// Parses `<action> <commit> [<subject>]` or `exec <command>`.
+    fn parse(odb: &ObjectDatabase, line: &str) -> Result<Step> {
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let action = Action::parse(word).with_context(|| format!("invalid command '{}'", word))?;
+        let rest = rest.trim();
+        if action == Action::Exec {
+            anyhow::ensure!(!rest.is_empty(), "missing command after 'exec'");
+            return Ok(Step {
+                action,
+                commit: None,
+                text: rest.to_string(),
+            });
+        }
+        let (rev, subject) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let commit = resolve_revision(odb, rev)
+            .and_then(|oid| peel(odb, oid, Some(ObjectKind::Commit)))
+            .with_context(|| format!("could not parse '{}'", rev))?;
+        Ok(Step {
+            action,
+            commit: Some(commit),
+            text: subject.trim().to_string(),
+        })
+    }
+
+    /// The line for the todo list, naming the commit by its full id or,
+    /// for the user to read, abbreviated.
+    fn to_line(&self, short: bool) -> String {
+        match self.commit {
+            Some(oid) if short => format!("{} {} {}", self.action.name(), oid.short(), self.text),
+            Some(oid) => format!("{} {} {}", self.action.name(), oid, self.text),
+            None => format!("{} {}", self.action.name(), self.text),
+        }
+    }
+}
+
+/// Parses a todo list, skipping blank lines and `#` comments.
+fn parse_todo(odb: &ObjectDatabase, text: &str) -> Result<Vec<Step>> {
+    text.lines()
+        .enumerate()
+        .map(|(n, line)| (n, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            Step::parse(odb, line).with_context(|| format!("invalid line {}: {}", n + 1, line))
+        })
+        .collect()
+}
+
+/// A rebase in progress, kept in `ugit/rebase-merge` so that it survives
+/// stopping for conflicts or edits.
+struct Rebase {
+    /// The branch being rebased (`refs/heads/<name>`), or `detached HEAD`.
+    head_name: String,
+    onto: ObjectId,
+    orig_head: ObjectId,
+    todo: Vec<Step>,
+    done: Vec<Step>,
+}
+
+impl Rebase {
+    fn load(odb: &ObjectDatabase) -> Result<Option<Rebase>> {
+        let Some(head_name) = read_state("head-name")? else {
+            return Ok(None);
+        };
+        Ok(Some(Rebase {
+            head_name: head_name.trim().to_string(),
+            onto: read_state_oid("onto")?,
+            orig_head: read_state_oid("orig-head")?,
+            todo: parse_todo(odb, &read_state("git-rebase-todo")?.unwrap_or_default())?,
+            done: parse_todo(odb, &read_state("done")?.unwrap_or_default())?,
+        }))
+    }
+
+    fn save(&self) -> Result<()> {
+        let lines = |steps: &[Step]| -> String {
+            steps
+                .iter()
+                .map(|step| step.to_line(false) + "\n")
+                .collect()
+        };
+        write_state("head-name", &format!("{}\n", self.head_name))?;
+        write_state("onto", &format!("{}\n", self.onto))?;
+        write_state("orig-head", &format!("{}\n", self.orig_head))?;
+        write_state("git-rebase-todo", &lines(&self.todo))?;
+        write_state("done", &lines(&self.done))
+    }
+}
+
+fn read_state(name: &str) -> Result<Option<String>> {
+    match fs::read_to_string(format!("{}/{}", REBASE_DIR, name)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("couldn't read rebase state '{}'", name)),
+    }
+}
+
+fn read_state_oid(name: &str) -> Result<ObjectId> {
+    let contents = read_state(name)?
+        .with_context(|| format!("corrupt rebase state: '{}' is missing", name))?;
+    ObjectId::from_hex(contents.trim())
+}
+
+fn write_state(name: &str, contents: &str) -> Result<()> {
+    fs::write(format!("{}/{}", REBASE_DIR, name), contents)
+        .with_context(|| format!("couldn't write rebase state '{}'", name))
+}
+
+fn remove_state(name: &str) -> Result<()> {
+    match fs::remove_file(format!("{}/{}", REBASE_DIR, name)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("couldn't remove rebase state '{}'", name))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Replays the current branch's commits that aren't in `upstream` on top of
+/// it, one three-way merge at a time, then moves the branch to the result.
+/// With `interactive`, the list of commits is edited first and may reorder,
+/// reword, edit, squash or drop them, or run commands in between.
+pub fn rebase(options: RebaseOptions, upstream: Option<String>) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let state = Rebase::load(&odb)?;
+    if options.abort || options.skip || options.continue_rebase {
+        let state = state.context("No rebase in progress?")?;
+        if options.abort {
+            return abort(&odb, state);
+        }
+        if options.skip {
+            let head = RefStore::default()
+                .resolve("HEAD")?
+                .context("HEAD does not point at a commit")?;
+            reset_hard(&odb, head)?;
+            remove_state("stopped-sha")?;
+            remove_state("amend")?;
+        } else {
+            resume(&odb, &state)?;
+        }
+        return run(&odb, state);
+    }
+    anyhow::ensure!(
+        state.is_none(),
+        "It seems that there is already a rebase-merge directory, and\n\
+         I wonder if you are in the middle of another rebase. If that is the\n\
+         case, please try\n\tugit rebase (--continue | --abort | --skip)\n\
+         If that is not the case, please\n\trm -fr \"ugit/rebase-merge\"\n\
+         and run me again."
+    );
+    start(&odb, options.interactive, upstream)
+}
+
+fn start(odb: &ObjectDatabase, interactive: bool, upstream: Option<String>) -> Result<()> {
+    let refs = RefStore::default();
+    let branch = current_branch(&refs)?;
+    let upstream = match upstream {
+        Some(upstream) => upstream,
+        None => {
+            let config = Config::load()?;
+            branch
+                .as_deref()
+                .and_then(|branch| upstream_of(&config, branch))
+                .context(
+                    "There is no tracking information for the current branch.\n\
+                     Please specify which branch you want to rebase against.",
+                )?
+        }
+    };
+    let onto = resolve_revision(odb, &upstream)
+        .and_then(|oid| peel(odb, oid, Some(ObjectKind::Commit)))
+        .with_context(|| format!("invalid upstream '{}'", upstream))?;
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+
+    let head_files = flatten_tree(odb, &odb.read_commit(&head)?.tree)?;
+    let mut index = Index::default().read()?;
+    let status = Status::collect(&head_files, &index)?;
+    anyhow::ensure!(
+        status.unstaged.is_empty() && status.unmerged.is_empty(),
+        "cannot rebase: You have unstaged changes.\nPlease commit or stash them."
+    );
+    anyhow::ensure!(
+        status.staged.is_empty(),
+        "cannot rebase: Your index contains uncommitted changes.\nPlease commit or stash them."
+    );
+
+    if !interactive && is_ancestor(odb, onto, head)? {
+        println!(
+            "Current branch {} is up to date.",
+            branch.as_deref().unwrap_or("HEAD")
+        );
+        return Ok(());
+    }
+
+    // the commits to replay, oldest first; merges are flattened away
+    let mut walk = RevWalk::new(odb);
+    walk.sort = Sort::Topo;
+    walk.push(head);
+    walk.hide(onto);
+    let mut todo: Vec<Step> = walk
+        .run()?
+        .into_iter()
+        .rev()
+        .filter(|walked| walked.commit.parent_commits.len() <= 1)
+        .map(|walked| Step {
+            action: Action::Pick,
+            commit: Some(walked.oid),
+            text: walked.commit.subject().to_string(),
+        })
+        .collect();
+
+    fs::create_dir_all(REBASE_DIR).context("couldn't create ugit/rebase-merge")?;
+    if interactive {
+        todo = match edit_todo(odb, &todo, onto, head) {
+            Ok(todo) if !todo.is_empty() => todo,
+            Ok(_) => {
+                fs::remove_dir_all(REBASE_DIR)?;
+                anyhow::bail!("nothing to do");
+            }
+            Err(err) => {
+                fs::remove_dir_all(REBASE_DIR)?;
+                return Err(err);
+            }
+        };
+    }
+    let state = Rebase {
+        head_name: match &branch {
+            Some(branch) => format!("refs/heads/{}", branch),
+            None => "detached HEAD".to_string(),
+        },
+        onto,
+        orig_head: head,
+        todo,
+        done: Vec::new(),
+    };
+    state.save()?;
+    fs::write(ORIG_HEAD, format!("{}\n", head)).context("couldn't write ORIG_HEAD")?;
+
+    // replay on a detached HEAD; the branch only moves once it's all done
+    let onto_files = flatten_tree(odb, &odb.read_commit(&onto)?.tree)?;
+    if let Err(err) = update_worktree(odb, &mut index, &head_files, &onto_files, false, "rebase") {
+        fs::remove_dir_all(REBASE_DIR)?;
+        return Err(err);
+    }
+    index
+        .write()
+        .context("Failed to write updated index file")?;
+    refs.update(
+        "HEAD",
+        onto,
+        None,
+        false,
+        &format!("rebase (start): checkout {}", upstream),
+    )?;
+    run(odb, state)
+}
+
+/// Has the user edit the todo list in `git-rebase-todo` and reads it back.
+fn edit_todo(
+    odb: &ObjectDatabase,
+    todo: &[Step],
+    onto: ObjectId,
+    head: ObjectId,
+) -> Result<Vec<Step>> {
+    let mut text: String = todo.iter().map(|step| step.to_line(true) + "\n").collect();
+    text.push_str(&format!(
+        "\n# Rebase {}..{} onto {} ({} commands)\n\
+         #\n\
+         # Commands:\n\
+         # p, pick <commit> = use commit\n\
+         # r, reword <commit> = use commit, but edit the commit message\n\
+         # e, edit <commit> = use commit, but stop for amending\n\
+         # s, squash <commit> = use commit, but meld into previous commit\n\
+         # f, fixup <commit> = like \"squash\" but keep only the previous\n\
+         #                    commit's log message\n\
+         # x, exec <command> = run command (the rest of the line) using shell\n\
+         # d, drop <commit> = remove commit\n\
+         #\n\
+         # These lines can be re-ordered; they are executed from top to bottom.\n\
+         #\n\
+         # If you remove a line here THAT COMMIT WILL BE LOST.\n\
+         #\n\
+         # However, if you remove everything, the rebase will be aborted.\n\
+         #\n",
+        onto.short(),
+        head.short(),
+        onto.short(),
+        todo.len()
+    ));
+    let path = format!("{}/git-rebase-todo", REBASE_DIR);
+    fs::write(&path, text).context("couldn't write the todo list")?;
+    launch_editor(&path)?;
+
+    let todo = parse_todo(odb, &fs::read_to_string(&path)?)?;
+    let first = todo
+        .iter()
+        .find(|step| !matches!(step.action, Action::Exec | Action::Drop));
+    if let Some(step) = first.filter(|step| matches!(step.action, Action::Squash | Action::Fixup)) {
+        anyhow::bail!("cannot '{}' without a previous commit", step.action.name());
+    }
+    Ok(todo)
+}
+
+/// Works through the rest of the todo list, saving the state after each
+/// step so the rebase can pick up where it stops.
+fn run(odb: &ObjectDatabase, mut state: Rebase) -> Result<()> {
+    let refs = RefStore::default();
+    loop {
+        let next = state.todo.first().map(|step| step.action);
+        if !matches!(next, Some(Action::Squash | Action::Fixup)) {
+            finish_squash(odb, &refs)?;
+        }
+        if state.todo.is_empty() {
+            break;
+        }
+        let step = state.todo.remove(0);
+        state.done.push(step.clone());
+        state.save()?;
+
+        match (step.action, step.commit) {
+            (Action::Drop, _) => {}
+            (Action::Exec, _) => exec(&step.text)?,
+            (action, Some(oid)) => {
+                pick(odb, &refs, action, oid)?;
+                if action == Action::Edit {
+                    let head = refs
+                        .resolve("HEAD")?
+                        .context("HEAD does not point at a commit")?;
+                    write_state("amend", &format!("{}\n", head))?;
+                    println!("Stopped at {}...  {}", oid.short(), step.text);
+                    println!(
+                        "You can amend the commit now, by staging your changes.\n\n\
+                         Once you are satisfied with your changes, run\n\n  \
+                         ugit rebase --continue"
+                    );
+                    return Ok(());
+                }
+            }
+            (action, None) => anyhow::bail!("'{}' needs a commit", action.name()),
+        }
+    }
+    finish(&refs, state)
+}
+
+/// Applies the changes `oid` made on top of HEAD and commits them as
+/// `action` says, failing with the conflicts left in the work tree when
+/// they don't apply cleanly.
+fn pick(odb: &ObjectDatabase, refs: &RefStore, action: Action, oid: ObjectId) -> Result<()> {
+    let commit = odb.read_commit(&oid)?;
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let head_commit = odb.read_commit(&head)?;
+    let current = flatten_tree(odb, &head_commit.tree)?;
+    let mut index = Index::default().read()?;
+
+    // a commit that already sits on HEAD is kept as it is
+    if matches!(action, Action::Pick | Action::Edit) && commit.parent_commits == [head] {
+        let target = flatten_tree(odb, &commit.tree)?;
+        update_worktree(odb, &mut index, &current, &target, false, "rebase")?;
+        index
+            .write()
+            .context("Failed to write updated index file")?;
+        return refs.update(
+            "HEAD",
+            oid,
+            Some(head),
+            false,
+            &format!("rebase (pick): {}", commit.subject()),
+        );
+    }
+
+    let parent = commit.parent_commits.first().copied();
+    let merged = merge_changes(odb, head_commit.tree, oid, &commit, parent, false)?;
+    checkout_merge(odb, &mut index, &current, &merged, "rebase")?;
+    write_state("stopped-sha", &format!("{}\n", oid))?;
+    if merged.conflicts.is_empty() {
+        return commit_picked(odb, refs, action, oid, &index, action.name());
+    }
+    // like git, what happened to each path is only worth telling on conflicts
+    for message in &merged.messages {
+        println!("{}", message);
+    }
+    anyhow::bail!(
+        "could not apply {}... {}\n\
+         hint: Resolve all conflicts manually, mark them as resolved with\n\
+         hint: \"ugit add/rm <conflicted_files>\", then run \"ugit rebase --continue\".\n\
+         hint: You can instead skip this commit: run \"ugit rebase --skip\".\n\
+         hint: To abort and get back to the state before \"ugit rebase\", run \"ugit rebase --abort\".",
+        oid.short(),
+        commit.subject()
+    )
+}
+
+/// Commits what picking `oid` left in the index: on top of HEAD, or melded
+/// into it for `squash` and `fixup`. A commit whose changes turn out to be
+/// there already is dropped.
+fn commit_picked(
+    odb: &ObjectDatabase,
+    refs: &RefStore,
+    action: Action,
+    oid: ObjectId,
+    index: &Index,
+    reflog_action: &str,
+) -> Result<()> {
+    let commit = odb.read_commit(&oid)?;
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let head_commit = odb.read_commit(&head)?;
+    let tree = write_tree_from_index(index)?;
+
+    if matches!(action, Action::Squash | Action::Fixup) {
+        squash(odb, refs, action, oid, &commit, tree)?;
+    } else if tree != head_commit.tree {
+        let message = match action {
+            Action::Reword => edit_message(&commit.message)?,
+            _ => commit.message.clone(),
+        };
+        let new = create_commit(tree, vec![head], message, Some(commit.author.clone()))?;
+        refs.update(
+            "HEAD",
+            new,
+            Some(head),
+            false,
+            &format!("rebase ({}): {}", reflog_action, commit.subject()),
+        )?;
+    }
+    remove_state("stopped-sha")
+}
+
+/// Melds `commit`, already applied as `tree`, into HEAD. The messages of
+/// a run of squashes and fixups are collected in `message-squash` for
+/// `finish_squash` to settle.
+fn squash(
+    odb: &ObjectDatabase,
+    refs: &RefStore,
+    action: Action,
+    oid: ObjectId,
+    commit: &Commit,
+    tree: ObjectId,
+) -> Result<()> {
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let head_commit = odb.read_commit(&head)?;
+    let fixups = read_state("current-fixups")?.unwrap_or_default();
+    let message = match read_state("message-squash")? {
+        Some(message) => message,
+        None => format!(
+            "# This is a combination of 1 commits.\n# This is the 1st commit message:\n\n{}",
+            head_commit.message
+        ),
+    };
+    let count = fixups.lines().count() + 2;
+    let (_, rest) = message.split_once('\n').unwrap_or_default();
+    let mut message = format!("# This is a combination of {} commits.\n{}", count, rest);
+    if action == Action::Squash {
+        message.push_str(&format!(
+            "\n# This is the commit message #{}:\n\n{}",
+            count, commit.message
+        ));
+    } else {
+        message.push_str(&format!(
+            "\n# The commit message #{} will be skipped:\n\n",
+            count
+        ));
+        for line in commit.message.lines() {
+            message.push_str(&format!("# {}\n", line));
+        }
+    }
+    write_state("message-squash", &message)?;
+    write_state(
+        "current-fixups",
+        &format!("{}{} {}\n", fixups, action.name(), oid),
+    )?;
+
+    let new = create_commit(
+        tree,
+        head_commit.parent_commits.clone(),
+        cleanup_message(&message),
+        Some(head_commit.author.clone()),
+    )?;
+    refs.update(
+        "HEAD",
+        new,
+        Some(head),
+        false,
+        &format!("rebase ({}): {}", action.name(), head_commit.subject()),
+    )
+}
+
+/// Ends a run of squashes and fixups. If any of them was a squash, the
+/// user gets to edit the combined message.
+fn finish_squash(odb: &ObjectDatabase, refs: &RefStore) -> Result<()> {
+    let Some(fixups) = read_state("current-fixups")? else {
+        return Ok(());
+    };
+    if fixups.lines().any(|line| line.starts_with("squash ")) {
+        let message = read_state("message-squash")?.unwrap_or_default();
+        let message = edit_message(&message)?;
+        let head = refs
+            .resolve("HEAD")?
+            .context("HEAD does not point at a commit")?;
+        let head_commit = odb.read_commit(&head)?;
+        let new = create_commit(
+            head_commit.tree,
+            head_commit.parent_commits.clone(),
+            message,
+            Some(head_commit.author.clone()),
+        )?;
+        refs.update(
+            "HEAD",
+            new,
+            Some(head),
+            false,
+            &format!("rebase (squash): {}", odb.read_commit(&new)?.subject()),
+        )?;
+    }
+    remove_state("current-fixups")?;
+    remove_state("message-squash")
+}
+
+/// Runs an `exec` line's command with the shell.
+fn exec(command: &str) -> Result<()> {
+    println!("Executing: {}", command);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .status()
+        .with_context(|| format!("couldn't run '{}'", command))?;
+    anyhow::ensure!(
+        status.success(),
+        "execution failed: {}\nYou can fix the problem, and then run\n\n  ugit rebase --continue",
+        command
+    );
+    Ok(())
+}
+
+/// `rebase --continue`: commits the resolved conflicts of the commit the
+/// rebase stopped at, or folds what was staged after an `edit` into the
+/// edited commit.
+fn resume(odb: &ObjectDatabase, state: &Rebase) -> Result<()> {
+    let refs = RefStore::default();
+    let index = Index::default().read()?;
+    anyhow::ensure!(
+        index.conflicts().is_empty(),
+        "You must edit all merge conflicts and then\n\
+         mark them as resolved using ugit add"
+    );
+    if let Some(stopped) = read_state("stopped-sha")? {
+        let oid = ObjectId::from_hex(stopped.trim())?;
+        let action = state.done.last().map_or(Action::Pick, |step| step.action);
+        commit_picked(odb, &refs, action, oid, &index, "continue")?;
+    }
+
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    let head_commit = odb.read_commit(&head)?;
+    let tree = write_tree_from_index(&index)?;
+    if tree != head_commit.tree {
+        let amend = match read_state("amend")? {
+            Some(amend) => ObjectId::from_hex(amend.trim())?,
+            None => ObjectId::NULL,
+        };
+        anyhow::ensure!(
+            amend == head,
+            "You have staged changes in your working tree.\n\
+             Commit them first and then run 'ugit rebase --continue' again."
+        );
+        let new = create_commit(
+            tree,
+            head_commit.parent_commits.clone(),
+            head_commit.message.clone(),
+            Some(head_commit.author.clone()),
+        )?;
+        refs.update(
+            "HEAD",
+            new,
+            Some(head),
+            false,
+            &format!("rebase (continue): {}", head_commit.subject()),
+        )?;
+    }
+    remove_state("amend")
+}
+
+/// Points the branch at the rebased commits and checks it out again.
+fn finish(refs: &RefStore, state: Rebase) -> Result<()> {
+    let head = refs
+        .resolve("HEAD")?
+        .context("HEAD does not point at a commit")?;
+    if state.head_name.starts_with("refs/heads/") {
+        refs.update(
+            &state.head_name,
+            head,
+            Some(state.orig_head),
+            false,
+            &format!("rebase (finish): {} onto {}", state.head_name, state.onto),
+        )?;
+        refs.set_symbolic(
+            "HEAD",
+            &state.head_name,
+            &format!("rebase (finish): returning to {}", state.head_name),
+        )?;
+    }
+    fs::remove_dir_all(REBASE_DIR).context("couldn't remove ugit/rebase-merge")?;
+    println!("Successfully rebased and updated {}.", state.head_name);
+    Ok(())
+}
+
+/// `rebase --abort`: goes back to the branch and commit the rebase started
+/// from, throwing away the commits replayed so far.
+fn abort(odb: &ObjectDatabase, state: Rebase) -> Result<()> {
+    let refs = RefStore::default();
+    reset_hard(odb, state.orig_head)?;
+    let message = format!("rebase (abort): returning to {}", state.head_name);
+    if state.head_name.starts_with("refs/heads/") {
+        refs.set_symbolic("HEAD", &state.head_name, &message)?;
+    } else {
+        refs.update("HEAD", state.orig_head, None, false, &message)?;
+    }
+    fs::remove_dir_all(REBASE_DIR).context("couldn't remove ugit/rebase-merge")
+}
@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::repository::{
+    parse_approxidate, reachable_objects, write_pack, Config, ObjectDatabase, ObjectId,
+    PackOptions, RefStore,
+};
+use crate::{find_index, Index};
+
+/// How long unreachable loose objects are kept by default, as git's
+/// `gc.pruneExpire`.
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+pub struct RepackOptions {
+    /// Pack everything reachable into one pack, instead of only the
+    /// reachable objects that aren't packed yet.
+    pub all: bool,
+    /// Remove what the new pack makes redundant: with `all` the old packs,
+    /// and loose objects that are now packed.
+    pub delete: bool,
+    pub pack: PackOptions,
+}
+
+/// Packs reachable objects into a new pack in `objects/pack`.
+pub fn repack(options: RepackOptions) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let (_, packed) = pack_reachable(&odb, &options, None)?;
+    if !packed {
+        println!("Nothing new to pack.");
+    }
+    Ok(())
+}
+
+/// Packs everything reachable into a single pack, then deletes unreachable
+/// loose objects older than `prune` (`gc.pruneExpire`, by default two weeks
+/// ago). Unreachable objects from the old packs are kept loose for the same
+/// grace period, so nothing recently made disappears at once; with
+/// `--no-prune` or `never` they are all kept.
+pub fn gc(prune: Option<String>, no_prune: bool) -> Result<()> {
+    let config = Config::load()?;
+    let prune = prune
+        .as_deref()
+        .or(config.get("gc", "pruneexpire"))
+        .unwrap_or(DEFAULT_PRUNE_EXPIRE);
+    let expire = match (no_prune, prune) {
+        (true, _) | (_, "never") => None,
+        (_, "now") => Some(now()),
+        (_, date) => Some(
+            parse_approxidate(date).with_context(|| format!("invalid prune date '{}'", date))?,
+        ),
+    };
+
+    let options = RepackOptions {
+        all: true,
+        delete: true,
+        pack: PackOptions::default(),
+    };
+    // never expiring keeps every unreachable object in the old packs
+    let unpack_since = expire.unwrap_or(i64::MIN);
+    let (reachable, _) = pack_reachable(&ObjectDatabase::default(), &options, Some(unpack_since))?;
+    let Some(expire) = expire else {
+        return Ok(());
+    };
+
+    let odb = ObjectDatabase::default();
+    for oid in odb.loose_objects()? {
+        let path = odb.object_path(&oid);
+        if !reachable.contains(&oid) && modified(&path)? <= expire {
+            remove_loose(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a pack of the reachable objects, tidying up after it as
+/// `options` says. With `unpack_since`, unreachable objects in old packs
+/// written after that time are kept as loose objects when the packs go.
+/// Returns every reachable object, and whether anything was packed.
+fn pack_reachable(
+    odb: &ObjectDatabase,
+    options: &RepackOptions,
+    unpack_since: Option<i64>,
+) -> Result<(HashSet<ObjectId>, bool)> {
+    let mut objects = reachable_objects(odb, &reachable_tips(odb)?)?;
+    let reachable: HashSet<ObjectId> = objects.iter().map(|object| object.oid).collect();
+    if !options.all {
+        let packed: HashSet<&ObjectId> = odb
+            .packs()?
+            .iter()
+            .flat_map(|pack| pack.index().oids())
+            .collect();
+        objects.retain(|object| !packed.contains(&object.oid));
+    }
+    if objects.is_empty() {
+        return Ok((reachable, false));
+    }
+
+    let name = write_pack(odb, &objects, options.pack, &odb.pack_dir().join("pack"))?;
+    if options.delete && options.all {
+        let keep = format!("pack-{}", name);
+        for pack in odb.packs()? {
+            let path = pack.index().path();
+            if path.file_stem().is_some_and(|stem| *stem == *keep) {
+                continue;
+            }
+            let written = modified(&path.with_extension("pack"))?;
+            if unpack_since.is_some_and(|since| written > since) {
+                for oid in pack.index().oids() {
+                    if !reachable.contains(oid) && !odb.object_path(oid).exists() {
+                        let (kind, content) = odb.read_raw(oid)?;
+                        odb.write_loose(kind, &content)?;
+                        set_modified(&odb.object_path(oid), written)?;
+                    }
+                }
+            }
+            for path in [path.with_extension("pack"), path.to_path_buf()] {
+                fs::remove_file(&path)
+                    .with_context(|| format!("couldn't remove {}", path.display()))?;
+            }
+        }
+    }
+    if options.delete {
+        // reopened so the new pack is seen
+        let odb = ObjectDatabase::default();
+        for oid in odb.loose_objects()? {
+            if odb.is_packed(&oid)? {
+                remove_loose(&odb.object_path(&oid))?;
+            }
+        }
+    }
+    Ok((reachable, true))
+}
+
+/// Where reachability starts: every ref, HEAD, everything in the reflogs
+/// and the objects staged in the index.
+fn reachable_tips(odb: &ObjectDatabase) -> Result<Vec<ObjectId>> {
+    let refs = RefStore::default();
+    let mut tips: Vec<ObjectId> = refs.list("refs/")?.into_iter().map(|(_, oid)| oid).collect();
+    tips.extend(refs.resolve("HEAD")?);
+    // old reflog entries may name objects that are long gone
+    tips.extend(
+        refs.reflog_objects()?
+            .into_iter()
+            .filter(|oid| odb.exists(oid)),
+    );
+    if find_index().is_ok() {
+        let index = Index::default().read()?;
+        tips.extend(
+            index
+                .entries
+                .iter()
+                .filter(|entry| entry.mode != 0o160000)
+                .map(|entry| entry.sha1),
+        );
+    }
+    Ok(tips)
+}
+
+fn remove_loose(path: &Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| format!("couldn't remove {}", path.display()))?;
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir); // only succeeds once the fan-out directory is empty
+    }
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64)
+}
+
+/// A file's modification time in seconds since the epoch.
+fn modified(path: &Path) -> Result<i64> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("couldn't stat {}", path.display()))?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64))
+}
+
+fn set_modified(path: &Path, time: i64) -> Result<()> {
+    let time = UNIX_EPOCH + Duration::from_secs(time.max(0) as u64);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .with_context(|| format!("couldn't set the time of {}", path.display()))
+}
@@ -0,0 +1,34 @@
+use anyhow::{Context, Result};
+
+use crate::repository::{resolve_revision, shorten_ref_name, ObjectDatabase, RefStore};
+
+pub fn rev_parse(revisions: Vec<String>, short: bool, abbrev_ref: bool) -> Result<()> {
+    let odb = ObjectDatabase::default();
+
+    for rev in revisions {
+        if abbrev_ref {
+            println!("{}", abbreviate_ref(&rev)?);
+            continue;
+        }
+        let oid = resolve_revision(&odb, &rev)?;
+        if short {
+            println!("{}", oid.short());
+        } else {
+            println!("{}", oid);
+        }
+    }
+
+    Ok(())
+}
+
+/// The short name of the ref `rev` names, e.g. the current branch for
+/// `HEAD`, or `HEAD` itself when detached.
+fn abbreviate_ref(rev: &str) -> Result<String> {
+    let refs = RefStore::default();
+    let rev = if rev == "@" { "HEAD" } else { rev };
+    let name = refs
+        .dwim(rev)?
+        .with_context(|| format!("unknown ref: '{}'", rev))?;
+    let (target, _) = refs.follow(&name)?;
+    Ok(shorten_ref_name(&target).to_string())
+}
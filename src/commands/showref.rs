@@ -0,0 +1,34 @@
+use anyhow::Result;
+
+use crate::repository::RefStore;
+
+pub fn show_ref(heads: bool, tags: bool, hash_only: bool, patterns: Vec<String>) -> Result<()> {
+    let refs = RefStore::default();
+
+    let mut found = false;
+    for (name, oid) in refs.list("refs/")? {
+        let wanted_kind = (!heads && !tags)
+            || (heads && name.starts_with("refs/heads/"))
+            || (tags && name.starts_with("refs/tags/"));
+        // like git, a pattern must match whole trailing path components
+        let matches_pattern = patterns.is_empty()
+            || patterns
+                .iter()
+                .any(|pattern| name == *pattern || name.ends_with(&format!("/{}", pattern)));
+        if !wanted_kind || !matches_pattern {
+            continue;
+        }
+
+        found = true;
+        if hash_only {
+            println!("{}", oid);
+        } else {
+            println!("{} {}", oid, name);
+        }
+    }
+
+    if !found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
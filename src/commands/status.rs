@@ -0,0 +1,297 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::repository::{
+    ahead_behind, flatten_tree, shorten_ref_name, Change, Config, Conflict, IndexEntry, ObjectDatabase,
+    ObjectId, RefStore, Status,
+};
+use crate::Index;
+
+use super::branch::{current_branch, upstream_of};
+use super::merge::merge_in_progress;
+
+/// The branch status reports on, and how it relates to its upstream.
+struct BranchInfo {
+    /// The current branch, `None` when HEAD is detached.
+    name: Option<String>,
+    head: Option<ObjectId>,
+    /// The upstream's short name and, unless it is gone, the ahead/behind counts.
+    upstream: Option<(String, Option<(usize, usize)>)>,
+}
+
+pub fn status(short: bool, porcelain: Option<String>, branch: bool) -> Result<()> {
+    let odb = ObjectDatabase::default();
+    let refs = RefStore::default();
+
+    let name = current_branch(&refs)?;
+    let head = refs.resolve("HEAD")?;
+    let head_files = match head {
+        Some(head) => flatten_tree(&odb, &odb.read_commit(&head)?.tree)?,
+        None => BTreeMap::new(),
+    };
+    let index = Index::default().read()?;
+    let status = Status::collect(&head_files, &index)?;
+
+    let mut upstream = None;
+    if let (Some(name), Some(head)) = (&name, head) {
+        if let Some(full) = upstream_of(&Config::load()?, name) {
+            let counts = match refs.resolve(&full)? {
+                Some(upstream) => Some(ahead_behind(&odb, head, upstream)?),
+                None => None,
+            };
+            upstream = Some((shorten_ref_name(&full).to_string(), counts));
+        }
+    }
+    let info = BranchInfo {
+        name,
+        head,
+        upstream,
+    };
+
+    match porcelain.as_deref() {
+        Some("v2") | Some("2") => print_porcelain_v2(&status, &info, &head_files, &index, branch),
+        Some("v1") | Some("1") => print_short(&status, &info, branch),
+        Some(version) => anyhow::bail!("unsupported porcelain version: '{}'", version),
+        None if short => print_short(&status, &info, branch),
+        None => print_long(&status, &info, merge_in_progress()?.is_some()),
+    }
+    Ok(())
+}
+
+/// Every path with a staged or unstaged change, in order.
+fn changed_paths(status: &Status) -> BTreeSet<&String> {
+    status.staged.keys().chain(status.unstaged.keys()).collect()
+}
+
+fn print_short(status: &Status, info: &BranchInfo, branch: bool) {
+    if branch {
+        let mut header = match (&info.name, info.head) {
+            (Some(name), None) => format!("## No commits yet on {}", name),
+            (Some(name), Some(_)) => format!("## {}", name),
+            (None, _) => "## HEAD (no branch)".to_string(),
+        };
+        if let Some((upstream, counts)) = &info.upstream {
+            header.push_str(&format!("...{}", upstream));
+            match counts {
+                None => header.push_str(" [gone]"),
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => header.push_str(&format!(" [ahead {}]", ahead)),
+                Some((0, behind)) => header.push_str(&format!(" [behind {}]", behind)),
+                Some((ahead, behind)) => {
+                    header.push_str(&format!(" [ahead {}, behind {}]", ahead, behind))
+                }
+            }
+        }
+        println!("{}", header);
+    }
+
+    let mut paths = changed_paths(status);
+    paths.extend(status.unmerged.iter().map(|conflict| &conflict.path));
+    for path in paths {
+        if let Some(conflict) = status.unmerged.iter().find(|c| &c.path == path) {
+            println!("{} {}", conflict.short_status(), path);
+            continue;
+        }
+        let x = status.staged.get(path).map_or(' ', Change::letter);
+        let y = status.unstaged.get(path).map_or(' ', Change::letter);
+        println!("{}{} {}", x, y, path);
+    }
+    for path in &status.untracked {
+        println!("?? {}", path);
+    }
+}
+
+/// `status --porcelain=v2`: one `1 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <path>`
+/// line per changed path, a `u <XY> <sub> <m1> <m2> <m3> <mW> <h1> <h2> <h3>
+/// <path>` line per unmerged path, then `? <path>` for untracked files.
+fn print_porcelain_v2(
+    status: &Status,
+    info: &BranchInfo,
+    head_files: &BTreeMap<String, (u32, ObjectId)>,
+    index: &Index,
+    branch: bool,
+) {
+    if branch {
+        match info.head {
+            Some(head) => println!("# branch.oid {}", head),
+            None => println!("# branch.oid (initial)"),
+        }
+        println!(
+            "# branch.head {}",
+            info.name.as_deref().unwrap_or("(detached)")
+        );
+        if let Some((upstream, counts)) = &info.upstream {
+            println!("# branch.upstream {}", upstream);
+            if let Some((ahead, behind)) = counts {
+                println!("# branch.ab +{} -{}", ahead, behind);
+            }
+        }
+    }
+
+    let staged: BTreeMap<&str, &IndexEntry> = index
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+    for path in changed_paths(status) {
+        let x = status.staged.get(path).map_or('.', Change::letter);
+        let y = status.unstaged.get(path).map_or('.', Change::letter);
+        let (head_mode, head_oid) = head_files.get(path).copied().unwrap_or((0, ObjectId::NULL));
+        let (index_mode, index_oid) = staged
+            .get(path.as_str())
+            .map_or((0, ObjectId::NULL), |entry| (entry.mode, entry.sha1));
+        let worktree_mode = match status.unstaged.get(path) {
+            None => index_mode,
+            Some(Change::Deleted) => 0,
+            Some(_) => fs::symlink_metadata(path)
+                .map_or(0, |metadata| IndexEntry::mode_from_metadata(&metadata)),
+        };
+        println!(
+            "1 {}{} N... {:06o} {:06o} {:06o} {} {} {}",
+            x, y, head_mode, index_mode, worktree_mode, head_oid, index_oid, path
+        );
+    }
+    for conflict in &status.unmerged {
+        let [base, ours, theirs] = conflict
+            .stages
+            .map(|stage| stage.unwrap_or((0, ObjectId::NULL)));
+        let worktree_mode = fs::symlink_metadata(&conflict.path)
+            .map_or(0, |metadata| IndexEntry::mode_from_metadata(&metadata));
+        println!(
+            "u {} N... {:06o} {:06o} {:06o} {:06o} {} {} {} {}",
+            conflict.short_status(),
+            base.0,
+            ours.0,
+            theirs.0,
+            worktree_mode,
+            base.1,
+            ours.1,
+            theirs.1,
+            conflict.path
+        );
+    }
+    for path in &status.untracked {
+        println!("? {}", path);
+    }
+}
+
+/// The long format. `merging` adds where a merge stands, as its conflicts
+/// are resolved.
+fn print_long(status: &Status, info: &BranchInfo, merging: bool) {
+    match (&info.name, info.head) {
+        (Some(name), _) => println!("On branch {}", name),
+        (None, Some(head)) => println!("HEAD detached at {}", head.short()),
+        (None, None) => println!("Not currently on any branch."),
+    }
+    if let Some((upstream, counts)) = &info.upstream {
+        let plural = |n: usize| if n == 1 { "commit" } else { "commits" };
+        match *counts {
+            None => println!(
+                "Your branch is based on '{}', but the upstream is gone.",
+                upstream
+            ),
+            Some((0, 0)) => println!("Your branch is up to date with '{}'.", upstream),
+            Some((ahead, 0)) => println!(
+                "Your branch is ahead of '{}' by {} {}.",
+                upstream,
+                ahead,
+                plural(ahead)
+            ),
+            Some((0, behind)) => println!(
+                "Your branch is behind '{}' by {} {}, and can be fast-forwarded.",
+                upstream,
+                behind,
+                plural(behind)
+            ),
+            Some((ahead, behind)) => println!(
+                "Your branch and '{}' have diverged,\n\
+                 and have {} and {} different commits each, respectively.",
+                upstream, ahead, behind
+            ),
+        }
+        println!();
+    }
+    if info.head.is_none() {
+        println!("\nNo commits yet\n");
+    }
+    if merging && !status.unmerged.is_empty() {
+        println!("You have unmerged paths.");
+        println!("  (fix conflicts and run \"ugit commit\")");
+        println!("  (use \"ugit merge --abort\" to abort the merge)");
+        println!();
+    } else if merging {
+        println!("All conflicts fixed but you are still merging.");
+        println!("  (use \"ugit commit\" to conclude merge)");
+        println!();
+    }
+
+    if !status.staged.is_empty() {
+        println!("Changes to be committed:");
+        // a merge is undone as a whole, not path by path
+        if !merging {
+            println!("  (use \"ugit rm --cached <file>...\" to unstage)");
+        }
+        print_changes(&status.staged);
+    }
+    if !status.unmerged.is_empty() {
+        println!("Unmerged paths:");
+        println!("  (use \"ugit {} to mark resolution)", resolution_hint(&status.unmerged));
+        for conflict in &status.unmerged {
+            println!("\t{:<17}{}", format!("{}:", conflict.label()), conflict.path);
+        }
+        println!();
+    }
+    if !status.unstaged.is_empty() {
+        println!("Changes not staged for commit:");
+        println!("  (use \"ugit add <file>...\" to update what will be committed)");
+        println!("  (use \"ugit checkout -- <file>...\" to discard changes in working directory)");
+        print_changes(&status.unstaged);
+    }
+    if !status.untracked.is_empty() {
+        println!("Untracked files:");
+        println!("  (use \"ugit add <file>...\" to include in what will be committed)");
+        for path in &status.untracked {
+            println!("\t{}", path);
+        }
+        println!();
+    }
+
+    if !status.staged.is_empty() {
+        return;
+    }
+    if !status.unstaged.is_empty() || !status.unmerged.is_empty() {
+        println!("no changes added to commit (use \"ugit add\")");
+    } else if !status.untracked.is_empty() {
+        println!("nothing added to commit but untracked files present (use \"ugit add\" to track)");
+    } else if info.head.is_none() {
+        println!("nothing to commit (create/copy files and use \"ugit add\" to track)");
+    } else {
+        println!("nothing to commit, working tree clean");
+    }
+}
+
+/// How to mark the conflicts resolved: adding the fixed files, removing
+/// deleted ones, or either.
+fn resolution_hint(conflicts: &[Conflict]) -> &'static str {
+    let (mut both_deleted, mut deleted_modified, mut not_deleted) = (false, false, false);
+    for conflict in conflicts {
+        match conflict.stages.map(|stage| stage.is_some()) {
+            [true, false, false] => both_deleted = true,
+            [true, true, false] | [true, false, true] => deleted_modified = true,
+            _ => not_deleted = true,
+        }
+    }
+    match (both_deleted, deleted_modified, not_deleted) {
+        (false, false, _) => "add <file>...\"",
+        (true, false, false) => "rm <file>...\"",
+        _ => "add/rm <file>...\" as appropriate",
+    }
+}
+
+fn print_changes(changes: &BTreeMap<String, Change>) {
+    for (path, change) in changes {
+        println!("\t{:<12}{}", format!("{}:", change.label()), path);
+    }
+    println!();
+}
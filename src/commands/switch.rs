@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+
+use crate::repository::{peel, resolve_revision, ObjectDatabase, ObjectKind, RefStore};
+
+use super::checkout::{create_and_switch, expand_previous, switch_to, Target};
+
+pub fn switch(
+    target: Option<String>,
+    create: Option<String>,
+    detach: bool,
+    force: bool,
+) -> Result<()> {
+    if let Some(name) = create {
+        return create_and_switch(&name, target.as_deref().unwrap_or("HEAD"), force);
+    }
+    let target = target.context("missing branch or commit argument")?;
+    let name = expand_previous(&target)?;
+
+    let odb = ObjectDatabase::default();
+    if detach {
+        let oid = peel(
+            &odb,
+            resolve_revision(&odb, &name)?,
+            Some(ObjectKind::Commit),
+        )?;
+        return switch_to(Target::Detached(oid), &name, force);
+    }
+
+    // unlike checkout, switch only detaches HEAD when asked to
+    if RefStore::default()
+        .resolve(&format!("refs/heads/{}", name))?
+        .is_none()
+    {
+        match resolve_revision(&odb, &name) {
+            Ok(_) => anyhow::bail!(
+                "a branch is expected, got '{}'\n\
+                 hint: If you want to detach HEAD at the commit, try again with the --detach option.",
+                name
+            ),
+            Err(_) => anyhow::bail!("invalid reference: {}", name),
+        }
+    }
+    switch_to(Target::Branch(name.clone()), &name, force)
+}
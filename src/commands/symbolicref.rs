@@ -0,0 +1,36 @@
+use anyhow::Result;
+
+use crate::repository::{shorten_ref_name, RefStore, RefTarget};
+
+pub fn symbolic_ref(
+    quiet: bool,
+    short: bool,
+    message: Option<String>,
+    name: String,
+    target: Option<String>,
+) -> Result<()> {
+    let refs = RefStore::default();
+
+    if let Some(target) = target {
+        anyhow::ensure!(
+            target.starts_with("refs/"),
+            "refusing to point {} outside of refs/: {}",
+            name,
+            target
+        );
+        return refs.set_symbolic(&name, &target, message.as_deref().unwrap_or_default());
+    }
+
+    match refs.read_raw(&name)? {
+        Some(RefTarget::Symbolic(target)) => {
+            let shown = match short {
+                true => shorten_ref_name(&target),
+                false => &target,
+            };
+            println!("{}", shown);
+            Ok(())
+        }
+        _ if quiet => std::process::exit(1),
+        _ => anyhow::bail!("ref {} is not a symbolic ref", name),
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::fs;
+
+use crate::repository::{
+    check_ref_format, resolve_revision, Config, ObjectDatabase, ObjectId, ObjectKind, RefStore,
+    Signature, Tag,
+};
+
+use super::commit::{cleanup_message, launch_editor};
+
+const TAG_EDITMSG: &str = "./ugit/TAG_EDITMSG";
+
+/// Options for `tag`. Without any, the tags are listed.
+#[derive(Debug, Default, Clone)]
+pub struct TagOptions {
+    /// Make an annotated tag object rather than a bare ref.
+    pub annotate: bool,
+    /// Paragraphs of the tag message; giving any implies `annotate`.
+    pub message: Vec<String>,
+    /// Replace a tag that already exists.
+    pub force: bool,
+    /// Delete the named tags.
+    pub delete: bool,
+    /// List tags, only those matching the arguments if there are any.
+    pub list: bool,
+    /// `refname` or `version:refname`, `-` in front to reverse; overrides
+    /// `tag.sort`.
+    pub sort: Option<String>,
+}
+
+pub fn tag(options: TagOptions, args: Vec<String>) -> Result<()> {
+    let refs = RefStore::default();
+    let annotate = options.annotate || !options.message.is_empty();
+
+    if options.delete {
+        anyhow::ensure!(!args.is_empty(), "tag name required");
+        delete_tags(&refs, &args)
+    } else if options.list || (args.is_empty() && !annotate) {
+        list_tags(&refs, options.sort.as_deref(), &args)
+    } else {
+        match args.as_slice() {
+            [] => anyhow::bail!("tag name required"),
+            [name] => create_tag(&refs, &options, name, "HEAD"),
+            [name, target] => create_tag(&refs, &options, name, target),
+            _ => anyhow::bail!("too many arguments"),
+        }
+    }
+}
+
+fn create_tag(refs: &RefStore, options: &TagOptions, name: &str, target: &str) -> Result<()> {
+    let full = format!("refs/tags/{}", name);
+    anyhow::ensure!(!name.starts_with('-'), "'{}' is not a valid tag name.", name);
+    check_ref_format(&full).with_context(|| format!("'{}' is not a valid tag name.", name))?;
+
+    let odb = ObjectDatabase::default();
+    let object = resolve_revision(&odb, target)
+        .with_context(|| format!("Failed to resolve '{}' as a valid ref.", target))?;
+    let previous = refs.resolve(&full)?;
+    anyhow::ensure!(
+        previous.is_none() || options.force,
+        "tag '{}' already exists",
+        name
+    );
+
+    let new = if options.annotate || !options.message.is_empty() {
+        let message = match options.message.is_empty() {
+            true => edit_tag_message(name)?,
+            false => cleanup_message(&options.message.join("\n\n")),
+        };
+        let tag = Tag {
+            object,
+            kind: odb.read_header(&object)?.0,
+            name: name.to_string(),
+            tagger: Some(Signature::from_env("COMMITTER", &Config::load()?)?),
+            message,
+        };
+        odb.write(ObjectKind::Tag, &tag.serialize())?
+    } else {
+        object
+    };
+
+    let expected = previous.or(Some(ObjectId::NULL));
+    refs.update(&full, new, expected, false, "")?;
+    if let Some(previous) = previous.filter(|previous| *previous != new) {
+        println!("Updated tag '{}' (was {})", name, previous.short());
+    }
+    Ok(())
+}
+
+/// Lets the user write the message of the tag `name` in `ugit/TAG_EDITMSG`.
+fn edit_tag_message(name: &str) -> Result<String> {
+    let template = format!(
+        "\n#\n# Write a message for tag:\n#   {}\n# Lines starting with '#' will be ignored.\n",
+        name
+    );
+    fs::write(TAG_EDITMSG, template).context("couldn't write TAG_EDITMSG")?;
+    launch_editor(TAG_EDITMSG)?;
+    let edited = fs::read_to_string(TAG_EDITMSG).context("couldn't read TAG_EDITMSG")?;
+    let message = cleanup_message(&edited);
+    anyhow::ensure!(!message.is_empty(), "no tag message?");
+    // like git, the file is only kept around when something went wrong
+    fs::remove_file(TAG_EDITMSG).context("couldn't remove TAG_EDITMSG")?;
+    Ok(message)
+}
+
+fn delete_tags(refs: &RefStore, names: &[String]) -> Result<()> {
+    let mut failed = false;
+    for name in names {
+        let full = format!("refs/tags/{}", name);
+        let Some(oid) = refs.resolve(&full)? else {
+            eprintln!("error: tag '{}' not found.", name);
+            failed = true;
+            continue;
+        };
+        refs.delete(&full, Some(oid), false)?;
+        println!("Deleted tag '{}' (was {})", name, oid.short());
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn list_tags(refs: &RefStore, sort: Option<&str>, patterns: &[String]) -> Result<()> {
+    let config = Config::load()?;
+    let sort = sort.or(config.get("tag", "sort")).unwrap_or("refname");
+    let (reverse, key) = match sort.strip_prefix('-') {
+        Some(key) => (true, key),
+        None => (false, sort),
+    };
+    let compare: fn(&str, &str) -> Ordering = match key {
+        "refname" => |a, b| a.cmp(b),
+        "version:refname" | "v:refname" => compare_versions,
+        _ => anyhow::bail!("unsupported sort key '{}'", sort),
+    };
+
+    let mut names: Vec<String> = refs
+        .list("refs/tags/")?
+        .into_iter()
+        .filter_map(|(name, _)| name.strip_prefix("refs/tags/").map(str::to_string))
+        .filter(|name| {
+            patterns.is_empty()
+                || patterns
+                    .iter()
+                    .any(|pattern| wildmatch(pattern.as_bytes(), name.as_bytes()))
+        })
+        .collect();
+    names.sort_by(|a, b| compare(a, b));
+    if reverse {
+        names.reverse();
+    }
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Orders names the way `version:refname` does: runs of digits compare as
+/// numbers, so `v1.9` sorts before `v1.10`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (x, y) = (trim_zeros(&a[..a_len]), trim_zeros(&b[..b_len]));
+                let order = x
+                    .len()
+                    .cmp(&y.len())
+                    .then_with(|| x.cmp(y))
+                    .then_with(|| a_len.cmp(&b_len));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) if x != y => return x.cmp(y),
+            _ => {
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
+}
+
+/// Shell-style matching of `text` against `pattern` with `*`, `?`, `[...]`
+/// classes and `\` escapes. As in `tag -l`, `*` also matches `/`.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| wildmatch(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && wildmatch(rest, &text[1..]),
+        Some((b'[', rest)) => match (text.first(), match_class(rest, text.first().copied())) {
+            (Some(_), Some((true, rest))) => wildmatch(rest, &text[1..]),
+            (_, Some((false, _))) | (None, Some(_)) => false,
+            // an unclosed `[` is just a character
+            (_, None) => text.first() == Some(&b'[') && wildmatch(rest, &text[1..]),
+        },
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            text.first() == Some(&rest[0]) && wildmatch(&rest[1..], &text[1..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && wildmatch(rest, &text[1..]),
+    }
+}
+
+/// Matches `c` against the class that follows a `[`, returning whether it
+/// matched and the pattern after the closing `]`, or `None` if unclosed.
+fn match_class(class: &[u8], c: Option<u8>) -> Option<(bool, &[u8])> {
+    let (negated, mut rest) = match class.first() {
+        Some(b'!' | b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            [b']', after @ ..] if !first => return Some((matched != negated, after)),
+            [low, b'-', high, after @ ..] if *high != b']' => {
+                matched |= c.is_some_and(|c| (*low..=*high).contains(&c));
+                rest = after;
+            }
+            [single, after @ ..] => {
+                matched |= c == Some(*single);
+                rest = after;
+            }
+        }
+        first = false;
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+
+use crate::repository::{resolve_revision, ObjectDatabase, ObjectId, RefStore};
+
+pub fn update_ref(
+    delete: bool,
+    no_deref: bool,
+    message: Option<String>,
+    refname: String,
+    values: Vec<String>,
+) -> Result<()> {
+    let refs = RefStore::default();
+    let odb = ObjectDatabase::default();
+
+    // an all-zero old value means the ref must not exist yet
+    let parse_old = |old: &String| -> Result<ObjectId> {
+        if old.bytes().all(|b| b == b'0') && !old.is_empty() {
+            return Ok(ObjectId::NULL);
+        }
+        resolve_revision(&odb, old)
+    };
+
+    if delete {
+        anyhow::ensure!(
+            values.len() <= 1,
+            "usage: update-ref -d <ref> [<old-value>]"
+        );
+        let expected = values.first().map(parse_old).transpose()?;
+        return refs.delete(&refname, expected, !no_deref);
+    }
+
+    let (new, old) = match values.as_slice() {
+        [new] => (new, None),
+        [new, old] => (new, Some(old)),
+        _ => anyhow::bail!("usage: update-ref <ref> <new-value> [<old-value>]"),
+    };
+    let new = resolve_revision(&odb, new).with_context(|| format!("{}: not a valid SHA1", new))?;
+    let expected = old.map(parse_old).transpose()?;
+
+    refs.update(
+        &refname,
+        new,
+        expected,
+        !no_deref,
+        message.as_deref().unwrap_or_default(),
+    )
+}
@@ -5,12 +5,37 @@ mod utils;
 
 pub use cli::{Args, Commands};
 pub use commands::add_to_index;
+pub use commands::{branch, BranchOptions};
 pub use commands::cat_file;
+pub use commands::{checkout, switch};
+pub use commands::{cherry_pick, revert, PickOptions};
+pub use commands::commit;
+pub use commands::commit_tree;
+pub use commands::{diff, diff_tree, DiffFormat, DiffOptions};
 pub use commands::hash_objects;
 pub use commands::index_read;
-pub use commands::ls_tree;
+pub use commands::{log, LogOptions};
+pub use commands::{merge, merge_base, MergeOptions};
+pub use commands::{rebase, RebaseOptions};
+pub use commands::{fsck, gc, pack_objects, repack, RepackOptions};
+pub use commands::{ls_tree, LsTreeOptions};
+pub use commands::rev_parse;
 pub use commands::rm;
+pub use commands::{show_ref, status, symbolic_ref, update_ref};
+pub use commands::{tag, TagOptions};
 pub use commands::write_tree;
 pub use commands::{clear_git, init_git};
-pub use repository::{Index, IndexEntry};
+pub use repository::{Blob, Commit, Config, Conflict, Index, IndexEntry, Signature, Tag, Tree, TreeEntry};
+pub use repository::write_tree_from_paths;
+pub use repository::{ahead_behind, is_ancestor, merge_bases, reachable, reachable_objects};
+pub use repository::{merge_commits, merge_file, merge_trees, ConflictStyle, FileMerge, MergeLabels, TreeMerge};
+pub use repository::{check_ref_format, peel, peel_tags, previous_branch, resolve_revision, shorten_ref_name};
+pub use repository::{check_file, check_path, checkout_file, flatten_tree, read_worktree_file, remove_file, FileState};
+pub use repository::{Change, RefStore, RefTarget, Status};
+pub use repository::{detect_renames, diff_files, diff_trees, DiffEntry, RenameOptions};
+pub use repository::{diff_lines, is_binary, split_lines, unified_diff, DiffAlgorithm, Edit, Hunk};
+pub use repository::{find_in_tree, parse_approxidate, RevWalk, Sort, WalkedCommit};
+pub use repository::{Object, ObjectDatabase, ObjectId, ObjectKind, Pack, PackIndex, MIN_ABBREV};
+pub use repository::{write_pack, PackObject, PackOptions};
+pub use repository::{check_object, FsckProblem, Severity};
 pub use utils::{find_index, get_full_path_from_hash};
@@ -1,14 +1,49 @@
 use std::path::Path;
 
 use clap::Parser;
-use ugit::{add_to_index, clear_git, index_read, init_git, rm};
-use ugit::{cat_file, hash_objects, ls_tree, write_tree};
-use ugit::{Args, Commands};
+use ugit::{add_to_index, clear_git, index_read, init_git, rev_parse, rm};
+use ugit::{checkout, show_ref, status, switch, symbolic_ref, update_ref};
+use ugit::{cat_file, commit, commit_tree, hash_objects, ls_tree, write_tree};
+use ugit::{branch, Args, BranchOptions, Commands, LsTreeOptions};
+use ugit::{diff, diff_tree, log, DiffAlgorithm, DiffFormat, DiffOptions, LogOptions, Sort};
+use ugit::{merge, merge_base, rebase, MergeOptions, RebaseOptions, RenameOptions};
+use ugit::{cherry_pick, revert, PickOptions};
+use ugit::{tag, TagOptions};
+use ugit::{fsck, gc, pack_objects, repack, PackOptions, RepackOptions};
 
 fn is_git_repo() -> bool {
     Path::new("./ugit").exists()
 }
 
+/// The output format picked by `--name-only`, `--name-status` or `--stat`.
+fn diff_format(name_only: bool, name_status: bool, stat: bool, default: DiffFormat) -> DiffFormat {
+    if name_only {
+        DiffFormat::NameOnly
+    } else if name_status {
+        DiffFormat::NameStatus
+    } else if stat {
+        DiffFormat::Stat
+    } else {
+        default
+    }
+}
+
+/// Rename detection from `-M` and `-C`; `-C` alone implies `-M` at its threshold.
+fn rename_options(
+    find_renames: Option<String>,
+    find_copies: Option<String>,
+) -> anyhow::Result<RenameOptions> {
+    let threshold = |value: Option<String>| {
+        value
+            .map(|value| RenameOptions::parse_threshold(&value))
+            .transpose()
+    };
+    Ok(RenameOptions {
+        renames: threshold(find_renames)?,
+        copies: threshold(find_copies)?,
+    })
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
@@ -26,28 +61,325 @@ fn main() -> anyhow::Result<()> {
         }
         Some(Commands::CatFile {
             pretty_print,
+            show_type,
+            show_size,
             object_hash,
         }) => {
-            cat_file(pretty_print, object_hash)?;
+            cat_file(pretty_print, show_type, show_size, object_hash)?;
         }
         Some(Commands::HashObject { objectfile }) => {
-            let hash_vec = hash_objects(&objectfile)?;
-            let hash = hex::encode(hash_vec);
+            let hash = hash_objects(&objectfile)?;
             println!("{}", hash);
         }
-        Some(Commands::LsTree { object_hash }) => {
-            ls_tree(object_hash)?;
+        Some(Commands::LsTree {
+            recursive,
+            show_trees,
+            name_only,
+            long,
+            object_hash,
+        }) => {
+            let options = LsTreeOptions {
+                recursive,
+                show_trees,
+                name_only,
+                long,
+            };
+            ls_tree(object_hash, options)?;
         }
-        Some(Commands::WriteTree { tree }) => {
-            let hash_vec = write_tree(tree)?;
-            let hash = hex::encode(hash_vec);
+        Some(Commands::WriteTree) => {
+            let hash = write_tree()?;
             println!("{}", hash);
         }
+        Some(Commands::CommitTree {
+            tree,
+            parents,
+            messages,
+        }) => {
+            let hash = commit_tree(tree, parents, messages)?;
+            println!("{}", hash);
+        }
+        Some(Commands::Commit { messages }) => {
+            commit(messages)?;
+        }
         Some(Commands::Add { objectfile }) => {
             add_to_index(objectfile)?;
         }
-        Some(Commands::LsFiles {}) => {
-            index_read()?;
+        Some(Commands::RevParse {
+            short,
+            abbrev_ref,
+            revisions,
+        }) => {
+            rev_parse(revisions, short, abbrev_ref)?;
+        }
+        Some(Commands::UpdateRef {
+            delete,
+            no_deref,
+            message,
+            refname,
+            values,
+        }) => {
+            update_ref(delete, no_deref, message, refname, values)?;
+        }
+        Some(Commands::SymbolicRef {
+            quiet,
+            short,
+            message,
+            name,
+            target,
+        }) => {
+            symbolic_ref(quiet, short, message, name, target)?;
+        }
+        Some(Commands::ShowRef {
+            heads,
+            tags,
+            hash_only,
+            patterns,
+        }) => {
+            show_ref(heads, tags, hash_only, patterns)?;
+        }
+        Some(Commands::Branch {
+            delete,
+            force_delete,
+            rename,
+            force_rename,
+            force,
+            verbose,
+            set_upstream_to,
+            unset_upstream,
+            args,
+        }) => {
+            let options = BranchOptions {
+                delete,
+                force_delete,
+                rename,
+                force_rename,
+                force,
+                verbose,
+                set_upstream_to,
+                unset_upstream,
+            };
+            branch(options, args)?;
+        }
+        Some(Commands::Tag {
+            annotate,
+            message,
+            force,
+            delete,
+            list,
+            sort,
+            args,
+        }) => {
+            let options = TagOptions {
+                annotate,
+                message,
+                force,
+                delete,
+                list,
+                sort,
+            };
+            tag(options, args)?;
+        }
+        Some(Commands::Checkout {
+            new_branch,
+            detach,
+            force,
+            target,
+            paths,
+        }) => {
+            checkout(target, new_branch, detach, force, paths)?;
+        }
+        Some(Commands::Switch {
+            create,
+            detach,
+            force,
+            target,
+        }) => {
+            switch(target, create, detach, force)?;
+        }
+        Some(Commands::Status {
+            short,
+            porcelain,
+            branch,
+        }) => {
+            status(short, porcelain, branch)?;
+        }
+        Some(Commands::Log {
+            oneline,
+            graph,
+            format,
+            max_count,
+            first_parent,
+            topo_order,
+            date_order,
+            author_date_order,
+            since,
+            until,
+            author,
+            grep,
+            revisions,
+            paths,
+        }) => {
+            let sort = if topo_order {
+                Sort::Topo
+            } else if date_order {
+                Sort::Date
+            } else if author_date_order {
+                Sort::AuthorDate
+            } else {
+                Sort::Chronological
+            };
+            let options = LogOptions {
+                oneline,
+                graph,
+                format,
+                max_count,
+                first_parent,
+                sort,
+                since,
+                until,
+                author,
+                grep,
+            };
+            log(options, revisions, paths)?;
+        }
+        Some(Commands::Diff {
+            cached,
+            unified,
+            diff_algorithm,
+            patience,
+            histogram,
+            name_only,
+            name_status,
+            stat,
+            find_renames,
+            find_copies,
+            no_renames,
+            revisions,
+            paths,
+        }) => {
+            let algorithm = match diff_algorithm {
+                Some(name) => DiffAlgorithm::parse(&name)?,
+                None if patience => DiffAlgorithm::Patience,
+                None if histogram => DiffAlgorithm::Histogram,
+                None => DiffAlgorithm::Myers,
+            };
+            // like git, `diff` looks for renames unless told not to
+            let mut renames = rename_options(find_renames, find_copies)?;
+            if renames.renames.is_none() && !no_renames {
+                renames.renames = Some(50);
+            }
+            let options = DiffOptions {
+                cached,
+                format: diff_format(name_only, name_status, stat, DiffFormat::Patch),
+                algorithm,
+                context: unified,
+                renames,
+            };
+            diff(options, revisions, paths)?;
+        }
+        Some(Commands::DiffTree {
+            recursive,
+            patch,
+            name_only,
+            name_status,
+            stat,
+            find_renames,
+            find_copies,
+            root,
+            tree_ishes,
+            paths,
+        }) => {
+            let default = if patch {
+                DiffFormat::Patch
+            } else {
+                DiffFormat::Raw
+            };
+            let options = DiffOptions {
+                format: diff_format(name_only, name_status, stat, default),
+                context: 3,
+                renames: rename_options(find_renames, find_copies)?,
+                ..DiffOptions::default()
+            };
+            diff_tree(options, recursive, root, tree_ishes, paths)?;
+        }
+        Some(Commands::Merge {
+            no_ff,
+            ff_only,
+            no_commit,
+            message,
+            conflict,
+            abort,
+            continue_merge,
+            commit,
+        }) => {
+            let options = MergeOptions {
+                no_ff,
+                ff_only,
+                no_commit,
+                message,
+                conflict_style: conflict,
+                abort,
+                continue_merge,
+            };
+            merge(options, commit)?;
+        }
+        Some(Commands::MergeBase {
+            all,
+            is_ancestor,
+            commits,
+        }) => {
+            merge_base(all, is_ancestor, commits)?;
+        }
+        Some(Commands::CherryPick {
+            mainline,
+            no_commit,
+            record_origin,
+            continue_pick,
+            abort,
+            commits,
+        }) => {
+            let options = PickOptions {
+                mainline,
+                no_commit,
+                record_origin,
+                continue_pick,
+                abort,
+            };
+            cherry_pick(options, commits)?;
+        }
+        Some(Commands::Revert {
+            mainline,
+            no_commit,
+            continue_pick,
+            abort,
+            commits,
+        }) => {
+            let options = PickOptions {
+                mainline,
+                no_commit,
+                record_origin: false,
+                continue_pick,
+                abort,
+            };
+            revert(options, commits)?;
+        }
+        Some(Commands::Rebase {
+            interactive,
+            continue_rebase,
+            skip,
+            abort,
+            upstream,
+        }) => {
+            let options = RebaseOptions {
+                interactive,
+                continue_rebase,
+                skip,
+                abort,
+            };
+            rebase(options, upstream)?;
+        }
+        Some(Commands::LsFiles { stage, unmerged }) => {
+            index_read(stage, unmerged)?;
         }
         Some(Commands::Rm {
             cached,
@@ -55,6 +387,32 @@ fn main() -> anyhow::Result<()> {
         }) => {
             rm(cached, objectfile)?;
         }
+        Some(Commands::PackObjects {
+            window,
+            depth,
+            base_name,
+        }) => {
+            pack_objects(PackOptions { window, depth }, base_name)?;
+        }
+        Some(Commands::Repack {
+            all,
+            delete,
+            window,
+            depth,
+        }) => {
+            let options = RepackOptions {
+                all,
+                delete,
+                pack: PackOptions { window, depth },
+            };
+            repack(options)?;
+        }
+        Some(Commands::Gc { prune, no_prune }) => {
+            gc(prune, no_prune)?;
+        }
+        Some(Commands::Fsck) => {
+            fsck()?;
+        }
         None => {
             println!("No commands provided");
         }
@@ -1,7 +1,261 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Utc};
+use std::fmt;
+
+use super::{Config, ObjectId};
+
+/// The author or committer line of a commit: who, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub time: i64,   // seconds since the epoch
+    pub offset: i32, // timezone offset from UTC in minutes
+}
+
 pub struct Commit {
-    tree: String,                // SHA-1 hash of the tree object
-    parent_commits: Vec<String>, // SHA-1 hashes of parent commits
-    author: String,
-    committer: String,
-    message: String,
+    pub tree: ObjectId,                // SHA-1 hash of the tree object
+    pub parent_commits: Vec<ObjectId>, // SHA-1 hashes of parent commits
+    pub author: Signature,
+    pub committer: Signature,
+    pub extra_headers: Vec<(String, String)>, // e.g. gpgsig, kept so the object round-trips
+    pub message: String,
+}
+
+impl Signature {
+    /// Creates a signature for `name <email>` at the current local time.
+    pub fn now(name: &str, email: &str) -> Signature {
+        let now = Local::now();
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time: now.timestamp(),
+            offset: now.offset().local_minus_utc() / 60,
+        }
+    }
+
+    /// Builds the author (`role == "AUTHOR"`) or committer (`role == "COMMITTER"`)
+    /// identity from `GIT_<role>_NAME`/`_EMAIL`/`_DATE`, falling back to
+    /// `user.name` and `user.email` from `ugit/config`.
+    pub fn from_env(role: &str, config: &Config) -> Result<Signature> {
+        let name = std::env::var(format!("GIT_{}_NAME", role))
+            .ok()
+            .or_else(|| config.get("user", "name").map(str::to_string));
+        let email = std::env::var(format!("GIT_{}_EMAIL", role))
+            .ok()
+            .or_else(|| config.get("user", "email").map(str::to_string));
+
+        let (Some(name), Some(email)) = (name, email) else {
+            anyhow::bail!(
+                "{} identity unknown: set user.name and user.email in ugit/config",
+                role.to_lowercase()
+            )
+        };
+
+        let mut signature = Signature::now(&name, &email);
+        if let Ok(date) = std::env::var(format!("GIT_{}_DATE", role)) {
+            let (time, offset) = parse_date(&date)
+                .with_context(|| format!("invalid GIT_{}_DATE: '{}'", role, date))?;
+            signature.time = time;
+            signature.offset = offset;
+        }
+        Ok(signature)
+    }
+
+    /// Parses `Name <email> 1710000000 +0100`.
+    pub fn parse(line: &str) -> Result<Signature> {
+        let open = line.find('<').context("signature is missing '<'")?;
+        let close = line.rfind('>').context("signature is missing '>'")?;
+        anyhow::ensure!(open < close, "malformed signature: '{}'", line);
+
+        let name = line[..open].trim_end().to_string();
+        let email = line[open + 1..close].to_string();
+
+        let mut rest = line[close + 1..].split_whitespace();
+        let time = rest
+            .next()
+            .context("signature is missing a timestamp")?
+            .parse()
+            .context("couldn't parse signature timestamp")?;
+        let offset = parse_offset(rest.next().context("signature is missing a timezone")?)?;
+
+        Ok(Signature {
+            name,
+            email,
+            time,
+            offset,
+        })
+    }
+
+    /// The signature's timestamp in its own timezone.
+    pub fn datetime(&self) -> DateTime<FixedOffset> {
+        let offset = FixedOffset::east_opt(self.offset * 60)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        DateTime::from_timestamp(self.time, 0)
+            .unwrap_or_default()
+            .with_timezone(&offset)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.abs();
+        write!(
+            f,
+            "{} <{}> {} {}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
+
+/// Parses a timezone like `+0530` or `-0800` into minutes.
+fn parse_offset(tz: &str) -> Result<i32> {
+    let (sign, digits) = match tz.as_bytes().first() {
+        Some(b'+') => (1, &tz[1..]),
+        Some(b'-') => (-1, &tz[1..]),
+        _ => anyhow::bail!("timezone must start with '+' or '-': '{}'", tz),
+    };
+    anyhow::ensure!(
+        digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit()),
+        "malformed timezone: '{}'",
+        tz
+    );
+    let hours: i32 = digits[..2].parse()?;
+    let minutes: i32 = digits[2..].parse()?;
+    Ok(sign * (hours * 60 + minutes))
+}
+
+/// Parses the date formats git accepts in `GIT_*_DATE`: `<unix> <tz>`
+/// (optionally prefixed with `@`), RFC 2822 and ISO 8601 / RFC 3339.
+pub fn parse_date(date: &str) -> Result<(i64, i32)> {
+    let date = date.trim();
+    let raw = date.strip_prefix('@').unwrap_or(date);
+    let mut parts = raw.split_whitespace();
+    if let Some(Ok(time)) = parts.next().map(str::parse::<i64>) {
+        let offset = match parts.next() {
+            Some(tz) => parse_offset(tz)?,
+            None => 0,
+        };
+        return Ok((time, offset));
+    }
+
+    let parsed = DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z"))
+        .context("unsupported date format")?;
+    Ok((parsed.timestamp(), parsed.offset().local_minus_utc() / 60))
+}
+
+/// Parses the looser dates `log --since/--until` accept: anything
+/// `parse_date` does, a bare `YYYY-MM-DD` (midnight UTC), `yesterday` or
+/// `<n> <unit>s ago`. Returns seconds since the epoch.
+pub fn parse_approxidate(date: &str) -> Result<i64> {
+    if let Ok((time, _)) = parse_date(date) {
+        return Ok(time);
+    }
+    let date = date.trim();
+    if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Ok(day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp());
+    }
+
+    let now = Utc::now().timestamp();
+    if date == "yesterday" {
+        return Ok(now - 24 * 60 * 60);
+    }
+    let words: Vec<&str> = date
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|word| !word.is_empty())
+        .collect();
+    if let [n, unit, "ago"] = words.as_slice() {
+        let n: i64 = n
+            .parse()
+            .with_context(|| format!("invalid date: '{}'", date))?;
+        let unit = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            "year" => 365 * 24 * 60 * 60,
+            unit => anyhow::bail!("unknown time unit '{}' in '{}'", unit, date),
+        };
+        return Ok(now - n * unit);
+    }
+    anyhow::bail!("unsupported date: '{}'", date)
+}
+
+impl Commit {
+    /// The first line of the message.
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+
+    /// Serializes the commit body, without the `commit <size>\0` header.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = String::new();
+        out.push_str(&format!("tree {}\n", self.tree));
+        for parent in &self.parent_commits {
+            out.push_str(&format!("parent {}\n", parent));
+        }
+        out.push_str(&format!("author {}\n", self.author));
+        out.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            // multi-line header values continue on lines starting with a space
+            out.push_str(&format!("{} {}\n", key, value.replace('\n', "\n ")));
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+
+    /// Parses a commit body, without the `commit <size>\0` header.
+    pub fn parse(content: &[u8]) -> Result<Commit> {
+        let content = std::str::from_utf8(content).context("commit isn't valid UTF-8")?;
+        let (headers, message) = content
+            .split_once("\n\n")
+            .unwrap_or((content.trim_end_matches('\n'), ""));
+
+        let mut tree = None;
+        let mut parent_commits = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
+
+        for line in headers.lines() {
+            if let Some(continuation) = line.strip_prefix(' ') {
+                let (_, value) = extra_headers
+                    .last_mut()
+                    .context("continuation line without a header")?;
+                value.push('\n');
+                value.push_str(continuation);
+                continue;
+            }
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("malformed commit header: '{}'", line))?;
+            match key {
+                "tree" => tree = Some(ObjectId::from_hex(value)?),
+                "parent" => parent_commits.push(ObjectId::from_hex(value)?),
+                "author" => author = Some(Signature::parse(value)?),
+                "committer" => committer = Some(Signature::parse(value)?),
+                _ => extra_headers.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        Ok(Commit {
+            tree: tree.context("commit has no tree")?,
+            parent_commits,
+            author: author.context("commit has no author")?,
+            committer: committer.context("commit has no committer")?,
+            extra_headers,
+            message: message.to_string(),
+        })
+    }
 }
@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+/// A parsed `ugit/config` file.
+///
+/// Only the simple `[section]` / `key = value` subset of git's config
+/// format is supported, which is all `init_git` ever writes. Edits keep
+/// every other line (comments included) as it was.
+#[derive(Debug, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+    entries: Vec<(String, String, String)>, // (section, key, value)
+}
+
+/// The section a `[section]` or `[section "sub"]` header line opens, e.g.
+/// `branch.main` for `[branch "main"]`.
+fn section_name(line: &str) -> Option<String> {
+    let name = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    Some(match name.split_once(' ') {
+        Some((name, sub)) => format!("{}.{}", name, sub.trim().trim_matches('"')),
+        None => name.to_string(),
+    })
+}
+
+/// The header line for a section name: `branch.main` -> `[branch "main"]`.
+fn section_header(section: &str) -> String {
+    match section.split_once('.') {
+        Some((name, sub)) => format!("[{} \"{}\"]", name, sub),
+        None => format!("[{}]", section),
+    }
+}
+
+/// Whether two section names are the same section. As in git, the section
+/// part is case-insensitive but a subsection (`branch.Main`) is not.
+fn same_section<'a>(a: &'a str, b: &'a str) -> bool {
+    let split = |name: &'a str| match name.split_once('.') {
+        Some((section, sub)) => (section, Some(sub)),
+        None => (name, None),
+    };
+    let ((a_section, a_sub), (b_section, b_sub)) = (split(a), split(b));
+    a_section.eq_ignore_ascii_case(b_section) && a_sub == b_sub
+}
+
+impl Config {
+    /// Loads `./ugit/config`, returning an empty config if it doesn't exist.
+    pub fn load() -> Result<Config> {
+        let path = PathBuf::from("./ugit/config");
+        let contents = match path.exists() {
+            true => fs::read_to_string(&path).context("couldn't read ugit/config")?,
+            false => String::new(),
+        };
+        let mut config = Config::parse(&contents);
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    pub fn parse(contents: &str) -> Config {
+        let mut config = Config {
+            path: None,
+            lines: contents.lines().map(str::to_string).collect(),
+            entries: Vec::new(),
+        };
+        config.reparse();
+        config
+    }
+
+    fn reparse(&mut self) {
+        let mut entries = Vec::new();
+        let mut section = String::new();
+
+        for line in &self.lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = section_name(line) {
+                section = name;
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (line, "true"),
+            };
+            entries.push((section.clone(), key.to_lowercase(), value.to_string()));
+        }
+
+        self.entries = entries;
+    }
+
+    /// Returns the last value of `key` in `section`, like `git config --get`.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(s, k, _)| same_section(s, section) && k.eq_ignore_ascii_case(key))
+            .map(|(_, _, v)| v.as_str())
+    }
+
+    /// Line range `[header, end)` of each occurrence of `section`.
+    fn section_ranges(&self, section: &str) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        let mut current: Option<usize> = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(name) = section_name(line) {
+                if let Some(start) = current.take() {
+                    ranges.push((start, i));
+                }
+                if same_section(&name, section) {
+                    current = Some(i);
+                }
+            }
+        }
+        if let Some(start) = current {
+            ranges.push((start, self.lines.len()));
+        }
+        ranges
+    }
+
+    fn is_key_line(line: &str, key: &str) -> bool {
+        let line = line.trim();
+        let name = line.split('=').next().unwrap_or_default().trim();
+        !line.starts_with('#') && !line.starts_with(';') && name.eq_ignore_ascii_case(key)
+    }
+
+    /// Sets `key` in `section`, replacing an existing value or adding the
+    /// section if needed. Call `save` to write the change.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let line = format!("\t{} = {}", key, value);
+        let ranges = self.section_ranges(section);
+
+        let existing = ranges.iter().rev().find_map(|&(start, end)| {
+            (start + 1..end).find(|&i| Config::is_key_line(&self.lines[i], key))
+        });
+        match (existing, ranges.last()) {
+            (Some(i), _) => self.lines[i] = line,
+            (None, Some(&(_, end))) => self.lines.insert(end, line),
+            (None, None) => {
+                self.lines.push(section_header(section));
+                self.lines.push(line);
+            }
+        }
+        self.reparse();
+    }
+
+    /// Removes every value of `key` in `section`.
+    pub fn unset(&mut self, section: &str, key: &str) {
+        let mut remove = Vec::new();
+        for (start, end) in self.section_ranges(section) {
+            remove.extend((start + 1..end).filter(|&i| Config::is_key_line(&self.lines[i], key)));
+        }
+        for i in remove.into_iter().rev() {
+            self.lines.remove(i);
+        }
+        self.reparse();
+    }
+
+    /// Renames `old` to `new`, e.g. when a branch is renamed.
+    pub fn rename_section(&mut self, old: &str, new: &str) {
+        for (start, _) in self.section_ranges(old) {
+            self.lines[start] = section_header(new);
+        }
+        self.reparse();
+    }
+
+    pub fn remove_section(&mut self, section: &str) {
+        for (start, end) in self.section_ranges(section).into_iter().rev() {
+            self.lines.drain(start..end);
+        }
+        self.reparse();
+    }
+
+    /// Writes the config back to the file it was loaded from.
+    pub fn save(&self) -> Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .context("config wasn't loaded from a file")?;
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+
+        let tmp = path.with_extension("lock");
+        fs::write(&tmp, contents).context("couldn't write ugit/config.lock")?;
+        fs::rename(&tmp, path).context("couldn't update ugit/config")?;
+        Ok(())
+    }
+}
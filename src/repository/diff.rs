@@ -0,0 +1,496 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// How `diff_lines` matches up the lines of two files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// The shortest edit script, found with Myers' O(ND) algorithm.
+    #[default]
+    Myers,
+    /// Lines that occur once on each side are matched first, and the gaps
+    /// between them diffed on their own.
+    Patience,
+    /// Like patience, but anchored on the rarest lines rather than only
+    /// unique ones.
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    pub fn parse(name: &str) -> Result<DiffAlgorithm> {
+        match name {
+            "myers" | "default" | "minimal" => Ok(DiffAlgorithm::Myers),
+            "patience" => Ok(DiffAlgorithm::Patience),
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            _ => anyhow::bail!("unknown diff algorithm: '{}'", name),
+        }
+    }
+}
+
+/// A run of lines that differ: the `old` lines were replaced by the `new`
+/// ones. One of the ranges is empty for a pure deletion or insertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// A hunk of a unified diff.
+#[derive(Debug, Clone)]
+pub struct Hunk<'a> {
+    /// The first line of the hunk in the old file, counting from 0.
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    /// The hunk's lines, each marked with ` `, `-` or `+`.
+    pub lines: Vec<(char, &'a [u8])>,
+    /// The closest line above the hunk that looks like the start of a
+    /// function, shown after the `@@` header.
+    pub heading: Option<&'a [u8]>,
+}
+
+impl Hunk<'_> {
+    /// The `@@ -1,3 +1,4 @@` line, without the heading.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            Hunk::range(self.old_start, self.old_count),
+            Hunk::range(self.new_start, self.new_count)
+        )
+    }
+
+    /// An empty range is named by the line before it, and a count of one is
+    /// left out.
+    fn range(start: usize, count: usize) -> String {
+        match count {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, count),
+        }
+    }
+}
+
+/// Git treats a file as binary if there is a NUL byte in its first 8000 bytes.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&byte| byte == 0)
+}
+
+/// Splits `content` into lines, each keeping its `\n` so that a missing
+/// newline at the end of the file counts as a difference.
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&byte| byte == b'\n').collect()
+}
+
+/// The hunks of a unified diff from `old` to `new`, with `context` unchanged
+/// lines around each change.
+pub fn unified_diff<'a>(
+    old: &'a [u8],
+    new: &'a [u8],
+    algorithm: DiffAlgorithm,
+    context: usize,
+) -> Vec<Hunk<'a>> {
+    let old = split_lines(old);
+    let new = split_lines(new);
+    let edits = diff_lines(&old, &new, algorithm);
+
+    // edits closer together than twice the context share a hunk
+    let mut groups: Vec<&[Edit]> = Vec::new();
+    let mut first = 0;
+    for i in 1..=edits.len() {
+        if i == edits.len() || edits[i].old.start - edits[i - 1].old.end > 2 * context {
+            groups.push(&edits[first..i]);
+            first = i;
+        }
+    }
+
+    let mut hunks = Vec::new();
+    for group in groups.into_iter().filter(|group| !group.is_empty()) {
+        let (head, tail) = (&group[0], &group[group.len() - 1]);
+        let before = head.old.start.min(context);
+        let after = (old.len() - tail.old.end).min(context);
+        let old_start = head.old.start - before;
+        let new_start = head.new.start - before;
+
+        let mut lines = Vec::new();
+        let mut position = old_start;
+        for edit in group {
+            lines.extend(
+                old[position..edit.old.start]
+                    .iter()
+                    .map(|&line| (' ', line)),
+            );
+            lines.extend(old[edit.old.clone()].iter().map(|&line| ('-', line)));
+            lines.extend(new[edit.new.clone()].iter().map(|&line| ('+', line)));
+            position = edit.old.end;
+        }
+        lines.extend(
+            old[position..tail.old.end + after]
+                .iter()
+                .map(|&line| (' ', line)),
+        );
+
+        hunks.push(Hunk {
+            old_start,
+            old_count: tail.old.end + after - old_start,
+            new_start,
+            new_count: tail.new.end + after - new_start,
+            lines,
+            heading: function_heading(&old[..old_start]),
+        });
+    }
+    hunks
+}
+
+/// Like git's default: the last line starting with a letter, `_` or `$`,
+/// cut to 80 bytes and without trailing whitespace.
+fn function_heading<'a>(lines: &[&'a [u8]]) -> Option<&'a [u8]> {
+    let starts_function = |c: &u8| c.is_ascii_alphabetic() || *c == b'_' || *c == b'$';
+    let line = lines
+        .iter()
+        .rev()
+        .find(|line| line.first().is_some_and(starts_function))?;
+    let line = &line[..line.len().min(80)];
+    let end = line
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    Some(&line[..end])
+}
+
+/// The runs of lines that differ between `old` and `new`, in order.
+pub fn diff_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]], algorithm: DiffAlgorithm) -> Vec<Edit> {
+    // compare small integers rather than lines
+    let mut ids: HashMap<&[u8], u32> = HashMap::new();
+    let mut intern = |lines: &[&'a [u8]]| -> Vec<u32> {
+        lines
+            .iter()
+            .map(|&line| {
+                let next = ids.len() as u32;
+                *ids.entry(line).or_insert(next)
+            })
+            .collect()
+    };
+    let a = intern(old);
+    let b = intern(new);
+
+    let mut matches = Vec::new();
+    match algorithm {
+        DiffAlgorithm::Myers => myers(&a, &b, 0, 0, &mut matches),
+        DiffAlgorithm::Patience => patience(&a, &b, 0, 0, &mut matches),
+        DiffAlgorithm::Histogram => histogram(&a, &b, 0, 0, &mut matches),
+    }
+
+    let mut removed = vec![true; a.len()];
+    let mut added = vec![true; b.len()];
+    for (i, j) in matches {
+        removed[i] = false;
+        added[j] = false;
+    }
+    slide_down(&a, &mut removed);
+    slide_down(&b, &mut added);
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let (start_i, start_j) = (i, j);
+        while i < a.len() && removed[i] {
+            i += 1;
+        }
+        while j < b.len() && added[j] {
+            j += 1;
+        }
+        if (i, j) != (start_i, start_j) {
+            edits.push(Edit {
+                old: start_i..i,
+                new: start_j..j,
+            });
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+    edits
+}
+
+/// Moves each run of changed lines as far down as it can go without
+/// changing the diff, so that e.g. an added block ends with its own closing
+/// line rather than starting with one borrowed from the block above.
+fn slide_down(lines: &[u32], changed: &mut [bool]) {
+    let mut start = 0;
+    while start < lines.len() {
+        if !changed[start] {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < lines.len() && changed[end] {
+            end += 1;
+        }
+        while end < lines.len() && lines[start] == lines[end] {
+            changed[start] = false;
+            changed[end] = true;
+            start += 1;
+            end += 1;
+            // joining up with the next run
+            while end < lines.len() && changed[end] {
+                end += 1;
+            }
+        }
+        start = end;
+    }
+}
+
+/// Matches the common prefix and suffix of `a` and `b` and returns the
+/// lengths of both.
+fn trim_common(a: &[u32], b: &[u32]) -> (usize, usize) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    (prefix, suffix)
+}
+
+/// Calls `inner` on what is left of `a` and `b` after their common prefix
+/// and suffix, recording the matched lines (offset by `i` and `j`) in order.
+fn with_common_trimmed(
+    a: &[u32],
+    b: &[u32],
+    i: usize,
+    j: usize,
+    matches: &mut Vec<(usize, usize)>,
+    inner: impl FnOnce(&[u32], &[u32], usize, usize, &mut Vec<(usize, usize)>),
+) {
+    let (prefix, suffix) = trim_common(a, b);
+    matches.extend((0..prefix).map(|k| (i + k, j + k)));
+    let a_rest = &a[prefix..a.len() - suffix];
+    let b_rest = &b[prefix..b.len() - suffix];
+    if !a_rest.is_empty() && !b_rest.is_empty() {
+        inner(a_rest, b_rest, i + prefix, j + prefix, matches);
+    }
+    let (a_end, b_end) = (i + a.len() - suffix, j + b.len() - suffix);
+    matches.extend((0..suffix).map(|k| (a_end + k, b_end + k)));
+}
+
+fn myers(a: &[u32], b: &[u32], i: usize, j: usize, matches: &mut Vec<(usize, usize)>) {
+    with_common_trimmed(a, b, i, j, matches, |a, b, i, j, matches| {
+        let (x, y) = middle_snake(a, b);
+        myers(&a[..x], &b[..y], i, j, matches);
+        myers(&a[x..], &b[y..], i + x, j + y, matches);
+    });
+}
+
+/// A point on an optimal edit path through `a` and `b`, found by searching
+/// forwards from the start and backwards from the end at the same time
+/// until the two searches meet. Both sides must be non-empty and differ in
+/// their first and last lines, so the point splits the problem in two
+/// smaller ones.
+fn middle_snake(a: &[u32], b: &[u32]) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    // furthest x reached on each diagonal k = x - y, from -m - 1 to n + 1
+    let index = |k: isize| (k + m + 1) as usize;
+    let mut forward = vec![-1isize; (n + m + 3) as usize];
+    let mut backward = vec![isize::MAX; (n + m + 3) as usize];
+
+    for d in 0.. {
+        for k in (-d..=d).step_by(2).filter(|&k| k >= -m && k <= n) {
+            // one step right from diagonal k - 1 or down from k + 1
+            let right = forward[index(k - 1)];
+            let down = forward[index(k + 1)];
+            let mut x = match d {
+                0 => 0,
+                _ => {
+                    let right = if right >= 0 && right < n {
+                        right + 1
+                    } else {
+                        -1
+                    };
+                    let down = if down >= 0 && down - (k + 1) < m {
+                        down
+                    } else {
+                        -1
+                    };
+                    right.max(down)
+                }
+            };
+            if x < 0 {
+                continue;
+            }
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            if odd && (k - delta).abs() < d && backward[index(k)] <= x {
+                return (x as usize, y as usize);
+            }
+        }
+
+        for k in (delta - d..=delta + d)
+            .step_by(2)
+            .filter(|&k| k >= -m && k <= n)
+        {
+            // one step left from diagonal k + 1 or up from k - 1
+            let left = backward[index(k + 1)];
+            let up = backward[index(k - 1)];
+            let mut x = match d {
+                0 => n,
+                _ => {
+                    let left = if left != isize::MAX && left > 0 {
+                        left - 1
+                    } else {
+                        isize::MAX
+                    };
+                    let up = if up != isize::MAX && up - (k - 1) > 0 {
+                        up
+                    } else {
+                        isize::MAX
+                    };
+                    left.min(up)
+                }
+            };
+            if x == isize::MAX {
+                continue;
+            }
+            let mut y = x - k;
+            while x > 0 && y > 0 && a[x as usize - 1] == b[y as usize - 1] {
+                x -= 1;
+                y -= 1;
+            }
+            backward[index(k)] = x;
+            if !odd && k.abs() <= d && forward[index(k)] >= x {
+                return (x as usize, y as usize);
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet")
+}
+
+fn patience(a: &[u32], b: &[u32], i: usize, j: usize, matches: &mut Vec<(usize, usize)>) {
+    with_common_trimmed(a, b, i, j, matches, |a, b, i, j, matches| {
+        // lines that occur exactly once on each side
+        let mut counts: HashMap<u32, (usize, usize, usize)> = HashMap::new();
+        for (x, &line) in a.iter().enumerate() {
+            let count = counts.entry(line).or_default();
+            count.0 += 1;
+            count.2 = x;
+        }
+        let mut unique: Vec<(usize, usize)> = Vec::new();
+        for (y, &line) in b.iter().enumerate() {
+            if let Some(count) = counts.get_mut(&line) {
+                count.1 += 1;
+                if count.0 == 1 && count.1 == 1 {
+                    unique.push((count.2, y));
+                }
+            }
+        }
+        unique.retain(|&(x, _)| counts[&a[x]].1 == 1);
+        unique.sort_unstable();
+
+        let anchors = longest_increasing(&unique);
+        if anchors.is_empty() {
+            return myers(a, b, i, j, matches);
+        }
+        let (mut x, mut y) = (0, 0);
+        for (anchor_x, anchor_y) in anchors {
+            patience(&a[x..anchor_x], &b[y..anchor_y], i + x, j + y, matches);
+            matches.push((i + anchor_x, j + anchor_y));
+            (x, y) = (anchor_x + 1, anchor_y + 1);
+        }
+        patience(&a[x..], &b[y..], i + x, j + y, matches);
+    });
+}
+
+/// The longest run of `pairs` (sorted by their first value) whose second
+/// values also increase, found by patience sorting.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // the top of each pile, and the card below which each card was placed
+    let mut piles: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+    for (card, &(_, y)) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|&top| pairs[top].1 < y);
+        previous.push(pile.checked_sub(1).map(|p| piles[p]));
+        if pile == piles.len() {
+            piles.push(card);
+        } else {
+            piles[pile] = card;
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut card = piles.last().copied();
+    while let Some(c) = card {
+        result.push(pairs[c]);
+        card = previous[c];
+    }
+    result.reverse();
+    result
+}
+
+/// Lines that occur more often than this are never used as anchors, as in git.
+const MAX_CHAIN: usize = 64;
+
+fn histogram(a: &[u32], b: &[u32], i: usize, j: usize, matches: &mut Vec<(usize, usize)>) {
+    with_common_trimmed(a, b, i, j, matches, |a, b, i, j, matches| {
+        let mut positions: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (x, &line) in a.iter().enumerate() {
+            positions.entry(line).or_default().push(x);
+        }
+
+        // the longest common run whose rarest line is as rare as possible
+        let mut best: Option<(usize, usize, usize, usize)> = None; // (count, x, y, len)
+        let mut y = 0;
+        while y < b.len() {
+            let mut next_y = y + 1;
+            let Some(candidates) = positions.get(&b[y]).filter(|p| p.len() <= MAX_CHAIN) else {
+                y = next_y;
+                continue;
+            };
+            for &x in candidates {
+                let (mut start_x, mut start_y) = (x, y);
+                while start_x > 0 && start_y > 0 && a[start_x - 1] == b[start_y - 1] {
+                    start_x -= 1;
+                    start_y -= 1;
+                }
+                let mut len = y - start_y + 1;
+                while start_x + len < a.len()
+                    && start_y + len < b.len()
+                    && a[start_x + len] == b[start_y + len]
+                {
+                    len += 1;
+                }
+                let count = a[start_x..start_x + len]
+                    .iter()
+                    .map(|line| positions[line].len())
+                    .min()
+                    .unwrap_or(usize::MAX);
+                if best.is_none_or(|(best_count, _, _, best_len)| {
+                    count < best_count || (count == best_count && len > best_len)
+                }) {
+                    best = Some((count, start_x, start_y, len));
+                    next_y = next_y.max(start_y + len);
+                }
+            }
+            y = next_y;
+        }
+
+        let Some((_, x, y, len)) = best else {
+            return myers(a, b, i, j, matches);
+        };
+        histogram(&a[..x], &b[..y], i, j, matches);
+        matches.extend((0..len).map(|k| (i + x + k, j + y + k)));
+        histogram(
+            &a[x + len..],
+            &b[y + len..],
+            i + x + len,
+            j + y + len,
+            matches,
+        );
+    });
+}
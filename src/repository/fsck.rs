@@ -0,0 +1,328 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{check_ref_format, ObjectId, ObjectKind, Tree};
+
+/// How bad an fsck finding is. Warnings are about objects git itself would
+/// never write but that don't break anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Something wrong with an object's contents, with git's fsck message id
+/// (e.g. `duplicateEntries`).
+#[derive(Debug, Clone)]
+pub struct FsckProblem {
+    pub severity: Severity,
+    pub id: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.id, self.message)
+    }
+}
+
+fn error(id: &'static str, message: &str) -> FsckProblem {
+    FsckProblem {
+        severity: Severity::Error,
+        id,
+        message: message.to_string(),
+    }
+}
+
+fn warning(id: &'static str, message: &str) -> FsckProblem {
+    FsckProblem {
+        severity: Severity::Warning,
+        id,
+        message: message.to_string(),
+    }
+}
+
+/// Checks that `content` is well formed for an object of `kind`, the way
+/// `git fsck` does. Blobs can hold anything.
+pub fn check_object(kind: ObjectKind, content: &[u8]) -> Vec<FsckProblem> {
+    match kind {
+        ObjectKind::Blob => Vec::new(),
+        ObjectKind::Tree => check_tree(content),
+        ObjectKind::Commit => check_commit(content),
+        ObjectKind::Tag => check_tag(content),
+    }
+}
+
+/// Modes, names, order and uniqueness of a tree's entries.
+fn check_tree(content: &[u8]) -> Vec<FsckProblem> {
+    let mut problems = Vec::new();
+    let mut flag = |problem: FsckProblem| {
+        if !problems
+            .iter()
+            .any(|seen: &FsckProblem| seen.id == problem.id)
+        {
+            problems.push(problem);
+        }
+    };
+
+    let mut names = HashSet::new();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut rest = content;
+    while !rest.is_empty() {
+        let Some(space) = rest.iter().position(|&b| b == b' ') else {
+            flag(error("badTree", "cannot be parsed as a tree"));
+            break;
+        };
+        let Some(nul) = rest[space..]
+            .iter()
+            .position(|&b| b == 0)
+            .map(|i| space + i)
+        else {
+            flag(error("badTree", "cannot be parsed as a tree"));
+            break;
+        };
+        if rest.len() < nul + 21 {
+            flag(error("badTree", "cannot be parsed as a tree"));
+            break;
+        }
+        let (mode, name) = (&rest[..space], &rest[space + 1..nul]);
+        rest = &rest[nul + 21..];
+
+        let Some(mode) = std::str::from_utf8(mode).ok().and_then(|digits| {
+            u32::from_str_radix(digits, 8)
+                .ok()
+                .map(|mode| (digits, mode))
+        }) else {
+            flag(error("badTree", "cannot be parsed as a tree"));
+            break;
+        };
+        if mode.0.starts_with('0') {
+            flag(warning(
+                "zeroPaddedFilemode",
+                "contains zero-padded file modes",
+            ));
+        }
+        // 100664 is what very old versions of git wrote for group-writable files
+        if ![0o100644, 0o100755, 0o100664, 0o120000, 0o040000, 0o160000].contains(&mode.1) {
+            flag(warning("badFilemode", "contains bad file modes"));
+        }
+
+        // checkout refuses these, and for good reason: they would write
+        // outside the work tree or into a repository. Our own `ugit` is only
+        // a problem at the top, which the caller knows about and we don't.
+        match name {
+            b"" => flag(error("emptyName", "contains empty pathname")),
+            b"." => flag(error("hasDot", "contains '.'")),
+            b".." => flag(error("hasDotdot", "contains '..'")),
+            name if name.eq_ignore_ascii_case(b".git") => {
+                flag(error("hasDotgit", "contains '.git'"))
+            }
+            name if name.contains(&b'/') => {
+                flag(error("fullPathname", "contains full pathnames"))
+            }
+            _ => {}
+        }
+
+        // files and directories of the same name clash too
+        if !names.insert(name.to_vec()) {
+            flag(error("duplicateEntries", "contains duplicate file entries"));
+        }
+        // trees sort as if their name ended in '/'
+        let mut key = name.to_vec();
+        if mode.1 == 0o040000 {
+            key.push(b'/');
+        }
+        if previous.as_ref().is_some_and(|previous| *previous > key) {
+            flag(error("treeNotSorted", "not properly sorted"));
+        }
+        previous = Some(key);
+    }
+    problems
+}
+
+/// A tree at the top of a commit can't have our repository directory in it.
+pub fn check_root_tree(content: &[u8]) -> Option<FsckProblem> {
+    let tree = Tree::parse(content).ok()?;
+    tree.entries
+        .iter()
+        .any(|entry| entry.name.eq_ignore_ascii_case("ugit"))
+        .then(|| error("hasUgit", "contains 'ugit'"))
+}
+
+/// A commit's headers: one tree, any number of parents, then exactly one
+/// author and one committer with well formed identities.
+fn check_commit(content: &[u8]) -> Vec<FsckProblem> {
+    let headers = headers(content);
+    let mut lines = headers.iter().peekable();
+
+    match lines.next().and_then(|line| line.strip_prefix("tree ")) {
+        None => {
+            return vec![error(
+                "missingTree",
+                "invalid format - expected 'tree' line",
+            )]
+        }
+        Some(hex) if !is_hex_oid(hex) => {
+            return vec![error(
+                "badTreeSha1",
+                "invalid 'tree' line format - bad sha1",
+            )]
+        }
+        Some(_) => {}
+    }
+    while let Some(hex) = lines.peek().and_then(|line| line.strip_prefix("parent ")) {
+        if !is_hex_oid(hex) {
+            return vec![error(
+                "badParentSha1",
+                "invalid 'parent' line format - bad sha1",
+            )];
+        }
+        lines.next();
+    }
+
+    let Some(author) = lines.next().and_then(|line| line.strip_prefix("author ")) else {
+        return vec![error(
+            "missingAuthor",
+            "invalid format - expected 'author' line",
+        )];
+    };
+    if let Some(problem) = check_ident(author) {
+        return vec![problem];
+    }
+    let committer = match lines.next() {
+        Some(line) if line.starts_with("author ") => {
+            return vec![error(
+                "multipleAuthors",
+                "invalid format - multiple 'author' lines",
+            )]
+        }
+        Some(line) => line.strip_prefix("committer "),
+        None => None,
+    };
+    let Some(committer) = committer else {
+        return vec![error(
+            "missingCommitter",
+            "invalid format - expected 'committer' line",
+        )];
+    };
+    check_ident(committer).into_iter().collect()
+}
+
+/// A tag's headers: the object it tags, that object's type, the tag's name
+/// and, usually, a tagger.
+fn check_tag(content: &[u8]) -> Vec<FsckProblem> {
+    let headers = headers(content);
+    let mut lines = headers.iter();
+
+    match lines.next().and_then(|line| line.strip_prefix("object ")) {
+        None => {
+            return vec![error(
+                "missingObject",
+                "invalid format - expected 'object' line",
+            )]
+        }
+        Some(hex) if !is_hex_oid(hex) => {
+            return vec![error(
+                "badObjectSha1",
+                "invalid 'object' line format - bad sha1",
+            )]
+        }
+        Some(_) => {}
+    }
+    match lines.next().and_then(|line| line.strip_prefix("type ")) {
+        None => {
+            return vec![error(
+                "missingTypeEntry",
+                "invalid format - expected 'type' line",
+            )]
+        }
+        Some(kind) if kind.parse::<ObjectKind>().is_err() => {
+            return vec![error("badType", "invalid 'type' value")]
+        }
+        Some(_) => {}
+    }
+    let Some(name) = lines.next().and_then(|line| line.strip_prefix("tag ")) else {
+        return vec![error(
+            "missingTagEntry",
+            "invalid format - expected 'tag' line",
+        )];
+    };
+    let mut problems = Vec::new();
+    if check_ref_format(&format!("refs/tags/{}", name)).is_err() {
+        problems.push(warning(
+            "badTagName",
+            &format!("invalid 'tag' name: {}", name),
+        ));
+    }
+    // tags from before taggers were recorded are fine without one
+    if let Some(tagger) = lines.next().and_then(|line| line.strip_prefix("tagger ")) {
+        problems.extend(check_ident(tagger));
+    }
+    problems
+}
+
+/// Checks `Name <email> 1234567890 +0000` the way git's `fsck_ident` does.
+fn check_ident(ident: &str) -> Option<FsckProblem> {
+    let bad = |id, what: &str| {
+        Some(error(
+            id,
+            &format!("invalid author/committer line - {}", what),
+        ))
+    };
+    if ident.starts_with('<') {
+        return bad("missingNameBeforeEmail", "missing space before email");
+    }
+    let Some(open) = ident.find(['<', '>']) else {
+        return bad("missingEmail", "missing email");
+    };
+    if ident.as_bytes()[open] == b'>' {
+        return bad("badName", "bad name");
+    }
+    if !ident[..open].ends_with(' ') {
+        return bad("missingSpaceBeforeEmail", "missing space before email");
+    }
+    let after_open = &ident[open + 1..];
+    match after_open.find(['<', '>']) {
+        Some(close) if after_open.as_bytes()[close] == b'>' => {
+            let Some(date) = after_open[close + 1..].strip_prefix(' ') else {
+                return bad("missingSpaceBeforeDate", "missing space before date");
+            };
+            let digits = date.bytes().take_while(u8::is_ascii_digit).count();
+            if date.starts_with('0') && digits > 1 {
+                return bad("zeroPaddedDate", "zero-padded date");
+            }
+            if digits == 0 || date.as_bytes().get(digits) != Some(&b' ') {
+                return bad("badDate", "bad date");
+            }
+            if date[..digits].parse::<i64>().is_err() {
+                return bad("badDateOverflow", "date causes integer overflow");
+            }
+            let zone = &date.as_bytes()[digits + 1..];
+            let valid_zone = zone.len() == 5
+                && (zone[0] == b'+' || zone[0] == b'-')
+                && zone[1..].iter().all(u8::is_ascii_digit);
+            if !valid_zone {
+                return bad("badTimezone", "bad time zone");
+            }
+            None
+        }
+        _ => bad("badEmail", "bad email"),
+    }
+}
+
+/// The header lines of a commit or tag, up to the blank line before the
+/// message.
+fn headers(content: &[u8]) -> Vec<String> {
+    let end = content
+        .windows(2)
+        .position(|pair| pair == b"\n\n")
+        .unwrap_or(content.len());
+    String::from_utf8_lossy(&content[..end])
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_hex_oid(hex: &str) -> bool {
+    ObjectId::from_hex(hex).is_ok()
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use std::collections::{HashSet, VecDeque};
+
+use super::{ObjectDatabase, ObjectId, ObjectKind, PackObject};
+
+/// Every commit reachable from `tips`, the tips included.
+pub fn reachable(odb: &ObjectDatabase, tips: &[ObjectId]) -> Result<HashSet<ObjectId>> {
+    let mut seen: HashSet<ObjectId> = tips.iter().copied().collect();
+    let mut queue: VecDeque<ObjectId> = tips.iter().copied().collect();
+    while let Some(oid) = queue.pop_front() {
+        for parent in odb.read_commit(&oid)?.parent_commits {
+            if seen.insert(parent) {
+                queue.push_back(parent);
+            }
+        }
+    }
+    Ok(seen)
+}
+
+/// Every object reachable from `tips`, which may be any kind of object, in
+/// the order `rev-list --objects` lists them: commits, then tags, then each
+/// commit's trees and blobs with the path they were first seen at.
+/// Submodule commits aren't followed, they live in another repository.
+pub fn reachable_objects(odb: &ObjectDatabase, tips: &[ObjectId]) -> Result<Vec<PackObject>> {
+    let mut seen = HashSet::new();
+    let mut commits = Vec::new();
+    let mut tags = Vec::new();
+    let mut roots = Vec::new();
+    let mut queue = VecDeque::new();
+    for &tip in tips {
+        let mut oid = tip;
+        loop {
+            match odb.read_header(&oid)?.0 {
+                ObjectKind::Tag if seen.insert(oid) => {
+                    tags.push(oid);
+                    oid = odb.read_tag(&oid)?.object;
+                }
+                ObjectKind::Commit if seen.insert(oid) => {
+                    queue.push_back(oid);
+                    break;
+                }
+                ObjectKind::Tree | ObjectKind::Blob => {
+                    roots.push(oid);
+                    break;
+                }
+                _ => break, // already seen
+            }
+        }
+    }
+    while let Some(oid) = queue.pop_front() {
+        let commit = odb.read_commit(&oid)?;
+        commits.push(oid);
+        roots.push(commit.tree);
+        for parent in commit.parent_commits {
+            if seen.insert(parent) {
+                queue.push_back(parent);
+            }
+        }
+    }
+
+    let mut objects: Vec<PackObject> = commits
+        .into_iter()
+        .chain(tags)
+        .map(|oid| PackObject {
+            oid,
+            path: String::new(),
+        })
+        .collect();
+    for root in roots {
+        if seen.insert(root) {
+            add_tree_objects(odb, root, String::new(), &mut seen, &mut objects)?;
+        }
+    }
+    Ok(objects)
+}
+
+/// Adds `oid` and, if it's a tree, everything below it that isn't `seen` yet.
+fn add_tree_objects(
+    odb: &ObjectDatabase,
+    oid: ObjectId,
+    path: String,
+    seen: &mut HashSet<ObjectId>,
+    objects: &mut Vec<PackObject>,
+) -> Result<()> {
+    let is_tree = odb.read_header(&oid)?.0 == ObjectKind::Tree;
+    objects.push(PackObject { oid, path: path.clone() });
+    if !is_tree {
+        return Ok(());
+    }
+    for entry in odb.read_tree(&oid)?.entries {
+        if entry.mode == 0o160000 || !seen.insert(entry.hash) {
+            continue;
+        }
+        let path = match path.as_str() {
+            "" => entry.name,
+            _ => format!("{}/{}", path, entry.name),
+        };
+        add_tree_objects(odb, entry.hash, path, seen, objects)?;
+    }
+    Ok(())
+}
+
+/// Whether `ancestor` is reachable from `descendant` (a commit is its own ancestor).
+pub fn is_ancestor(odb: &ObjectDatabase, ancestor: ObjectId, descendant: ObjectId) -> Result<bool> {
+    let mut seen = HashSet::from([descendant]);
+    let mut queue = VecDeque::from([descendant]);
+    while let Some(oid) = queue.pop_front() {
+        if oid == ancestor {
+            return Ok(true);
+        }
+        for parent in odb.read_commit(&oid)?.parent_commits {
+            if seen.insert(parent) {
+                queue.push_back(parent);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// How many commits `ours` has that `theirs` doesn't, and the reverse.
+pub fn ahead_behind(
+    odb: &ObjectDatabase,
+    ours: ObjectId,
+    theirs: ObjectId,
+) -> Result<(usize, usize)> {
+    let ours = reachable(odb, &[ours])?;
+    let theirs = reachable(odb, &[theirs])?;
+    Ok((
+        ours.difference(&theirs).count(),
+        theirs.difference(&ours).count(),
+    ))
+}
+
+/// The best common ancestors of `one` and any of `others`: the common
+/// ancestors that aren't an ancestor of another one. There is usually just
+/// one, but criss-cross merges leave several. Newest first.
+pub fn merge_bases(
+    odb: &ObjectDatabase,
+    one: ObjectId,
+    others: &[ObjectId],
+) -> Result<Vec<ObjectId>> {
+    let ours = reachable(odb, &[one])?;
+    let theirs = reachable(odb, others)?;
+    let common: HashSet<ObjectId> = ours.intersection(&theirs).copied().collect();
+
+    // everything behind a common ancestor is a worse one
+    let mut behind = Vec::new();
+    for oid in &common {
+        behind.extend(odb.read_commit(oid)?.parent_commits);
+    }
+    let worse = reachable(odb, &behind)?;
+
+    let mut bases = Vec::new();
+    for oid in common.difference(&worse) {
+        bases.push((odb.read_commit(oid)?.committer.time, *oid));
+    }
+    bases.sort_by(|a, b| b.cmp(a));
+    Ok(bases.into_iter().map(|(_, oid)| oid).collect())
+}
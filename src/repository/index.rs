@@ -1,23 +1,87 @@
 use anyhow::{Context, Result};
-use byteorder::{LittleEndian, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use crypto_hash::{digest, Algorithm};
 use ignore::WalkBuilder;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::os::unix::fs::PermissionsExt;
+use std::fs::{File, Metadata};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
 
+use super::{check_path, LockFile, ObjectDatabase, ObjectId, ObjectKind};
 use crate::{find_index, hash_objects};
 
-#[derive(Debug)]
+// Bits of the 16-bit entry flags field.
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+/// A path with merge conflicts, which the index holds as up to three
+/// entries: the base version at stage 1, ours at stage 2 and theirs at 3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: String,
+    /// The base, our and their version, where they exist.
+    pub stages: [Option<(u32, ObjectId)>; 3],
+}
+
+impl Conflict {
+    /// The two letters `status --short` shows, e.g. `UU` when both sides
+    /// modified the path or `DU` when we deleted it.
+    pub fn short_status(&self) -> &'static str {
+        match self.present() {
+            [true, true, true] => "UU",
+            [false, true, true] => "AA",
+            [true, false, true] => "DU",
+            [true, true, false] => "UD",
+            [false, true, false] => "AU",
+            [false, false, true] => "UA",
+            _ => "DD",
+        }
+    }
+
+    /// How the long `status` format describes the conflict.
+    pub fn label(&self) -> &'static str {
+        match self.present() {
+            [true, true, true] => "both modified",
+            [false, true, true] => "both added",
+            [true, false, true] => "deleted by us",
+            [true, true, false] => "deleted by them",
+            [false, true, false] => "added by us",
+            [false, false, true] => "added by them",
+            _ => "both deleted",
+        }
+    }
+
+    fn present(&self) -> [bool; 3] {
+        self.stages.map(|stage| stage.is_some())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct IndexEntry {
-    pub mode: u16,  // 2 bytes 100644 -> file and 040000 -> directory
-    file_size: u32, // 4 bytes file size in bytes
-    mtime: u32,     // 4 bytes last modified time in seconds since the epoch
-    // TODO: ctime: u32,
-    sha1: [u8; 20],
+    ctime: u32,      // 4 bytes last metadata change, seconds since the epoch
+    ctime_nsec: u32, // 4 bytes nanosecond fraction of ctime
+    mtime: u32,      // 4 bytes last modified time in seconds since the epoch
+    mtime_nsec: u32, // 4 bytes nanosecond fraction of mtime
+    dev: u32,
+    ino: u32,
+    pub mode: u32, // 4 bytes 100644 -> file, 100755 -> executable, 120000 -> symlink
+    uid: u32,
+    gid: u32,
+    file_size: u32, // 4 bytes file size in bytes, truncated to 32 bits
+    pub sha1: ObjectId,
+    flags: u16,          // assume-valid, extended, stage (2 bits) and name length (12 bits)
+    extended_flags: u16, // v3+ only: skip-worktree and intent-to-add
     pub path: String,
-    // TODO: Flags
 }
 
+/// The staging area, stored in `ugit/index` in git's `DIRC` format.
+///
+/// Versions 2, 3 and 4 are read; entries are big-endian with a 62-byte fixed
+/// header, paths are NUL padded to 8 bytes (v2/v3) or prefix compressed (v4),
+/// and the file ends with the SHA-1 of everything before it.
 pub struct Index {
     signature: [u8; 4],
     version: u32,
@@ -29,152 +93,219 @@ impl Default for Index {
     fn default() -> Self {
         let index_path = "./ugit/index";
 
-        // Check if the index file exists
-        if !std::path::Path::new(index_path).exists() {
-            // Create the index file if it doesn't exist
-            let mut file = File::create(index_path).expect("Failed to create index file");
-
-            // Write the signature "DIRC"
-            file.write_all(b"DIRC").expect("Failed to write signature");
-
-            // Write the version (4 bytes, little-endian)
-            file.write_u32::<LittleEndian>(2)
-                .expect("Failed to write version");
-
-            // Write the entry count (4 bytes, little-endian)
-            file.write_u32::<LittleEndian>(0)
-                .expect("Failed to write entry count");
-        }
-
         // Return a default `Index` instance
-        Index {
+        let index = Index {
             signature: *b"DIRC",  // Default signature for Git index files
             version: 2,           // Default version
             number_of_entries: 0, // Default number of entries
             entries: Vec::new(),  // Empty vector for entries
+        };
+
+        // Check if the index file exists
+        if !std::path::Path::new(index_path).exists() {
+            // Create an empty index file if it doesn't exist
+            let mut file = File::create(index_path).expect("Failed to create index file");
+            file.write_all(&index.to_bytes())
+                .expect("Failed to write empty index file");
         }
+
+        index
     }
 }
 
 impl Index {
+    /// Adds entries, replacing what the index had for their paths. A merged
+    /// (stage 0) entry replaces every stage of its path, resolving any
+    /// conflict; a conflict stage replaces the merged entry and that stage.
     pub fn add_entries(&mut self, entries: Vec<IndexEntry>) {
-        // this helps remove duplicate entries....
-        self.entries
-            .retain(|entry| !entries.iter().any(|new_entry| new_entry.path == entry.path));
+        self.entries.retain(|entry| {
+            !entries.iter().any(|new_entry| {
+                new_entry.path == entry.path
+                    && (new_entry.stage() == 0
+                        || entry.stage() == 0
+                        || new_entry.stage() == entry.stage())
+            })
+        });
 
         self.entries.extend(entries);
+        // git requires entries sorted by path, then stage
+        self.entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
         self.number_of_entries = self.entries.len() as u32;
     }
 
+    /// Every path with merge conflicts, in order.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for entry in self.entries.iter().filter(|entry| entry.stage() != 0) {
+            if conflicts.last().is_none_or(|last| last.path != entry.path) {
+                conflicts.push(Conflict {
+                    path: entry.path.clone(),
+                    stages: [None; 3],
+                });
+            }
+            let conflict = conflicts.last_mut().expect("pushed above");
+            conflict.stages[entry.stage() as usize - 1] = Some((entry.mode, entry.sha1));
+        }
+        conflicts
+    }
+
+    /// Records `conflict`'s versions as the stages of its path, in place of
+    /// the path's merged entry.
+    pub fn add_conflict(&mut self, conflict: &Conflict) {
+        let mut entries = Vec::new();
+        for (stage, side) in (1..).zip(conflict.stages) {
+            if let Some((mode, sha1)) = side {
+                entries.push(IndexEntry::unmerged(conflict.path.clone(), mode, sha1, stage));
+            }
+        }
+        self.remove_entry(&conflict.path);
+        self.add_entries(entries);
+    }
+
+    /// Resolves the conflict at `path` with the version from `stage` (2 for
+    /// ours, 3 for theirs), which becomes its merged entry. If that side
+    /// deleted the path, it is resolved by removing it. Returns the new entry.
+    pub fn resolve(&mut self, path: &str, stage: u16) -> Result<Option<IndexEntry>> {
+        anyhow::ensure!(
+            self.entries
+                .iter()
+                .any(|entry| entry.path == path && entry.stage() != 0),
+            "path '{}' is not unmerged",
+            path
+        );
+        let chosen = self
+            .entries
+            .iter()
+            .find(|entry| entry.path == path && entry.stage() == stage)
+            .map(|entry| {
+                let mut entry = entry.clone();
+                entry.flags &= !FLAG_STAGE_MASK;
+                entry
+            });
+        self.remove_entry(path);
+        if let Some(entry) = &chosen {
+            self.add_entries(vec![entry.clone()]);
+        }
+        Ok(chosen)
+    }
+
     pub fn remove_entry(&mut self, path: &str) {
         self.entries.retain(|entry| entry.path != path);
         self.number_of_entries = self.entries.len() as u32;
     }
 
-    pub fn write(&self) -> Result<()> {
-        // Find and open the index file
-        let index_file = find_index()?;
-        let mut file = File::create(&index_file)
-            .with_context(|| format!("Failed to create file: {}", index_file.display()))?;
-
-        // Write the signature "DIRC"
-        file.write_all(&self.signature)
-            .with_context(|| "Failed to write signature to index file")?;
-
-        // Write the version (4 bytes, little-endian)
-        file.write_u32::<LittleEndian>(self.version)
-            .with_context(|| "Failed to write version to index file")?;
+    /// Serializes the index, including the trailing checksum. Extensions
+    /// (cached trees, etc.) are not kept, as they'd be stale after any change.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<&IndexEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
 
-        // Write the entry count (4 bytes, little-endian)
-        file.write_u32::<LittleEndian>(self.number_of_entries)
-            .with_context(|| "Failed to write entry count to index file")?;
+        // v2 can't store extended flags, so such entries need at least v3
+        let mut version = self.version;
+        if version == 2 && entries.iter().any(|entry| entry.extended_flags != 0) {
+            version = 3;
+        }
 
-        // Write the entries
-        for entry in &self.entries {
-            file.write_all(&entry.to_bytes())
-                .with_context(|| "Failed to write entry to index file")?;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.signature);
+        bytes.write_u32::<BigEndian>(version).unwrap();
+        bytes.write_u32::<BigEndian>(entries.len() as u32).unwrap();
+
+        let mut previous_path = "";
+        for entry in entries {
+            if version >= 4 {
+                entry.write_fixed(&mut bytes, version);
+                // strip the common prefix with the previous path, no padding
+                let common = previous_path
+                    .bytes()
+                    .zip(entry.path.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_varint(&mut bytes, (previous_path.len() - common) as u64);
+                bytes.extend_from_slice(&entry.path.as_bytes()[common..]);
+                bytes.push(0);
+                previous_path = &entry.path;
+            } else {
+                bytes.extend_from_slice(&entry.to_bytes(version));
+            }
         }
 
-        Ok(())
+        let checksum = digest(Algorithm::SHA1, &bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    pub fn write(&self) -> Result<()> {
+        // Written to index.lock and renamed into place, like git does, so
+        // nobody reading the index sees it half written
+        let index_file = find_index()?;
+        LockFile::acquire(&index_file)?.commit(&self.to_bytes())
     }
 
     pub fn read(&self) -> Result<Index> {
-        // Find and open the index file
+        // Find and read the index file
         let index_file = find_index()?;
-        let file = File::open(&index_file)
+        let bytes = std::fs::read(&index_file)
             .with_context(|| format!("Failed to open file: {}", index_file.display()))?;
 
-        let mut reader = BufReader::new(file);
+        Index::parse(&bytes)
+    }
+
+    /// Parses a complete index file, verifying its trailing checksum.
+    pub fn parse(bytes: &[u8]) -> Result<Index> {
+        if bytes.len() < 12 + 20 {
+            return Err(anyhow::anyhow!("Index file is truncated"));
+        }
+
+        // Verify the trailing SHA-1 before trusting anything else
+        let (content, checksum) = bytes.split_at(bytes.len() - 20);
+        if digest(Algorithm::SHA1, content) != checksum {
+            return Err(anyhow::anyhow!("Index file checksum mismatch"));
+        }
 
         // Read and validate the signature (4 bytes)
         let mut signature = [0u8; 4];
-        reader
-            .read_exact(&mut signature)
-            .with_context(|| "Failed to read signature from index file")?;
+        signature.copy_from_slice(&content[..4]);
         if signature != *b"DIRC" {
             return Err(anyhow::anyhow!("Invalid signature in index file"));
         }
 
         // Read the version (4 bytes)
-        let mut version_bytes = [0u8; 4];
-        reader
-            .read_exact(&mut version_bytes)
-            .with_context(|| "Failed to read version from index file")?;
-        let version = u32::from_le_bytes(version_bytes);
-        if version != self.version {
-            return Err(anyhow::anyhow!("Invalid version in index file"));
+        let version = BigEndian::read_u32(&content[4..8]);
+        if !(2..=4).contains(&version) {
+            return Err(anyhow::anyhow!("Unsupported index file version: {}", version));
         }
 
         // Read the entry count (4 bytes)
-        let mut entry_count_bytes = [0u8; 4];
-        reader
-            .read_exact(&mut entry_count_bytes)
-            .with_context(|| "Failed to read entry count from index file")?;
-        let number_of_entries = u32::from_le_bytes(entry_count_bytes);
+        let number_of_entries = BigEndian::read_u32(&content[8..12]);
 
         // Read the entries
-        let mut entries = Vec::new();
+        // every entry takes at least 62 bytes, whatever a corrupt count says
+        let mut entries = Vec::with_capacity((number_of_entries as usize).min(content.len() / 62));
+        let mut offset = 12;
+        let mut previous_path = String::new();
         for _ in 0..number_of_entries {
-            let mut mode_bytes = [0u8; 2];
-            reader
-                .read_exact(&mut mode_bytes)
-                .with_context(|| "Failed to read mode from index file")?;
-            let mode = u16::from_le_bytes(mode_bytes);
-
-            let mut file_size_bytes = [0u8; 4];
-            reader
-                .read_exact(&mut file_size_bytes)
-                .with_context(|| "Failed to read file size from index file")?;
-            let file_size = u32::from_le_bytes(file_size_bytes);
-
-            let mut mtime_bytes = [0u8; 4];
-            reader
-                .read_exact(&mut mtime_bytes)
-                .with_context(|| "Failed to read mtime from index file")?;
-            let mtime = u32::from_le_bytes(mtime_bytes);
-
-            let mut sha1 = [0u8; 20];
-            reader
-                .read_exact(&mut sha1)
-                .with_context(|| "Failed to read sha1 from index file")?;
-
-            let mut path_bytes = Vec::new();
-            reader
-                .read_until(0, &mut path_bytes)
-                .with_context(|| "Failed to read path from index file")?;
-            let path = String::from_utf8(path_bytes[..path_bytes.len() - 1].to_vec())
-                .with_context(|| "Failed to convert path to string")?;
-
-            entries.push(IndexEntry {
-                mode,
-                file_size,
-                mtime,
-                sha1,
-                path,
-            });
+            let (entry, consumed) = IndexEntry::parse(&content[offset..], version, &previous_path)
+                .with_context(|| format!("Failed to read entry {} of index file", entries.len()))?;
+            offset += consumed;
+            previous_path.clone_from(&entry.path);
+            entries.push(entry);
+        }
+
+        // Skip extensions: 4-byte signature, 4-byte size, then the data.
+        // Lowercase signatures mark extensions we must understand to be correct.
+        while offset + 8 <= content.len() {
+            let signature = &content[offset..offset + 4];
+            let size = BigEndian::read_u32(&content[offset + 4..offset + 8]) as usize;
+            if signature[0].is_ascii_lowercase() {
+                return Err(anyhow::anyhow!(
+                    "Unsupported required index extension: {}",
+                    String::from_utf8_lossy(signature)
+                ));
+            }
+            offset += 8 + size;
         }
 
-        // Return the entry count and lines
         Ok(Index {
             signature,
             version,
@@ -185,35 +316,200 @@ impl Index {
 }
 
 impl IndexEntry {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// 0 for a merged path; 1, 2 and 3 for the base, our and their version
+    /// of a path with merge conflicts.
+    pub fn stage(&self) -> u16 {
+        (self.flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT
+    }
+
+    /// An entry for one version of a conflicted path. It has no stat data,
+    /// as it doesn't describe the file on disk.
+    pub fn unmerged(path: String, mode: u32, sha1: ObjectId, stage: u16) -> IndexEntry {
+        IndexEntry {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            file_size: 0,
+            sha1,
+            flags: (stage << FLAG_STAGE_SHIFT) & FLAG_STAGE_MASK,
+            extended_flags: 0,
+            path,
+        }
+    }
+
+    fn sort_key(&self) -> (&[u8], u16) {
+        (self.path.as_bytes(), self.stage())
+    }
+
+    /// Writes the fixed-size part of the entry: stat data, mode, sha1 and
+    /// flags, followed by the extended flags when present in v3+.
+    fn write_fixed(&self, bytes: &mut Vec<u8>, version: u32) {
+        for field in [
+            self.ctime,
+            self.ctime_nsec,
+            self.mtime,
+            self.mtime_nsec,
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.file_size,
+        ] {
+            bytes.write_u32::<BigEndian>(field).unwrap();
+        }
+        bytes.extend_from_slice(self.sha1.as_bytes());
+
+        let name_length = self.path.len().min(FLAG_NAME_MASK as usize) as u16;
+        let mut flags = (self.flags & !FLAG_NAME_MASK & !FLAG_EXTENDED) | name_length;
+        if version >= 3 && self.extended_flags != 0 {
+            flags |= FLAG_EXTENDED;
+        }
+        bytes.write_u16::<BigEndian>(flags).unwrap();
+        if flags & FLAG_EXTENDED != 0 {
+            bytes.write_u16::<BigEndian>(self.extended_flags).unwrap();
+        }
+    }
+
+    /// Serializes a v2/v3 entry, NUL padded to a multiple of 8 bytes.
+    pub fn to_bytes(&self, version: u32) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.mode.to_le_bytes());
-        bytes.extend_from_slice(&self.file_size.to_le_bytes());
-        bytes.extend_from_slice(&self.mtime.to_le_bytes());
-        bytes.extend_from_slice(&self.sha1);
+        self.write_fixed(&mut bytes, version);
         bytes.extend_from_slice(self.path.as_bytes());
-        bytes.push(0);
+        // at least one NUL terminates the path
+        let padding = 8 - (bytes.len() % 8);
+        bytes.resize(bytes.len() + padding, 0);
         bytes
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<IndexEntry> {
-        let mode = u16::from_le_bytes([bytes[0], bytes[1]]);
-        let file_size = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
-        let mtime = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
-        let mut sha1 = [0; 20];
-        sha1.copy_from_slice(&bytes[10..30]);
-        let path = String::from_utf8(bytes[30..].to_vec())
-            .with_context(|| "Failed to convert path to string")?;
-        Ok(IndexEntry {
+    /// Parses one entry from the start of `bytes`, returning it with the
+    /// number of bytes consumed. `previous_path` is needed to expand v4's
+    /// prefix compressed paths.
+    pub fn parse(bytes: &[u8], version: u32, previous_path: &str) -> Result<(IndexEntry, usize)> {
+        if bytes.len() < 62 {
+            return Err(anyhow::anyhow!("Index entry is truncated"));
+        }
+        let field = |i: usize| BigEndian::read_u32(&bytes[i * 4..i * 4 + 4]);
+        let sha1 = ObjectId::from_bytes(&bytes[40..60])?;
+        let flags = BigEndian::read_u16(&bytes[60..62]);
+
+        let mut offset = 62;
+        let mut extended_flags = 0;
+        if flags & FLAG_EXTENDED != 0 {
+            if version < 3 {
+                return Err(anyhow::anyhow!("Extended flags in a version 2 index"));
+            }
+            if bytes.len() < 64 {
+                return Err(anyhow::anyhow!("Index entry is truncated"));
+            }
+            extended_flags = BigEndian::read_u16(&bytes[62..64]);
+            offset = 64;
+        }
+
+        let path_bytes;
+        if version >= 4 {
+            let (strip, varint_len) = read_varint(&bytes[offset..])?;
+            offset += varint_len;
+            let keep = previous_path
+                .len()
+                .checked_sub(strip as usize)
+                .context("Index path prefix is longer than the previous path")?;
+            let suffix_len = bytes[offset..]
+                .iter()
+                .position(|&b| b == 0)
+                .context("Index path is not NUL terminated")?;
+            let mut path = previous_path.as_bytes()[..keep].to_vec();
+            path.extend_from_slice(&bytes[offset..offset + suffix_len]);
+            path_bytes = path;
+            offset += suffix_len + 1;
+        } else {
+            let path_len = bytes[offset..]
+                .iter()
+                .position(|&b| b == 0)
+                .context("Index path is not NUL terminated")?;
+            path_bytes = bytes[offset..offset + path_len].to_vec();
+            // entries are padded with 1-8 NULs to a multiple of 8 bytes
+            offset += path_len;
+            offset += 8 - (offset % 8);
+            if offset > bytes.len() {
+                return Err(anyhow::anyhow!("Index entry is truncated"));
+            }
+        }
+        let path = String::from_utf8(path_bytes).with_context(|| "Failed to convert path to string")?;
+
+        let entry = IndexEntry {
+            ctime: field(0),
+            ctime_nsec: field(1),
+            mtime: field(2),
+            mtime_nsec: field(3),
+            dev: field(4),
+            ino: field(5),
+            mode: field(6),
+            uid: field(7),
+            gid: field(8),
+            file_size: field(9),
+            sha1,
+            flags,
+            extended_flags,
+            path,
+        };
+        Ok((entry, offset))
+    }
+
+    /// The mode git records for a file: 100755 if any execute bit is set,
+    /// 120000 for symlinks, 100644 otherwise.
+    pub fn mode_from_metadata(metadata: &Metadata) -> u32 {
+        if metadata.file_type().is_symlink() {
+            0o120000
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        }
+    }
+
+    /// An entry for `path` with the stat data of the file now on disk.
+    pub fn from_metadata(
+        path: String,
+        mode: u32,
+        sha1: ObjectId,
+        metadata: &Metadata,
+    ) -> IndexEntry {
+        IndexEntry {
+            ctime: metadata.ctime() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            mtime: metadata.mtime() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
             mode,
-            file_size,
-            mtime,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            file_size: metadata.len() as u32,
             sha1,
+            flags: 0,
+            extended_flags: 0,
             path,
-        })
+        }
+    }
+
+    /// Whether the file on disk still has the stat data recorded for it, in
+    /// which case its content is assumed unchanged without rehashing.
+    pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+        self.mtime == metadata.mtime() as u32
+            && self.mtime_nsec == metadata.mtime_nsec() as u32
+            && self.file_size == metadata.len() as u32
+            && self.ino == metadata.ino() as u32
+            && self.mode == IndexEntry::mode_from_metadata(metadata)
     }
 
-    pub fn get_sha(path: &str) -> Result<[u8; 20]> {
+    pub fn get_sha(path: &str) -> Result<ObjectId> {
         // Check if the path is a directory
         if std::fs::metadata(path)?.is_dir() {
             return Err(anyhow::anyhow!("Directories are not supported"));
@@ -235,6 +531,7 @@ impl IndexEntry {
                 .hidden(true)
                 .git_ignore(true)
                 .git_exclude(false)
+                .filter_entry(|entry| entry.path() != Path::new("./ugit")) // never stage the repository itself
                 .build()
             {
                 let entry = entry?;
@@ -255,39 +552,59 @@ impl IndexEntry {
             // sort entries
             path_entries.sort();
         } else {
-            path_entries.push(path.to_string());
+            path_entries.push(path.trim_start_matches("./").to_string());
         }
 
         let mut entries = Vec::new();
         for entry in path_entries {
-            let metadata = std::fs::metadata(&entry).context("couldn't get metadata")?;
-            let is_dir = metadata.is_dir();
-
-            if is_dir {
+            check_path(&entry)?;
+            // symlinks are staged as links, not as the file they point at
+            let metadata =
+                std::fs::symlink_metadata(&entry).context("couldn't get metadata")?;
+            if metadata.is_dir() {
                 continue;
             }
 
-            let sha = IndexEntry::get_sha(&entry)?;
-
-            let mode = if metadata.is_dir() {
-                    0o040000 // Directory
-                } else if metadata.file_type().is_symlink() {
-                    0o120000 // Symbolic link
-                } else if metadata.permissions().mode() & 0o111 != 0 {
-                    0o100755 // Executable file
-                } else {
-                    0o100644 // Regular file
-                };
-
-            let entry = IndexEntry {
-                mode,
-                file_size: metadata.len() as u32,
-                mtime: metadata.modified()?.duration_since(std::time::SystemTime::UNIX_EPOCH)?.as_secs() as u32,
-                sha1: sha,
-                path: entry,
+            let sha = if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(&entry).context("couldn't read symlink")?;
+                ObjectDatabase::default()
+                    .write(ObjectKind::Blob, target.as_os_str().as_bytes())?
+            } else {
+                IndexEntry::get_sha(&entry)?
             };
-            entries.push(entry);
+
+            let mode = IndexEntry::mode_from_metadata(&metadata);
+            entries.push(IndexEntry::from_metadata(entry, mode, sha, &metadata));
         }
         Ok(entries)
     }
 }
+
+/// Writes git's offset varint: 7 bits per byte, most significant first, with
+/// one added to every continued group so each value has a single encoding.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    let mut buf = [0u8; 10];
+    let mut pos = buf.len() - 1;
+    buf[pos] = (value & 0x7f) as u8;
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        pos -= 1;
+        buf[pos] = 0x80 | (value & 0x7f) as u8;
+        value >>= 7;
+    }
+    bytes.extend_from_slice(&buf[pos..]);
+}
+
+/// Reads a varint written by `write_varint`, returning it with its length in bytes.
+fn read_varint(bytes: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate() {
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+        value += 1;
+    }
+    Err(anyhow::anyhow!("Index path varint is truncated"))
+}
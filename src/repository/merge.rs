@@ -0,0 +1,620 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use super::status::same_type;
+use super::tree_diff::{detect_renames, diff_files, RenameOptions};
+use super::{
+    diff_lines, flatten_tree, Conflict, is_binary, merge_bases, split_lines, Change, DiffAlgorithm, Edit,
+    write_tree_from_paths, ObjectDatabase, ObjectId, ObjectKind,
+};
+
+/// How conflicting hunks are marked up in a merged file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Our and their version of the hunk.
+    #[default]
+    Merge,
+    /// Also the base version, between `|||||||` and `=======`.
+    Diff3,
+}
+
+impl ConflictStyle {
+    pub fn parse(name: &str) -> Result<ConflictStyle> {
+        match name {
+            "merge" => Ok(ConflictStyle::Merge),
+            "diff3" => Ok(ConflictStyle::Diff3),
+            _ => anyhow::bail!("unknown conflict style '{}'", name),
+        }
+    }
+}
+
+/// What the base, our and their side are called in conflict markers and
+/// messages, e.g. an abbreviated commit id, `HEAD` and a branch name.
+#[derive(Debug, Clone)]
+pub struct MergeLabels {
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// The result of merging three versions of a file.
+pub struct FileMerge {
+    pub content: Vec<u8>,
+    /// How many conflicts were marked up in `content`.
+    pub conflicts: usize,
+}
+
+/// A run of lines changed on at least one side, as ranges of the base, our
+/// and their lines.
+#[derive(Debug, Clone)]
+struct Region {
+    base: Range<usize>,
+    ours: Range<usize>,
+    theirs: Range<usize>,
+    resolution: Resolution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    Ours,
+    Theirs,
+    Conflict,
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`,
+/// like `git merge-file`. Changes that overlap or touch conflict unless both
+/// sides made the same change. In the merge style, conflicts are narrowed
+/// down to the lines the two sides disagree on, and ones only a few lines
+/// apart are joined, as git does.
+pub fn merge_file(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> FileMerge {
+    let (base, ours, theirs) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    let algorithm = DiffAlgorithm::Myers;
+    let regions = changed_regions(
+        &diff_lines(&base, &ours, algorithm),
+        &diff_lines(&base, &theirs, algorithm),
+    );
+
+    let mut resolved = Vec::with_capacity(regions.len());
+    for mut region in regions {
+        let (o, t) = (&ours[region.ours.clone()], &theirs[region.theirs.clone()]);
+        if region.resolution == Resolution::Conflict && o == t {
+            region.resolution = Resolution::Ours; // both sides made the same change
+        }
+        if region.resolution != Resolution::Conflict
+            || style == ConflictStyle::Diff3
+            || o.is_empty()
+            || t.is_empty()
+        {
+            resolved.push(region);
+            continue;
+        }
+        // only the lines that differ between the two sides conflict
+        for edit in diff_lines(o, t, algorithm) {
+            resolved.push(Region {
+                base: region.base.clone(),
+                ours: region.ours.start + edit.old.start..region.ours.start + edit.old.end,
+                theirs: region.theirs.start + edit.new.start..region.theirs.start + edit.new.end,
+                resolution: Resolution::Conflict,
+            });
+        }
+    }
+    if style == ConflictStyle::Merge {
+        resolved = join_close_conflicts(resolved);
+    }
+
+    let mut merged = FileMerge {
+        content: Vec::new(),
+        conflicts: 0,
+    };
+    let out = &mut merged.content;
+    let mut position = 0; // in our lines, which hold the unchanged ones
+    for region in &resolved {
+        out.extend(ours[position..region.ours.start].concat());
+        position = region.ours.end;
+        match region.resolution {
+            Resolution::Ours => out.extend(ours[region.ours.clone()].concat()),
+            Resolution::Theirs => out.extend(theirs[region.theirs.clone()].concat()),
+            Resolution::Conflict => {
+                merged.conflicts += 1;
+                write_marker(out, b'<', &labels.ours);
+                write_lines(out, &ours[region.ours.clone()]);
+                if style == ConflictStyle::Diff3 {
+                    write_marker(out, b'|', &labels.base);
+                    write_lines(out, &base[region.base.clone()]);
+                }
+                write_marker(out, b'=', "");
+                write_lines(out, &theirs[region.theirs.clone()]);
+                write_marker(out, b'>', &labels.theirs);
+            }
+        }
+    }
+    out.extend(ours[position..].concat());
+    merged
+}
+
+/// Groups the edits each side made to the base into regions, one per run of
+/// edits that overlap or touch.
+fn changed_regions(ours: &[Edit], theirs: &[Edit]) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    // how many lines each side has gained before the current region
+    let (mut ours_shift, mut theirs_shift) = (0isize, 0isize);
+    let shifted = |line: usize, shift: isize| (line as isize + shift) as usize;
+    while i < ours.len() || j < theirs.len() {
+        let start = match (ours.get(i), theirs.get(j)) {
+            (Some(a), Some(b)) => a.old.start.min(b.old.start),
+            (Some(a), None) => a.old.start,
+            (None, Some(b)) => b.old.start,
+            (None, None) => unreachable!("the loop runs while edits remain"),
+        };
+        let mut end = start;
+        let (first_ours, first_theirs) = (i, j);
+        loop {
+            if let Some(edit) = ours.get(i).filter(|edit| edit.old.start <= end) {
+                end = end.max(edit.old.end);
+                i += 1;
+            } else if let Some(edit) = theirs.get(j).filter(|edit| edit.old.start <= end) {
+                end = end.max(edit.old.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        let growth = |edits: &[Edit]| -> isize {
+            edits
+                .iter()
+                .map(|edit| edit.new.len() as isize - edit.old.len() as isize)
+                .sum()
+        };
+        let ours_range = shifted(start, ours_shift)..{
+            ours_shift += growth(&ours[first_ours..i]);
+            shifted(end, ours_shift)
+        };
+        let theirs_range = shifted(start, theirs_shift)..{
+            theirs_shift += growth(&theirs[first_theirs..j]);
+            shifted(end, theirs_shift)
+        };
+        let resolution = match (i > first_ours, j > first_theirs) {
+            (true, false) => Resolution::Ours,
+            (false, true) => Resolution::Theirs,
+            _ => Resolution::Conflict,
+        };
+        regions.push(Region {
+            base: start..end,
+            ours: ours_range,
+            theirs: theirs_range,
+            resolution,
+        });
+    }
+    regions
+}
+
+/// Joins consecutive conflicts separated by no more than three unchanged
+/// lines into one.
+fn join_close_conflicts(regions: Vec<Region>) -> Vec<Region> {
+    let mut joined: Vec<Region> = Vec::with_capacity(regions.len());
+    for region in regions {
+        if let Some(last) = joined.last_mut() {
+            if last.resolution == Resolution::Conflict
+                && region.resolution == Resolution::Conflict
+                && region.ours.start - last.ours.end <= 3
+            {
+                last.base.end = last.base.end.max(region.base.end);
+                last.ours.end = region.ours.end;
+                last.theirs.end = region.theirs.end;
+                continue;
+            }
+        }
+        joined.push(region);
+    }
+    joined
+}
+
+fn write_marker(out: &mut Vec<u8>, marker: u8, label: &str) {
+    out.extend([marker; 7]);
+    if !label.is_empty() {
+        out.push(b' ');
+        out.extend(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+/// Writes lines inside a conflict, ending the last one if the file didn't.
+fn write_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend(*line);
+    }
+    if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        out.push(b'\n');
+    }
+}
+
+/// The outcome of merging two trees.
+pub struct TreeMerge {
+    /// Every file of the merged tree. Conflicted files hold what goes in the
+    /// work tree: the content with conflict markers, or the side that wasn't
+    /// deleted.
+    pub files: BTreeMap<String, (u32, ObjectId)>,
+    pub conflicts: Vec<Conflict>,
+    /// What happened to each path that needed merging, in git's words.
+    pub messages: Vec<String>,
+}
+
+/// A path to merge: what it was in the base and is on each side, which may
+/// be under other names when a side renamed it.
+struct Slot {
+    path: String,
+    paths: [String; 3],
+    sides: [Option<(u32, ObjectId)>; 3],
+    /// A rename conflict, already reported.
+    conflicted: bool,
+}
+
+/// Three-way merges the trees `ours` and `theirs` from their common `base`.
+/// Renames on either side (found as by `diff -M`) carry the other side's
+/// changes along to the new name. Conflicted content is written to the
+/// object database, so it can be checked out like any other file.
+pub fn merge_trees(
+    odb: &ObjectDatabase,
+    base: ObjectId,
+    ours: ObjectId,
+    theirs: ObjectId,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> Result<TreeMerge> {
+    let base = flatten_tree(odb, &base)?;
+    let sides = [
+        &base,
+        &flatten_tree(odb, &ours)?,
+        &flatten_tree(odb, &theirs)?,
+    ];
+    let renames = |side: &BTreeMap<String, (u32, ObjectId)>| -> Result<HashMap<String, String>> {
+        let options = RenameOptions {
+            renames: Some(50),
+            copies: None,
+        };
+        let entries = detect_renames(diff_files(&base, side), options, |oid| {
+            Ok(odb.read_raw(oid)?.1)
+        })?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.change == Change::Renamed)
+            .map(|entry| (entry.old_path, entry.path))
+            .collect())
+    };
+    let (ours_renames, theirs_renames) = (renames(sides[1])?, renames(sides[2])?);
+
+    let mut slots = Vec::new();
+    // paths already taken care of, on each side
+    let mut used: [HashSet<String>; 3] = Default::default();
+    let mut messages = Vec::new();
+    for (source, file) in &base {
+        let ours_name = ours_renames.get(source);
+        let theirs_name = theirs_renames.get(source);
+        // a rename onto a path the other side added is left to the path by path merge
+        let clashes = |name: Option<&String>, other: usize| {
+            name.is_some_and(|name| sides[other].contains_key(name) && !base.contains_key(name))
+        };
+        if (ours_name.is_none() && theirs_name.is_none())
+            || clashes(ours_name, 2)
+            || clashes(theirs_name, 1)
+        {
+            continue;
+        }
+        used[0].insert(source.clone());
+        // `base` is false where a conflict leaves the base version at the old path
+        let mut slot = |path: &String,
+                        ours: Option<&String>,
+                        theirs: Option<&String>,
+                        base: bool,
+                        conflicted| {
+            let mut side = |i: usize, name: Option<&String>| {
+                let name = name?;
+                used[i].insert(name.clone());
+                sides[i].get(name).copied()
+            };
+            slots.push(Slot {
+                path: path.clone(),
+                paths: [
+                    source.clone(),
+                    ours.unwrap_or(path).clone(),
+                    theirs.unwrap_or(path).clone(),
+                ],
+                sides: [base.then_some(*file), side(1, ours), side(2, theirs)],
+                conflicted,
+            });
+        };
+        match (ours_name, theirs_name) {
+            (Some(a), Some(b)) if a == b => slot(a, Some(a), Some(b), true, false),
+            (Some(a), Some(b)) => {
+                messages.push(format!(
+                    "CONFLICT (rename/rename): {} renamed to {} in {} and to {} in {}.",
+                    source, a, labels.ours, b, labels.theirs
+                ));
+                slot(source, None, None, true, true);
+                slot(a, Some(a), None, false, true);
+                slot(b, None, Some(b), false, true);
+            }
+            (Some(name), None) | (None, Some(name))
+                if !sides[1].contains_key(source) && !sides[2].contains_key(source) =>
+            {
+                let (renamed, deleted, side) = match ours_name {
+                    Some(_) => (&labels.ours, &labels.theirs, 1),
+                    None => (&labels.theirs, &labels.ours, 2),
+                };
+                messages.push(format!(
+                    "CONFLICT (rename/delete): {} renamed to {} in {}, but deleted in {}.",
+                    source, name, renamed, deleted
+                ));
+                if sides[side][name].1 != file.1 {
+                    messages.push(format!(
+                        "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  \
+                         Version {} of {} left in tree.",
+                        name, deleted, renamed, renamed, name
+                    ));
+                }
+                match side {
+                    1 => slot(name, Some(name), None, true, true),
+                    _ => slot(name, None, Some(name), true, true),
+                }
+            }
+            (Some(a), None) => slot(a, Some(a), Some(source), true, false),
+            (None, Some(b)) => slot(b, Some(source), Some(b), true, false),
+            (None, None) => unreachable!("unrenamed paths were skipped"),
+        }
+    }
+
+    let mut paths: Vec<&String> = sides.iter().flat_map(|side| side.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let side = |i: usize| match used[i].contains(path) {
+            true => None,
+            false => sides[i].get(path).copied(),
+        };
+        let sides = [side(0), side(1), side(2)];
+        if sides.iter().any(Option::is_some) {
+            slots.push(Slot {
+                path: path.clone(),
+                paths: [path.clone(), path.clone(), path.clone()],
+                sides,
+                conflicted: false,
+            });
+        }
+    }
+    slots.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut merge = TreeMerge {
+        files: BTreeMap::new(),
+        conflicts: Vec::new(),
+        messages: Vec::new(),
+    };
+    for slot in slots {
+        merge_slot(odb, slot, labels, style, &mut merge)?;
+    }
+    merge.messages.splice(0..0, messages);
+
+    // a file where the other side has a directory can't be merged yet
+    for path in merge.files.keys() {
+        let mut dir = path.as_str();
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            anyhow::ensure!(
+                !merge.files.contains_key(parent),
+                "CONFLICT (file/directory): {} is a file on one side and a directory on the other; \
+                 merging it is not supported",
+                parent
+            );
+            dir = parent;
+        }
+    }
+    Ok(merge)
+}
+
+fn merge_slot(
+    odb: &ObjectDatabase,
+    slot: Slot,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+    merge: &mut TreeMerge,
+) -> Result<()> {
+    let Slot {
+        path,
+        paths,
+        sides: [base, ours, theirs],
+        conflicted: rename_conflict,
+    } = slot;
+    let conflicted = |file: Option<(u32, ObjectId)>, merge: &mut TreeMerge| {
+        if let Some(file) = file {
+            merge.files.insert(path.clone(), file);
+        }
+        merge.conflicts.push(Conflict {
+            path: path.clone(),
+            stages: [base, ours, theirs],
+        });
+    };
+    if rename_conflict {
+        conflicted(ours.or(theirs), merge);
+        return Ok(());
+    }
+
+    let (ours_file, theirs_file) = match (ours, theirs) {
+        _ if ours == theirs || base == theirs => {
+            merge.files.extend(ours.map(|file| (path.clone(), file)));
+            return Ok(());
+        }
+        _ if base == ours => {
+            merge.files.extend(theirs.map(|file| (path.clone(), file)));
+            return Ok(());
+        }
+        (None, Some(file)) | (Some(file), None) => {
+            let (deleted, modified) = match ours {
+                None => (&labels.ours, &labels.theirs),
+                Some(_) => (&labels.theirs, &labels.ours),
+            };
+            merge.messages.push(format!(
+                "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  \
+                 Version {} of {} left in tree.",
+                path, deleted, modified, modified, path
+            ));
+            conflicted(Some(file), merge);
+            return Ok(());
+        }
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (None, None) => unreachable!("equal sides were handled"),
+    };
+
+    let base_mode = base.map(|(mode, _)| mode);
+    let mode = if Some(ours_file.0) == base_mode {
+        theirs_file.0
+    } else {
+        ours_file.0
+    };
+    let mode_conflict = ours_file.0 != theirs_file.0
+        && base_mode.is_none_or(|mode| mode != ours_file.0 && mode != theirs_file.0);
+    let base_oid = base.map(|(_, oid)| oid);
+    let oid = if ours_file.1 == theirs_file.1 || base_oid == Some(theirs_file.1) {
+        Some(ours_file.1)
+    } else if base_oid == Some(ours_file.1) {
+        Some(theirs_file.1)
+    } else {
+        None
+    };
+    let kind = if base.is_some() { "content" } else { "add/add" };
+
+    let Some(oid) = oid else {
+        merge.messages.push(format!("Auto-merging {}", path));
+        let mergeable = [ours_file.0, theirs_file.0]
+            .iter()
+            .all(|&mode| mode & 0o170000 == 0o100000)
+            && base_mode.is_none_or(|mode| mode & 0o170000 == 0o100000);
+        let read = |oid: Option<ObjectId>| -> Result<Vec<u8>> {
+            match oid {
+                Some(oid) => Ok(odb.read_raw(&oid)?.1),
+                None => Ok(Vec::new()),
+            }
+        };
+        let contents = [
+            read(base_oid)?,
+            read(Some(ours_file.1))?,
+            read(Some(theirs_file.1))?,
+        ];
+        if !mergeable || contents.iter().any(|content| is_binary(content)) {
+            if mergeable {
+                merge.messages.push(format!(
+                    "warning: Cannot merge binary files: {} ({} vs. {})",
+                    path, labels.ours, labels.theirs
+                ));
+            }
+            merge
+                .messages
+                .push(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+            conflicted(Some((mode, ours_file.1)), merge);
+            return Ok(());
+        }
+
+        // name the paths in the markers when a side renamed the file
+        let labels = match paths[1] == paths[2] {
+            true => labels.clone(),
+            false => MergeLabels {
+                base: format!("{}:{}", labels.base, paths[0]),
+                ours: format!("{}:{}", labels.ours, paths[1]),
+                theirs: format!("{}:{}", labels.theirs, paths[2]),
+            },
+        };
+        let merged = merge_file(&contents[0], &contents[1], &contents[2], &labels, style);
+        let oid = odb.write(ObjectKind::Blob, &merged.content)?;
+        if merged.conflicts > 0 || mode_conflict {
+            merge
+                .messages
+                .push(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+            conflicted(Some((mode, oid)), merge);
+        } else {
+            merge.files.insert(path, (mode, oid));
+        }
+        return Ok(());
+    };
+
+    if mode_conflict || !same_type(ours_file.0, theirs_file.0) {
+        merge
+            .messages
+            .push(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+        conflicted(Some((ours_file.0, oid)), merge);
+    } else {
+        merge.files.insert(path, (mode, oid));
+    }
+    Ok(())
+}
+
+/// Merges the commit `theirs` into `ours`, from their best common ancestor.
+/// With several (after criss-cross merges), the ancestors are first merged
+/// into a virtual one, conflicts and all, like git's recursive strategy.
+pub fn merge_commits(
+    odb: &ObjectDatabase,
+    ours: ObjectId,
+    theirs: ObjectId,
+    ours_label: &str,
+    theirs_label: &str,
+    style: ConflictStyle,
+) -> Result<TreeMerge> {
+    let bases = merge_bases(odb, ours, &[theirs])?;
+    anyhow::ensure!(!bases.is_empty(), "refusing to merge unrelated histories");
+    let base_label = match bases.as_slice() {
+        [base] => base.short(),
+        _ => "merged common ancestors".to_string(),
+    };
+    let labels = MergeLabels {
+        base: base_label,
+        ours: ours_label.to_string(),
+        theirs: theirs_label.to_string(),
+    };
+    let base = virtual_base(odb, &bases, style)?;
+    merge_trees(
+        odb,
+        base,
+        odb.read_commit(&ours)?.tree,
+        odb.read_commit(&theirs)?.tree,
+        &labels,
+        style,
+    )
+}
+
+/// The tree to use as the base when merging from all of `bases`.
+fn virtual_base(
+    odb: &ObjectDatabase,
+    bases: &[ObjectId],
+    style: ConflictStyle,
+) -> Result<ObjectId> {
+    let mut tree = odb.read_commit(&bases[0])?.tree;
+    for (i, base) in bases.iter().enumerate().skip(1) {
+        // the merged bases so far act like a commit with all of them as parents
+        let ancestors = merge_bases(odb, *base, &bases[..i])?;
+        let ancestor = match ancestors.is_empty() {
+            true => write_tree_from_paths(odb, &BTreeMap::new())?,
+            false => virtual_base(odb, &ancestors, style)?,
+        };
+        let labels = MergeLabels {
+            base: "merged common ancestors".to_string(),
+            ours: "Temporary merge branch 1".to_string(),
+            theirs: "Temporary merge branch 2".to_string(),
+        };
+        let merged = merge_trees(
+            odb,
+            ancestor,
+            tree,
+            odb.read_commit(base)?.tree,
+            &labels,
+            style,
+        )
+        .context("couldn't merge the merge bases")?;
+        tree = write_tree_from_paths(odb, &merged.files)?;
+    }
+    Ok(tree)
+}
@@ -1,8 +1,47 @@
-// TODO: refs, config, remote, object, pack,
-// TODO: branches, tags, diff, log, rebase, merge, hooks
-
+// TODO: remote,
+// TODO: hooks
 
+mod blob;
+mod commit;
+mod config;
+mod diff;
+mod fsck;
+mod graph;
 mod index;
+mod merge;
+mod object;
+mod object_id;
+mod pack;
+mod pack_writer;
+mod refs;
+mod revision;
+mod status;
+mod tag;
+mod tree;
+mod tree_diff;
+mod walk;
+mod worktree;
 
+pub use blob::Blob;
+pub use commit::{parse_approxidate, Commit, Signature};
+pub use config::Config;
+pub use diff::{diff_lines, is_binary, split_lines, unified_diff, DiffAlgorithm, Edit, Hunk};
+pub use fsck::{check_object, check_root_tree, FsckProblem, Severity};
+pub use graph::{ahead_behind, is_ancestor, merge_bases, reachable, reachable_objects};
 pub use index::Index;
-pub use index::IndexEntry;
+pub use index::{Conflict, IndexEntry};
+pub use merge::{
+    merge_commits, merge_file, merge_trees, ConflictStyle, FileMerge, MergeLabels, TreeMerge,
+};
+pub use object::{Object, ObjectDatabase, ObjectKind, MIN_ABBREV};
+pub use object_id::ObjectId;
+pub use pack::{Pack, PackIndex};
+pub use pack_writer::{write_pack, PackObject, PackOptions};
+pub use refs::{check_ref_format, shorten_ref_name, LockFile, RefStore, RefTarget};
+pub use revision::{find_in_tree, peel, peel_tags, previous_branch, resolve_revision};
+pub use status::{Change, Status};
+pub use tag::Tag;
+pub use tree::{write_tree_from_paths, Tree, TreeEntry};
+pub use tree_diff::{detect_renames, diff_files, diff_trees, DiffEntry, RenameOptions};
+pub use walk::{RevWalk, Sort, WalkedCommit};
+pub use worktree::{checkout_file, check_file, check_path, flatten_tree, read_worktree_file, remove_file, FileState};
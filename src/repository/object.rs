@@ -0,0 +1,378 @@
+use anyhow::{Context, Result};
+use crypto_hash::{digest, Algorithm};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::cell::OnceCell;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::{Blob, Commit, ObjectId, Pack, Tag, Tree};
+
+/// The shortest abbreviated object id accepted, as in git.
+pub const MIN_ABBREV: usize = 4;
+
+/// The four kinds of object git stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+/// A parsed object read from the database.
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+/// Object storage under `ugit/objects`: loose objects, each zlib compressed
+/// as `<kind> <size>\0<content>` at `objects/<2 hex>/<38 hex>`, and packs in
+/// `objects/pack`, opened the first time they're needed.
+pub struct ObjectDatabase {
+    objects_dir: PathBuf,
+    packs: OnceCell<Vec<Pack>>,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tag => "tag",
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ObjectKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "blob" => Ok(ObjectKind::Blob),
+            "tree" => Ok(ObjectKind::Tree),
+            "commit" => Ok(ObjectKind::Commit),
+            "tag" => Ok(ObjectKind::Tag),
+            _ => anyhow::bail!("unknown object type: {}", s),
+        }
+    }
+}
+
+impl Object {
+    pub fn parse(kind: ObjectKind, content: Vec<u8>) -> Result<Object> {
+        Ok(match kind {
+            ObjectKind::Blob => Object::Blob(Blob { content }),
+            ObjectKind::Tree => Object::Tree(Tree::parse(&content)?),
+            ObjectKind::Commit => Object::Commit(Commit::parse(&content)?),
+            ObjectKind::Tag => Object::Tag(Tag::parse(&content)?),
+        })
+    }
+
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Object::Blob(_) => ObjectKind::Blob,
+            Object::Tree(_) => ObjectKind::Tree,
+            Object::Commit(_) => ObjectKind::Commit,
+            Object::Tag(_) => ObjectKind::Tag,
+        }
+    }
+}
+
+impl Default for ObjectDatabase {
+    fn default() -> Self {
+        ObjectDatabase::new("./ugit/objects")
+    }
+}
+
+impl ObjectDatabase {
+    pub fn new(objects_dir: impl Into<PathBuf>) -> ObjectDatabase {
+        ObjectDatabase {
+            objects_dir: objects_dir.into(),
+            packs: OnceCell::new(),
+        }
+    }
+
+    /// Computes the id an object would have, without storing it.
+    pub fn hash(kind: ObjectKind, content: &[u8]) -> ObjectId {
+        let header = format!("{} {}\0", kind, content.len());
+        let data = [header.as_bytes(), content].concat();
+        ObjectId::from_bytes(&digest(Algorithm::SHA1, &data)).expect("SHA-1 digests are 20 bytes")
+    }
+
+    /// Where the loose copy of `oid` is, or would be.
+    pub fn object_path(&self, oid: &ObjectId) -> PathBuf {
+        let hash = oid.to_hex();
+        self.objects_dir.join(&hash[..2]).join(&hash[2..])
+    }
+
+    pub fn pack_dir(&self) -> PathBuf {
+        self.objects_dir.join("pack")
+    }
+
+    /// Every loose object, in no particular order.
+    pub fn loose_objects(&self) -> Result<Vec<ObjectId>> {
+        let mut found = Vec::new();
+        let Ok(dirs) = fs::read_dir(&self.objects_dir) else {
+            return Ok(found);
+        };
+        for dir in dirs {
+            let dir = dir.context("couldn't read ugit/objects")?;
+            let prefix = dir.file_name().to_string_lossy().into_owned();
+            if prefix.len() != 2 || !dir.path().is_dir() {
+                continue; // pack/ and info/
+            }
+            for entry in fs::read_dir(dir.path()).context("couldn't read ugit/objects")? {
+                let name = entry.context("couldn't read ugit/objects")?.file_name();
+                // skips temporary files left by interrupted writes
+                if let Ok(oid) = ObjectId::from_hex(&format!("{}{}", prefix, name.to_string_lossy())) {
+                    found.push(oid);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Whether any pack holds `oid`.
+    pub fn is_packed(&self, oid: &ObjectId) -> Result<bool> {
+        Ok(self.packs()?.iter().any(|pack| pack.index().contains(oid)))
+    }
+
+    /// Expands a full or abbreviated hex id into an `ObjectId`.
+    ///
+    /// Abbreviations must be at least `MIN_ABBREV` digits and match exactly
+    /// one object, loose or packed; an ambiguous prefix is an error listing
+    /// every candidate.
+    pub fn resolve(&self, hash: &str) -> Result<ObjectId> {
+        let prefix = hash.to_ascii_lowercase();
+        anyhow::ensure!(
+            !prefix.is_empty() && prefix.bytes().all(|b| b.is_ascii_hexdigit()),
+            "not a valid object name: {}",
+            hash
+        );
+        if prefix.len() == 40 {
+            return ObjectId::from_hex(&prefix);
+        }
+        anyhow::ensure!(
+            (MIN_ABBREV..40).contains(&prefix.len()),
+            "short object ID {} must be between {} and 40 characters",
+            hash,
+            MIN_ABBREV
+        );
+
+        let mut candidates = self.find_prefix(&prefix)?;
+        candidates.sort();
+        candidates.dedup();
+
+        match candidates.as_slice() {
+            [] => anyhow::bail!("not a valid object name: {}", hash),
+            [oid] => Ok(*oid),
+            _ => {
+                // abbreviate the candidates just enough to tell them apart
+                let mut len = 7;
+                while candidates
+                    .windows(2)
+                    .any(|w| w[0].abbrev(len) == w[1].abbrev(len))
+                {
+                    len += 1;
+                }
+                let mut message = format!(
+                    "short object ID {} is ambiguous\nhint: The candidates are:",
+                    hash
+                );
+                for oid in &candidates {
+                    let kind = match self.read_header(oid) {
+                        Ok((kind, _)) => kind.to_string(),
+                        Err(_) => "unknown".to_string(),
+                    };
+                    message.push_str(&format!("\nhint:   {} {}", oid.abbrev(len), kind));
+                }
+                anyhow::bail!(message)
+            }
+        }
+    }
+
+    /// All loose and packed ids starting with the lowercase hex `prefix`,
+    /// which must be at least two digits long.
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        let mut found = Vec::new();
+
+        let fanout_dir = self.objects_dir.join(&prefix[..2]);
+        if let Ok(entries) = fs::read_dir(&fanout_dir) {
+            for entry in entries {
+                let entry = entry.context("Couldn't get entry in object directory")?;
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                if file_name.len() == 38 && file_name.starts_with(&prefix[2..]) {
+                    if let Ok(oid) = ObjectId::from_hex(&format!("{}{}", &prefix[..2], file_name)) {
+                        found.push(oid);
+                    }
+                }
+            }
+        }
+
+        for pack in self.packs()? {
+            found.extend(pack.index().find_prefix(prefix));
+        }
+
+        Ok(found)
+    }
+
+    pub fn exists(&self, oid: &ObjectId) -> bool {
+        self.object_path(oid).exists() || self.find_packed(oid).is_ok_and(|found| found.is_some())
+    }
+
+    /// The packs in `objects/pack`.
+    pub fn packs(&self) -> Result<&[Pack]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let packs = Pack::open_all(&self.pack_dir())?;
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    /// The pack holding `oid` and where in it, unless it's only loose (or
+    /// missing).
+    fn find_packed(&self, oid: &ObjectId) -> Result<Option<(&Pack, u64)>> {
+        if self.object_path(oid).exists() {
+            return Ok(None);
+        }
+        Ok(self
+            .packs()?
+            .iter()
+            .find_map(|pack| Some((pack, pack.index().offset(oid)?))))
+    }
+
+    /// Opens an object and parses its header, leaving the reader at the content.
+    fn open(&self, oid: &ObjectId) -> Result<(ObjectKind, usize, impl BufRead)> {
+        let path = self.object_path(oid);
+        let f = fs::File::open(&path)
+            .with_context(|| format!("couldn't open object file: {}", path.display()))?;
+        let mut z = BufReader::new(ZlibDecoder::new(f));
+
+        let mut buf = Vec::new();
+        z.read_until(0, &mut buf)
+            .context("read header from ugit/objects")?;
+        let Some(header) = buf.strip_suffix(&[0]) else {
+            anyhow::bail!("ugit/objects header isn't NUL terminated")
+        };
+        let header =
+            std::str::from_utf8(header).context("ugit/objects file header isn't valid UTF-8")?;
+        let Some((kind, size)) = header.split_once(' ') else {
+            anyhow::bail!("malformed ugit/objects header: '{header}'")
+        };
+        let size: usize = size.parse().context("couldn't parse size")?;
+
+        Ok((kind.parse()?, size, z))
+    }
+
+    /// Reads only an object's type and size.
+    pub fn read_header(&self, oid: &ObjectId) -> Result<(ObjectKind, usize)> {
+        if let Some((pack, offset)) = self.find_packed(oid)? {
+            return pack
+                .read_header(self, offset)
+                .with_context(|| format!("couldn't read packed object {}", oid));
+        }
+        let (kind, size, _) = self.open(oid)?;
+        Ok((kind, size))
+    }
+
+    /// Reads an object's type and raw content.
+    pub fn read_raw(&self, oid: &ObjectId) -> Result<(ObjectKind, Vec<u8>)> {
+        if let Some((pack, offset)) = self.find_packed(oid)? {
+            return pack
+                .read(self, offset)
+                .with_context(|| format!("couldn't read packed object {}", oid));
+        }
+        let (kind, size, mut z) = self.open(oid)?;
+        let mut content = Vec::with_capacity(size);
+        z.read_to_end(&mut content)
+            .context("read content from ugit/objects")?;
+        anyhow::ensure!(
+            content.len() == size,
+            "object {} is {} bytes but its header says {}",
+            oid,
+            content.len(),
+            size
+        );
+        Ok((kind, content))
+    }
+
+    pub fn read(&self, oid: &ObjectId) -> Result<Object> {
+        let (kind, content) = self.read_raw(oid)?;
+        Object::parse(kind, content).with_context(|| format!("couldn't parse {} {}", kind, oid))
+    }
+
+    pub fn read_tree(&self, oid: &ObjectId) -> Result<Tree> {
+        match self.read(oid)? {
+            Object::Tree(tree) => Ok(tree),
+            object => anyhow::bail!("{} is a {}, not a tree", oid, object.kind()),
+        }
+    }
+
+    pub fn read_commit(&self, oid: &ObjectId) -> Result<Commit> {
+        match self.read(oid)? {
+            Object::Commit(commit) => Ok(commit),
+            object => anyhow::bail!("{} is a {}, not a commit", oid, object.kind()),
+        }
+    }
+
+    pub fn read_tag(&self, oid: &ObjectId) -> Result<Tag> {
+        match self.read(oid)? {
+            Object::Tag(tag) => Ok(tag),
+            object => anyhow::bail!("{} is a {}, not a tag", oid, object.kind()),
+        }
+    }
+
+    /// Stores an object and returns its hash. Objects that already exist
+    /// aren't rewritten.
+    pub fn write(&self, kind: ObjectKind, content: &[u8]) -> Result<ObjectId> {
+        let oid = ObjectDatabase::hash(kind, content);
+        if self.exists(&oid) {
+            return Ok(oid);
+        }
+        self.write_loose(kind, content)
+    }
+
+    /// Stores an object as a loose file even if a pack already has it.
+    pub fn write_loose(&self, kind: ObjectKind, content: &[u8]) -> Result<ObjectId> {
+        let oid = ObjectDatabase::hash(kind, content);
+        let object_path = self.object_path(&oid);
+
+        let object_dir = object_path
+            .parent()
+            .expect("object path has a fan-out directory");
+        fs::create_dir_all(object_dir).with_context(|| {
+            format!("couldn't create object directory: {}", object_dir.display())
+        })?;
+
+        // Write to a temporary file first so readers never see a partial object
+        let tmp_path = object_dir.join(format!("tmp_obj_{}", std::process::id()));
+        let file = fs::File::create(&tmp_path)
+            .with_context(|| format!("couldn't create object file: {}", tmp_path.display()))?;
+        let mut z = ZlibEncoder::new(file, Compression::default());
+        z.write_all(format!("{} {}\0", kind, content.len()).as_bytes())
+            .and_then(|_| z.write_all(content))
+            .with_context(|| format!("write data to object file: {}", tmp_path.display()))?;
+        z.finish()
+            .with_context(|| format!("finishing writing to object file: {}", tmp_path.display()))?;
+
+        fs::rename(&tmp_path, &object_path).with_context(|| {
+            format!("couldn't move object into place: {}", object_path.display())
+        })?;
+
+        Ok(oid)
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// A full 20-byte SHA-1 object id.
+///
+/// Parsing only accepts all 40 hex digits; abbreviated ids have to be
+/// resolved against the object database instead.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ObjectId([u8; 20]);
+
+impl ObjectId {
+    /// The all-zero id git uses for "no object", e.g. a ref that doesn't exist yet.
+    pub const NULL: ObjectId = ObjectId([0; 20]);
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjectId> {
+        let bytes: [u8; 20] = bytes
+            .try_into()
+            .with_context(|| format!("object id must be 20 bytes, got {}", bytes.len()))?;
+        Ok(ObjectId(bytes))
+    }
+
+    pub fn from_hex(hex: &str) -> Result<ObjectId> {
+        anyhow::ensure!(
+            hex.len() == 40,
+            "object id must be 40 hex characters: '{}'",
+            hex
+        );
+        let mut bytes = [0u8; 20];
+        hex::decode_to_slice(hex, &mut bytes)
+            .with_context(|| format!("invalid object id: '{}'", hex))?;
+        Ok(ObjectId(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// The first `len` hex digits of the id.
+    pub fn abbrev(&self, len: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(len);
+        hex
+    }
+
+    /// The id abbreviated to git's default 7 hex digits.
+    pub fn short(&self) -> String {
+        self.abbrev(7)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == [0; 20]
+    }
+}
+
+impl From<[u8; 20]> for ObjectId {
+    fn from(bytes: [u8; 20]) -> Self {
+        ObjectId(bytes)
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ObjectId::from_hex(s)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
@@ -0,0 +1,553 @@
+use anyhow::{Context, Result};
+use byteorder::{BigEndian, ByteOrder};
+use flate2::bufread::ZlibDecoder;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::{ObjectDatabase, ObjectId, ObjectKind};
+
+/// How many bytes of inflated delta bases each pack keeps around, like
+/// git's `core.deltaBaseCacheLimit`.
+const DELTA_BASE_CACHE_LIMIT: usize = 96 << 20;
+
+/// A version 2 pack index (`objects/pack/pack-*.idx`).
+///
+/// Layout: `\377tOc`, version 2, a 256 entry fan-out table of cumulative
+/// counts by first byte, the sorted object ids, a CRC32 per object, a 31-bit
+/// pack offset per object (or, with the top bit set, an index into a table
+/// of 64-bit offsets that follows), then the pack's and the index's SHA-1.
+pub struct PackIndex {
+    path: PathBuf,
+    fanout: [u32; 256],
+    oids: Vec<ObjectId>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+    pack_checksum: ObjectId,
+}
+
+/// A packfile (`objects/pack/pack-*.pack`) along with its index.
+///
+/// Objects are stored one after another, each a type and size header
+/// followed by zlib data. Deltified objects name their base either by its
+/// offset earlier in the pack (`OFS_DELTA`) or by its id (`REF_DELTA`).
+pub struct Pack {
+    index: PackIndex,
+    file: fs::File,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+/// What a pack entry's header says it holds.
+enum EntryKind {
+    Object(ObjectKind),
+    OfsDelta(u64),
+    RefDelta(ObjectId),
+}
+
+struct Entry {
+    kind: EntryKind,
+    size: usize,      // inflated size of the object or delta
+    data_offset: u64, // where its zlib data starts
+}
+
+/// Inflated delta bases by pack offset, evicting the least recently used
+/// once they add up to more than `DELTA_BASE_CACHE_LIMIT`.
+#[derive(Default)]
+struct DeltaBaseCache {
+    entries: HashMap<u64, (ObjectKind, Rc<Vec<u8>>, u64)>, // kind, content, last use
+    size: usize,
+    clock: u64,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> Result<PackIndex> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("couldn't read pack index: {}", path.display()))?;
+        anyhow::ensure!(
+            bytes.len() >= 8 + 256 * 4 && bytes[..4] == *b"\xfftOc",
+            "{} is not a version 2 pack index",
+            path.display()
+        );
+        let version = BigEndian::read_u32(&bytes[4..8]);
+        anyhow::ensure!(version == 2, "unsupported pack index version: {}", version);
+
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = BigEndian::read_u32(&bytes[8 + i * 4..12 + i * 4]);
+        }
+
+        // each count includes the ones before, and the lookups slice by them
+        anyhow::ensure!(
+            fanout.windows(2).all(|pair| pair[0] <= pair[1]),
+            "pack index {} has a corrupt fanout table",
+            path.display()
+        );
+        let count = fanout[255] as usize;
+        let oid_start = 8 + 256 * 4;
+        let crc_start = oid_start + count * 20;
+        let offset_start = crc_start + count * 4;
+        let large_start = offset_start + count * 4;
+        anyhow::ensure!(
+            bytes.len() >= large_start + 40,
+            "pack index {} is truncated",
+            path.display()
+        );
+        let oids = bytes[oid_start..crc_start]
+            .chunks_exact(20)
+            .map(ObjectId::from_bytes)
+            .collect::<Result<Vec<_>>>()?;
+        let crcs = bytes[crc_start..offset_start]
+            .chunks_exact(4)
+            .map(BigEndian::read_u32)
+            .collect();
+
+        let large_count = (bytes.len() - large_start - 40) / 8;
+        let mut offsets = Vec::with_capacity(count);
+        for raw in bytes[offset_start..large_start].chunks_exact(4) {
+            let raw = BigEndian::read_u32(raw);
+            offsets.push(match raw & 0x8000_0000 {
+                0 => raw as u64,
+                _ => {
+                    let large = (raw & 0x7fff_ffff) as usize;
+                    anyhow::ensure!(
+                        large < large_count,
+                        "pack index {} has a bad large offset",
+                        path.display()
+                    );
+                    BigEndian::read_u64(&bytes[large_start + large * 8..])
+                }
+            });
+        }
+        let trailer = bytes.len() - 40;
+        let pack_checksum = ObjectId::from_bytes(&bytes[trailer..trailer + 20])?;
+
+        Ok(PackIndex {
+            path: path.to_path_buf(),
+            fanout,
+            oids,
+            crcs,
+            offsets,
+            pack_checksum,
+        })
+    }
+
+    /// Opens every `.idx` file in `objects/pack`.
+    pub fn open_all(pack_dir: &Path) -> Result<Vec<PackIndex>> {
+        let Ok(entries) = fs::read_dir(pack_dir) else {
+            return Ok(Vec::new()); // no pack directory, no packs
+        };
+        let mut indexes = Vec::new();
+        for entry in entries {
+            let path = entry.context("couldn't read pack directory")?.path();
+            if path.extension().is_some_and(|ext| ext == "idx") {
+                indexes.push(PackIndex::open(&path)?);
+            }
+        }
+        Ok(indexes)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.oids.len()
+    }
+
+    /// The ids of every object in the pack, sorted.
+    pub fn oids(&self) -> &[ObjectId] {
+        &self.oids
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.oids.is_empty()
+    }
+
+    /// Position of `oid` in the sorted id table.
+    pub fn position(&self, oid: &ObjectId) -> Option<usize> {
+        let (start, end) = self.fanout_range(oid.as_bytes()[0]);
+        self.oids[start..end]
+            .binary_search(oid)
+            .ok()
+            .map(|i| start + i)
+    }
+
+    pub fn contains(&self, oid: &ObjectId) -> bool {
+        self.position(oid).is_some()
+    }
+
+    /// Where `oid` starts in the pack.
+    pub fn offset(&self, oid: &ObjectId) -> Option<u64> {
+        self.position(oid).map(|i| self.offsets[i])
+    }
+
+    /// The CRC32 of `oid`'s raw, still compressed, entry in the pack.
+    pub fn crc32(&self, oid: &ObjectId) -> Option<u32> {
+        self.position(oid).map(|i| self.crcs[i])
+    }
+
+    /// All ids in the pack whose hex form starts with `prefix`.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let Ok(first) = u8::from_str_radix(&prefix[..2], 16) else {
+            return Vec::new();
+        };
+        let (start, end) = self.fanout_range(first);
+        self.oids[start..end]
+            .iter()
+            .filter(|oid| oid.to_hex().starts_with(prefix))
+            .copied()
+            .collect()
+    }
+
+    /// The slice of the id table holding ids that start with `first_byte`.
+    fn fanout_range(&self, first_byte: u8) -> (usize, usize) {
+        let end = self.fanout[first_byte as usize] as usize;
+        let start = match first_byte {
+            0 => 0,
+            b => self.fanout[b as usize - 1] as usize,
+        };
+        (start, end)
+    }
+}
+
+impl Pack {
+    /// Opens the pack that `index` describes, checking that they belong
+    /// together.
+    pub fn open(index: PackIndex) -> Result<Pack> {
+        let path = index.path.with_extension("pack");
+        let mut file = fs::File::open(&path)
+            .with_context(|| format!("couldn't open pack: {}", path.display()))?;
+
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header)
+            .with_context(|| format!("couldn't read pack header: {}", path.display()))?;
+        anyhow::ensure!(&header[..4] == b"PACK", "{} is not a pack", path.display());
+        let version = BigEndian::read_u32(&header[4..8]);
+        anyhow::ensure!(
+            version == 2 || version == 3,
+            "unsupported pack version: {}",
+            version
+        );
+        let count = BigEndian::read_u32(&header[8..12]) as usize;
+        anyhow::ensure!(
+            count == index.len(),
+            "{} has {} objects but its index lists {}",
+            path.display(),
+            count,
+            index.len()
+        );
+
+        let mut checksum = [0u8; 20];
+        file.seek(SeekFrom::End(-20))
+            .and_then(|_| file.read_exact(&mut checksum))
+            .with_context(|| format!("couldn't read pack checksum: {}", path.display()))?;
+        anyhow::ensure!(
+            ObjectId::from_bytes(&checksum)? == index.pack_checksum,
+            "{} doesn't match its index",
+            path.display()
+        );
+
+        Ok(Pack {
+            index,
+            file,
+            cache: RefCell::default(),
+        })
+    }
+
+    /// Opens every pack in `objects/pack` that has an index.
+    pub fn open_all(pack_dir: &Path) -> Result<Vec<Pack>> {
+        PackIndex::open_all(pack_dir)?
+            .into_iter()
+            .map(Pack::open)
+            .collect()
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// Reads the type and size of the object at `offset`. Following a delta
+    /// to find the type only reads headers; the size is the delta's target
+    /// size. `odb` looks up `REF_DELTA` bases that live outside this pack.
+    pub fn read_header(&self, odb: &ObjectDatabase, offset: u64) -> Result<(ObjectKind, usize)> {
+        let entry = self.entry(offset)?;
+        let size = match entry.kind {
+            EntryKind::Object(_) => entry.size,
+            _ => {
+                // the target size is the second varint at the start of the delta
+                let mut start = Vec::new();
+                self.decoder(&entry)?
+                    .take(20)
+                    .read_to_end(&mut start)
+                    .context("couldn't inflate delta header")?;
+                let mut pos = 0;
+                read_size(&start, &mut pos)?;
+                read_size(&start, &mut pos)?
+            }
+        };
+
+        let mut entry = entry;
+        let mut visited = HashSet::from([offset]);
+        let kind = loop {
+            let base = match entry.kind {
+                EntryKind::Object(kind) => break kind,
+                EntryKind::OfsDelta(base) => base,
+                EntryKind::RefDelta(base) => match self.index.offset(&base) {
+                    Some(base) => base,
+                    None => break odb.read_header(&base)?.0,
+                },
+            };
+            anyhow::ensure!(visited.insert(base), "delta chain cycle at offset {}", offset);
+            entry = self.entry(base)?;
+        };
+        Ok((kind, size))
+    }
+
+    /// Reads the object at `offset`, applying its chain of deltas to the
+    /// base at the end. Bases are cached, since objects deltified against
+    /// the same base tend to be read together.
+    pub fn read(&self, odb: &ObjectDatabase, offset: u64) -> Result<(ObjectKind, Vec<u8>)> {
+        let mut deltas = Vec::new();
+        let mut visited = HashSet::from([offset]);
+        let mut current = offset;
+        // the base of the chain, and its offset if it's in this pack
+        let (kind, mut content, mut base_offset) = loop {
+            if let Some((kind, content)) = self.cache.borrow_mut().get(current) {
+                break (kind, content, None);
+            }
+            let entry = self.entry(current)?;
+            let base = match entry.kind {
+                EntryKind::Object(kind) => {
+                    break (kind, Rc::new(self.inflate(&entry)?), Some(current))
+                }
+                EntryKind::OfsDelta(base) => Some(base),
+                EntryKind::RefDelta(base) => match self.index.offset(&base) {
+                    Some(base) => Some(base),
+                    None => {
+                        deltas.push((current, self.inflate(&entry)?));
+                        let (kind, content) = odb.read_raw(&base)?;
+                        break (kind, Rc::new(content), None);
+                    }
+                },
+            };
+            deltas.push((current, self.inflate(&entry)?));
+            current = base.expect("deltas in this pack have a base offset");
+            // REF_DELTAs can name each other; OFS_DELTAs only point backwards
+            anyhow::ensure!(
+                visited.insert(current),
+                "delta chain cycle at offset {}",
+                offset
+            );
+        };
+
+        while let Some((offset, delta)) = deltas.pop() {
+            if let Some(base_offset) = base_offset {
+                self.cache
+                    .borrow_mut()
+                    .insert(base_offset, kind, Rc::clone(&content));
+            }
+            content = Rc::new(
+                apply_delta(&content, &delta)
+                    .with_context(|| format!("bad delta at offset {} in pack", offset))?,
+            );
+            base_offset = Some(offset);
+        }
+        Ok((kind, Rc::unwrap_or_clone(content)))
+    }
+
+    /// Parses the entry header at `offset`.
+    fn entry(&self, offset: u64) -> Result<Entry> {
+        let mut header = Vec::with_capacity(32);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.take(32).read_to_end(&mut header))
+            .with_context(|| format!("couldn't read pack entry at offset {}", offset))?;
+        let byte = |pos: usize| {
+            header
+                .get(pos)
+                .copied()
+                .with_context(|| format!("truncated pack entry at offset {}", offset))
+        };
+
+        let mut c = byte(0)?;
+        let code = (c >> 4) & 7;
+        let mut size = (c & 0x0f) as usize;
+        let mut shift = 4;
+        let mut pos = 1;
+        while c & 0x80 != 0 {
+            c = byte(pos)?;
+            pos += 1;
+            anyhow::ensure!(shift < 64, "pack entry size at offset {} overflows", offset);
+            size |= ((c & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let kind = match code {
+            1 => EntryKind::Object(ObjectKind::Commit),
+            2 => EntryKind::Object(ObjectKind::Tree),
+            3 => EntryKind::Object(ObjectKind::Blob),
+            4 => EntryKind::Object(ObjectKind::Tag),
+            6 => {
+                // big-endian base-128, with one added per continuation byte
+                let mut c = byte(pos)?;
+                pos += 1;
+                let mut distance = (c & 0x7f) as u64;
+                while c & 0x80 != 0 {
+                    c = byte(pos)?;
+                    pos += 1;
+                    distance = distance
+                        .checked_add(1)
+                        .filter(|distance| distance.leading_zeros() >= 7)
+                        .with_context(|| format!("bad delta base offset at offset {}", offset))?
+                        << 7
+                        | (c & 0x7f) as u64;
+                }
+                anyhow::ensure!(
+                    distance > 0 && distance <= offset,
+                    "bad delta base offset at offset {}",
+                    offset
+                );
+                EntryKind::OfsDelta(offset - distance)
+            }
+            7 => {
+                let base = header
+                    .get(pos..pos + 20)
+                    .with_context(|| format!("truncated pack entry at offset {}", offset))?;
+                pos += 20;
+                EntryKind::RefDelta(ObjectId::from_bytes(base)?)
+            }
+            code => anyhow::bail!("unknown pack entry type {} at offset {}", code, offset),
+        };
+        Ok(Entry {
+            kind,
+            size,
+            data_offset: offset + pos as u64,
+        })
+    }
+
+    fn decoder(&self, entry: &Entry) -> Result<ZlibDecoder<BufReader<&fs::File>>> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(entry.data_offset))
+            .context("couldn't seek in pack")?;
+        Ok(ZlibDecoder::new(BufReader::new(file)))
+    }
+
+    fn inflate(&self, entry: &Entry) -> Result<Vec<u8>> {
+        // grown as it inflates, so a corrupt size can't make us allocate it up front
+        let mut content = Vec::new();
+        self.decoder(entry)?
+            .take(entry.size as u64 + 1)
+            .read_to_end(&mut content)
+            .with_context(|| format!("couldn't inflate pack entry in {}", self.index.path.display()))?;
+        anyhow::ensure!(
+            content.len() == entry.size,
+            "pack entry is {} bytes but its header says {}",
+            content.len(),
+            entry.size
+        );
+        Ok(content)
+    }
+}
+
+impl DeltaBaseCache {
+    fn get(&mut self, offset: u64) -> Option<(ObjectKind, Rc<Vec<u8>>)> {
+        self.clock += 1;
+        let (kind, content, used) = self.entries.get_mut(&offset)?;
+        *used = self.clock;
+        Some((*kind, Rc::clone(content)))
+    }
+
+    fn insert(&mut self, offset: u64, kind: ObjectKind, content: Rc<Vec<u8>>) {
+        if content.len() > DELTA_BASE_CACHE_LIMIT || self.entries.contains_key(&offset) {
+            return;
+        }
+        self.clock += 1;
+        self.size += content.len();
+        self.entries.insert(offset, (kind, content, self.clock));
+        while self.size > DELTA_BASE_CACHE_LIMIT {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(offset, _)| *offset)
+                .expect("the cache is over its limit, so it isn't empty");
+            if let Some((_, content, _)) = self.entries.remove(&oldest) {
+                self.size -= content.len();
+            }
+        }
+    }
+}
+
+/// Rebuilds an object from `base` and a git delta: the base and target
+/// sizes as varints, then instructions that either copy a range of the base
+/// (high bit set) or insert the next 1-127 bytes of the delta.
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    anyhow::ensure!(
+        base_size == base.len(),
+        "delta expects a {} byte base, not {}",
+        base_size,
+        base.len()
+    );
+    let target_size = read_size(delta, &mut pos)?;
+
+    // a corrupt size mustn't make us allocate far more than the delta could build
+    let mut out = Vec::with_capacity(target_size.min(base.len() + delta.len()));
+    while let Some(&op) = delta.get(pos) {
+        pos += 1;
+        if op & 0x80 != 0 {
+            // which of the offset's 4 and the size's 3 little-endian bytes follow
+            let mut fields = [0usize; 2];
+            for (field, (bits, first)) in fields.iter_mut().zip([(4, 0x01), (3, 0x10)]) {
+                for i in 0..bits {
+                    if op & (first << i) != 0 {
+                        let byte = *delta.get(pos).context("truncated delta copy")?;
+                        *field |= (byte as usize) << (8 * i);
+                        pos += 1;
+                    }
+                }
+            }
+            let [start, size] = fields;
+            let size = if size == 0 { 0x10000 } else { size };
+            let range = base
+                .get(start..start + size)
+                .context("delta copies past the end of its base")?;
+            out.extend_from_slice(range);
+        } else if op != 0 {
+            let data = delta
+                .get(pos..pos + op as usize)
+                .context("truncated delta insert")?;
+            out.extend_from_slice(data);
+            pos += op as usize;
+        } else {
+            anyhow::bail!("delta uses reserved instruction 0");
+        }
+    }
+    anyhow::ensure!(
+        out.len() == target_size,
+        "delta produced {} bytes but promised {}",
+        out.len(),
+        target_size
+    );
+    Ok(out)
+}
+
+/// Reads a little-endian base-128 size from the start of a delta.
+fn read_size(delta: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos).context("truncated delta header")?;
+        *pos += 1;
+        anyhow::ensure!(shift < 64, "delta size overflows");
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::{Context, Result};
+use crypto_hash::{digest, Algorithm, Hasher};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::{ObjectDatabase, ObjectId, ObjectKind};
+
+/// Blocks of the delta base indexed when looking for matches; any common
+/// run of twice this length is found.
+const BLOCK: usize = 16;
+/// How many places in the base a block is remembered at.
+const MAX_CANDIDATES: usize = 16;
+
+/// An object to put in a pack, with the path it was reached by. Objects at
+/// similar paths are tried as delta bases for each other first.
+pub struct PackObject {
+    pub oid: ObjectId,
+    pub path: String,
+}
+
+/// How hard `write_pack` looks for deltas.
+#[derive(Debug, Clone, Copy)]
+pub struct PackOptions {
+    /// How many of the preceding objects (by type, path and size) are
+    /// tried as the base of each object; 0 turns deltas off.
+    pub window: usize,
+    /// The longest chain of deltas an object may need to be rebuilt.
+    pub depth: usize,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            window: 10,
+            depth: 50,
+        }
+    }
+}
+
+/// An object's place in the delta search.
+struct Slot {
+    kind: ObjectKind,
+    size: usize,
+    name_hash: u32,
+    depth: usize,
+    delta: Option<(usize, Vec<u8>)>, // base slot and the delta against it
+}
+
+/// Writes `objects` to `<base_name>-<checksum>.pack` along with its `.idx`,
+/// returning the checksum that names them.
+///
+/// Objects are written in the order given, except that a delta's base
+/// always comes before it, so every delta can be an `OFS_DELTA`.
+pub fn write_pack(
+    odb: &ObjectDatabase,
+    objects: &[PackObject],
+    options: PackOptions,
+    base_name: &Path,
+) -> Result<ObjectId> {
+    let mut seen = HashSet::new();
+    let objects: Vec<&PackObject> = objects
+        .iter()
+        .filter(|object| seen.insert(object.oid))
+        .collect();
+    let mut slots = Vec::with_capacity(objects.len());
+    for object in &objects {
+        let (kind, size) = odb.read_header(&object.oid)?;
+        slots.push(Slot {
+            kind,
+            size,
+            name_hash: name_hash(&object.path),
+            depth: 0,
+            delta: None,
+        });
+    }
+    find_deltas(odb, &objects, &mut slots, options)?;
+
+    let dir = base_name.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)
+        .with_context(|| format!("couldn't create pack directory: {}", dir.display()))?;
+    let tmp_pack = dir.join(format!("tmp_pack_{}", std::process::id()));
+    let file = fs::File::create(&tmp_pack)
+        .with_context(|| format!("couldn't create pack: {}", tmp_pack.display()))?;
+    let mut out = PackFile {
+        out: BufWriter::new(file),
+        hasher: Hasher::new(Algorithm::SHA1),
+        offset: 0,
+    };
+
+    let mut header = b"PACK".to_vec();
+    header.extend(2u32.to_be_bytes());
+    header.extend((objects.len() as u32).to_be_bytes());
+    out.write(&header)?;
+
+    // (offset, crc) of each slot once written
+    let mut written: Vec<Option<(u64, u32)>> = vec![None; objects.len()];
+    for i in 0..objects.len() {
+        let mut chain = Vec::new();
+        let mut current = i;
+        while written[current].is_none() {
+            chain.push(current);
+            match &slots[current].delta {
+                Some((base, _)) => current = *base,
+                None => break,
+            }
+        }
+        for &slot in chain.iter().rev() {
+            let offset = out.offset;
+            let entry = match &slots[slot].delta {
+                Some((base, delta)) => {
+                    let (base_offset, _) = written[*base].expect("bases are written first");
+                    let mut entry = entry_header(6, delta.len());
+                    entry.extend(encode_distance(offset - base_offset));
+                    entry.extend(compress(delta)?);
+                    entry
+                }
+                None => {
+                    let (kind, content) = odb.read_raw(&objects[slot].oid)?;
+                    let mut entry = entry_header(type_code(kind), content.len());
+                    entry.extend(compress(&content)?);
+                    entry
+                }
+            };
+            let mut crc = Crc::new();
+            crc.update(&entry);
+            out.write(&entry)?;
+            written[slot] = Some((offset, crc.sum()));
+        }
+    }
+
+    let checksum = out.hasher.finish();
+    out.out
+        .write_all(&checksum)
+        .and_then(|_| out.out.flush())
+        .with_context(|| format!("couldn't write pack: {}", tmp_pack.display()))?;
+    drop(out);
+    let checksum = ObjectId::from_bytes(&checksum)?;
+
+    let mut entries: Vec<(ObjectId, u64, u32)> = objects
+        .iter()
+        .zip(&written)
+        .map(|(object, written)| {
+            let (offset, crc) = written.expect("every object is written");
+            (object.oid, offset, crc)
+        })
+        .collect();
+    entries.sort();
+    let index = index_bytes(&entries, &checksum);
+
+    let name = format!("{}-{}", base_name.display(), checksum);
+    let pack_path = PathBuf::from(format!("{}.pack", name));
+    let index_path = PathBuf::from(format!("{}.idx", name));
+    fs::rename(&tmp_pack, &pack_path)
+        .with_context(|| format!("couldn't move pack into place: {}", pack_path.display()))?;
+    // the index goes in last: a pack isn't used until it has one
+    let tmp_index = dir.join(format!("tmp_idx_{}", std::process::id()));
+    fs::write(&tmp_index, index)
+        .and_then(|_| fs::rename(&tmp_index, &index_path))
+        .with_context(|| format!("couldn't write pack index: {}", index_path.display()))?;
+    Ok(checksum)
+}
+
+/// The pack being written, hashed as it goes.
+struct PackFile {
+    out: BufWriter<fs::File>,
+    hasher: Hasher,
+    offset: u64,
+}
+
+impl PackFile {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes).context("couldn't write pack")?;
+        self.hasher.write_all(bytes).context("couldn't hash pack")?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Picks delta bases the way git does: objects are sorted by type, path
+/// hash and decreasing size, and each is tried against the `window` objects
+/// before it, keeping the smallest delta that's under half its size.
+fn find_deltas(
+    odb: &ObjectDatabase,
+    objects: &[&PackObject],
+    slots: &mut [Slot],
+    options: PackOptions,
+) -> Result<()> {
+    if options.window == 0 || options.depth == 0 {
+        return Ok(());
+    }
+    let mut order: Vec<usize> = (0..slots.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&slots[a], &slots[b]);
+        type_code(a.kind)
+            .cmp(&type_code(b.kind))
+            .then(a.name_hash.cmp(&b.name_hash))
+            .then(b.size.cmp(&a.size))
+    });
+
+    let mut window: VecDeque<(usize, Vec<u8>)> = VecDeque::new();
+    for i in order {
+        if window
+            .back()
+            .is_some_and(|(last, _)| slots[*last].kind != slots[i].kind)
+        {
+            window.clear(); // only objects of the same type delta well
+        }
+        let (_, content) = odb.read_raw(&objects[i].oid)?;
+
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for (base, base_content) in window.iter().rev() {
+            if slots[*base].depth >= options.depth || content.len() < base_content.len() / 32 {
+                continue;
+            }
+            let max_size = match &best {
+                Some((_, delta)) => delta.len() - 1,
+                None => (content.len() / 2).saturating_sub(20),
+            };
+            if let Some(delta) = create_delta(base_content, &content, max_size) {
+                best = Some((*base, delta));
+            }
+        }
+        if let Some((base, delta)) = best {
+            slots[i].depth = slots[base].depth + 1;
+            slots[i].delta = Some((base, delta));
+        }
+
+        window.push_back((i, content));
+        if window.len() > options.window {
+            window.pop_front();
+        }
+    }
+    Ok(())
+}
+
+/// Encodes `target` as a delta against `base`, or `None` if that takes more
+/// than `max_size` bytes.
+///
+/// The base is indexed in `BLOCK` sized blocks; wherever the target has one
+/// of them, the match is grown in both directions and copied, and anything
+/// unmatched is inserted literally.
+fn create_delta(base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for start in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        let positions = blocks.entry(&base[start..start + BLOCK]).or_default();
+        if positions.len() < MAX_CANDIDATES {
+            positions.push(start);
+        }
+    }
+
+    let mut out = Vec::new();
+    write_size(&mut out, base.len());
+    write_size(&mut out, target.len());
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK <= target.len() {
+        let best = blocks
+            .get(&target[pos..pos + BLOCK])
+            .into_iter()
+            .flatten()
+            .map(|&start| {
+                let len = base[start..]
+                    .iter()
+                    .zip(&target[pos..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (start, len)
+            })
+            .max_by_key(|&(_, len)| len);
+        let Some((mut start, mut len)) = best else {
+            pos += 1;
+            continue;
+        };
+
+        // take back whatever of the pending literal the base also has
+        let mut copy_pos = pos;
+        while copy_pos > literal_start && start > 0 && base[start - 1] == target[copy_pos - 1] {
+            start -= 1;
+            copy_pos -= 1;
+            len += 1;
+        }
+        push_literal(&mut out, &target[literal_start..copy_pos]);
+        push_copy(&mut out, start, len);
+        pos = copy_pos + len;
+        literal_start = pos;
+        if out.len() > max_size {
+            return None;
+        }
+    }
+    push_literal(&mut out, &target[literal_start..]);
+    (out.len() <= max_size).then_some(out)
+}
+
+fn push_literal(out: &mut Vec<u8>, mut data: &[u8]) {
+    while !data.is_empty() {
+        let len = data.len().min(0x7f);
+        out.push(len as u8);
+        out.extend_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+}
+
+fn push_copy(out: &mut Vec<u8>, mut start: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(0x10000);
+        let op = out.len();
+        out.push(0x80);
+        // only the non-zero bytes are stored, flagged in the op byte;
+        // a size of 0x10000 is stored as no size bytes at all
+        for i in 0..4 {
+            let byte = (start >> (8 * i)) as u8;
+            if byte != 0 {
+                out[op] |= 1 << i;
+                out.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = ((size & 0xffff) >> (8 * i)) as u8;
+            if byte != 0 {
+                out[op] |= 0x10 << i;
+                out.push(byte);
+            }
+        }
+        start += size;
+        len -= size;
+    }
+}
+
+/// Little-endian base-128, as the sizes at the start of a delta.
+fn write_size(out: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        out.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+/// An entry's type and inflated size: 3 bits of type and 4 of size in the
+/// first byte, then 7 bits of size per byte while the high bit is set.
+fn entry_header(code: u8, mut size: usize) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut byte = (code << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size > 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+    header
+}
+
+/// How far back an `OFS_DELTA`'s base is: big-endian base-128, with one
+/// taken off each continuation byte so no distance has two encodings.
+fn encode_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn type_code(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => 1,
+        ObjectKind::Tree => 2,
+        ObjectKind::Blob => 3,
+        ObjectKind::Tag => 4,
+    }
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+    z.write_all(data).context("couldn't compress pack entry")?;
+    z.finish().context("couldn't compress pack entry")
+}
+
+/// git's path hash for ordering the delta search: mostly the last sixteen
+/// characters, so files with the same name or extension end up together.
+fn name_hash(path: &str) -> u32 {
+    path.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// The version 2 `.idx` for `entries`, which are sorted by id.
+fn index_bytes(entries: &[(ObjectId, u64, u32)], pack_checksum: &ObjectId) -> Vec<u8> {
+    let mut out = b"\xfftOc".to_vec();
+    out.extend(2u32.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (oid, _, _) in entries {
+        fanout[oid.as_bytes()[0] as usize] += 1;
+    }
+    let mut count = 0;
+    for first in fanout {
+        count += first;
+        out.extend(count.to_be_bytes());
+    }
+    for (oid, _, _) in entries {
+        out.extend_from_slice(oid.as_bytes());
+    }
+    for (_, _, crc) in entries {
+        out.extend(crc.to_be_bytes());
+    }
+    let mut large = Vec::new();
+    for (_, offset, _) in entries {
+        let small = match u32::try_from(*offset) {
+            Ok(offset) if offset < 0x8000_0000 => offset,
+            _ => {
+                large.push(*offset);
+                0x8000_0000 | (large.len() - 1) as u32
+            }
+        };
+        out.extend(small.to_be_bytes());
+    }
+    for offset in large {
+        out.extend(offset.to_be_bytes());
+    }
+    out.extend_from_slice(pack_checksum.as_bytes());
+    let checksum = digest(Algorithm::SHA1, &out);
+    out.extend(checksum);
+    out
+}
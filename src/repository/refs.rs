@@ -0,0 +1,499 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use super::{Config, ObjectId, Signature};
+
+/// What a ref points at: an object, or another ref (`ref: refs/heads/main`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefTarget {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+/// Loose refs under `ugit/refs` (plus `HEAD` and other pseudo-refs) backed
+/// by `ugit/packed-refs`. Loose refs take precedence over packed ones.
+///
+/// Every write goes through a `<file>.lock` created exclusively and renamed
+/// into place, so concurrent writers fail instead of clobbering each other.
+pub struct RefStore {
+    git_dir: PathBuf,
+}
+
+/// An exclusively created `<path>.lock`, removed again if dropped before commit.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> Result<LockFile> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("couldn't create directory: {}", dir.display()))?;
+        }
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .with_context(|| {
+                format!(
+                    "Unable to create '{}': File exists or is not writable. \
+                     Another ugit process seems to be running.",
+                    lock_path.display()
+                )
+            })?;
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    pub fn commit(mut self, contents: &[u8]) -> Result<()> {
+        let mut file = self.file.take().expect("lock file is open until committed");
+        file.write_all(contents)
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("couldn't write {}", self.lock_path.display()))?;
+        fs::rename(&self.lock_path, &self.path)
+            .with_context(|| format!("couldn't move {} into place", self.lock_path.display()))?;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Checks a full ref name against git's `check-ref-format` rules.
+pub fn check_ref_format(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name != "@"
+        && !name.ends_with('/')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_control() || " ~^:?*[\\".contains(c))
+        // no leading '/' or "//", and no "." or ".." components
+        && name.split('/').all(|part| {
+            !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock")
+        });
+    anyhow::ensure!(valid, "'{}' is not a valid ref name", name);
+    Ok(())
+}
+
+/// The short form git displays for a full ref name, e.g. `main` for
+/// `refs/heads/main`.
+pub fn shorten_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+impl Default for RefStore {
+    fn default() -> Self {
+        RefStore::new("./ugit")
+    }
+}
+
+impl RefStore {
+    pub fn new(git_dir: impl Into<PathBuf>) -> RefStore {
+        RefStore {
+            git_dir: git_dir.into(),
+        }
+    }
+
+    /// Where the loose ref `name` lives. Refuses anything that would end up
+    /// outside the git dir, whatever the caller checked.
+    fn ref_path(&self, name: &str) -> Result<PathBuf> {
+        self.path_under(&self.git_dir, name)
+    }
+
+    /// Where the reflog of `name` lives, with the same checks as `ref_path`.
+    fn reflog_path(&self, name: &str) -> Result<PathBuf> {
+        self.path_under(&self.git_dir.join("logs"), name)
+    }
+
+    fn path_under(&self, dir: &Path, name: &str) -> Result<PathBuf> {
+        let relative = Path::new(name);
+        anyhow::ensure!(
+            relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))),
+            "refusing to use ref '{}' outside of {}",
+            name,
+            self.git_dir.display()
+        );
+        Ok(dir.join(relative))
+    }
+
+    /// Parses `packed-refs` into `(name, oid, peeled)` lines.
+    fn read_packed(&self) -> Result<Vec<(String, ObjectId, Option<ObjectId>)>> {
+        let path = self.git_dir.join("packed-refs");
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Ok(Vec::new());
+        };
+        let mut refs: Vec<(String, ObjectId, Option<ObjectId>)> = Vec::new();
+        for line in contents.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            // `^<oid>` records what the preceding annotated tag peels to
+            if let Some(peeled) = line.strip_prefix('^') {
+                let last = refs
+                    .last_mut()
+                    .context("peeled line without a ref in packed-refs")?;
+                last.2 = Some(ObjectId::from_hex(peeled)?);
+                continue;
+            }
+            let (oid, name) = line
+                .split_once(' ')
+                .with_context(|| format!("malformed packed-refs line: '{}'", line))?;
+            refs.push((name.to_string(), ObjectId::from_hex(oid)?, None));
+        }
+        Ok(refs)
+    }
+
+    /// Reads a single ref without following symbolic refs.
+    pub fn read_raw(&self, name: &str) -> Result<Option<RefTarget>> {
+        // no ref can have a name like that, so there's nothing to read
+        if check_ref_format(name).is_err() {
+            return Ok(None);
+        }
+        let path = self.ref_path(name)?;
+        if path.is_file() {
+            let contents =
+                fs::read_to_string(&path).with_context(|| format!("couldn't read ref {}", name))?;
+            let contents = contents.trim();
+            return Ok(Some(match contents.strip_prefix("ref:") {
+                Some(target) => RefTarget::Symbolic(target.trim().to_string()),
+                None => RefTarget::Direct(
+                    ObjectId::from_hex(contents)
+                        .with_context(|| format!("ref {} is corrupt", name))?,
+                ),
+            }));
+        }
+        Ok(self
+            .read_packed()?
+            .into_iter()
+            .find(|(packed, _, _)| packed == name)
+            .map(|(_, oid, _)| RefTarget::Direct(oid)))
+    }
+
+    /// Follows symbolic refs from `name`, returning the name of the last ref
+    /// in the chain and the object it points at, if it exists yet.
+    pub fn follow(&self, name: &str) -> Result<(String, Option<ObjectId>)> {
+        let mut name = name.to_string();
+        for _ in 0..5 {
+            match self.read_raw(&name)? {
+                Some(RefTarget::Symbolic(target)) => name = target,
+                Some(RefTarget::Direct(oid)) => return Ok((name, Some(oid))),
+                None => return Ok((name, None)),
+            }
+        }
+        anyhow::bail!("too many levels of symbolic refs: {}", name)
+    }
+
+    /// The object a ref ultimately points at.
+    pub fn resolve(&self, name: &str) -> Result<Option<ObjectId>> {
+        Ok(self.follow(name)?.1)
+    }
+
+    /// Expands a short name like `main` or `v1.0` into the full name of an
+    /// existing ref, trying the same places as git.
+    pub fn dwim(&self, short: &str) -> Result<Option<String>> {
+        let is_pseudo_ref = short.bytes().all(|b| b.is_ascii_uppercase() || b == b'_');
+        let mut candidates = Vec::new();
+        if is_pseudo_ref || short.starts_with("refs/") {
+            candidates.push(short.to_string());
+        }
+        candidates.extend([
+            format!("refs/{}", short),
+            format!("refs/tags/{}", short),
+            format!("refs/heads/{}", short),
+            format!("refs/remotes/{}", short),
+            format!("refs/remotes/{}/HEAD", short),
+        ]);
+        for candidate in candidates {
+            if self.resolve(&candidate)?.is_some() {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    /// All refs under `prefix` (e.g. `refs/heads/`) with the objects they
+    /// point at, sorted by name. Symbolic refs are resolved.
+    pub fn list(&self, prefix: &str) -> Result<Vec<(String, ObjectId)>> {
+        let mut refs = std::collections::BTreeMap::new();
+        for (name, oid, _) in self.read_packed()? {
+            if name.starts_with(prefix) {
+                refs.insert(name, oid);
+            }
+        }
+
+        let refs_dir = self.git_dir.join("refs");
+        for entry in walkdir::WalkDir::new(&refs_dir) {
+            let entry = entry.context("couldn't walk ugit/refs")?;
+            if !entry.file_type().is_file() || entry.path().extension().is_some_and(|e| e == "lock")
+            {
+                continue;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(&self.git_dir)
+                .expect("walked paths are inside the git dir");
+            let name = relative.to_string_lossy().replace('\\', "/");
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(oid) = self.resolve(&name)? {
+                refs.insert(name, oid);
+            }
+        }
+        Ok(refs.into_iter().collect())
+    }
+
+    /// Every object any reflog says a ref pointed at, old values included.
+    pub fn reflog_objects(&self) -> Result<Vec<ObjectId>> {
+        let mut oids = Vec::new();
+        let logs_dir = self.git_dir.join("logs");
+        if !logs_dir.is_dir() {
+            return Ok(oids);
+        }
+        for entry in walkdir::WalkDir::new(&logs_dir) {
+            let entry = entry.context("couldn't walk ugit/logs")?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let log = fs::read_to_string(entry.path())
+                .with_context(|| format!("couldn't read reflog {}", entry.path().display()))?;
+            for line in log.lines() {
+                for hex in line.split(' ').take(2) {
+                    match ObjectId::from_hex(hex) {
+                        Ok(oid) if !oid.is_null() => oids.push(oid),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(oids)
+    }
+
+    /// The peeled value packed-refs records for an annotated tag, if any.
+    pub fn packed_peeled(&self, name: &str) -> Result<Option<ObjectId>> {
+        Ok(self
+            .read_packed()?
+            .into_iter()
+            .find(|(packed, _, _)| packed == name)
+            .and_then(|(_, _, peeled)| peeled))
+    }
+
+    /// Points `name` at `new`. With `deref`, symbolic refs are followed and
+    /// the ref they end at is updated instead (e.g. `HEAD` -> `refs/heads/main`).
+    ///
+    /// `expected` makes this a compare-and-swap: the update fails unless the
+    /// ref currently points at that object (`ObjectId::NULL`: must not exist).
+    pub fn update(
+        &self,
+        name: &str,
+        new: ObjectId,
+        expected: Option<ObjectId>,
+        deref: bool,
+        message: &str,
+    ) -> Result<()> {
+        let target = if deref {
+            self.follow(name)?.0
+        } else {
+            name.to_string()
+        };
+        check_ref_format(&target)?;
+
+        let lock = LockFile::acquire(&self.ref_path(&target)?)?;
+        let old = match self.read_raw(&target)? {
+            Some(RefTarget::Direct(oid)) => Some(oid),
+            _ => None,
+        };
+        check_expected(&target, old, expected)?;
+        lock.commit(format!("{}\n", new).as_bytes())?;
+
+        let old = old.unwrap_or(ObjectId::NULL);
+        self.append_reflog(&target, old, new, message)?;
+        if target != name {
+            self.append_reflog(name, old, new, message)?;
+        }
+        // HEAD's reflog also records updates made through the branch it names
+        if target != "HEAD" && name != "HEAD" && self.follow("HEAD")?.0 == target {
+            self.append_reflog("HEAD", old, new, message)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a ref, loose and packed, and its reflog.
+    pub fn delete(&self, name: &str, expected: Option<ObjectId>, deref: bool) -> Result<()> {
+        let target = if deref {
+            self.follow(name)?.0
+        } else {
+            name.to_string()
+        };
+        check_ref_format(&target)?;
+
+        let path = self.ref_path(&target)?;
+        let lock = LockFile::acquire(&path)?;
+        let old = match self.read_raw(&target)? {
+            Some(RefTarget::Direct(oid)) => Some(oid),
+            Some(RefTarget::Symbolic(_)) => None,
+            None => anyhow::bail!("ref {} does not exist", target),
+        };
+        check_expected(&target, old, expected)?;
+
+        let packed = self.read_packed()?;
+        if packed.iter().any(|(packed, _, _)| *packed == target) {
+            let remaining: Vec<_> = packed
+                .into_iter()
+                .filter(|(packed, _, _)| *packed != target)
+                .collect();
+            self.write_packed(&remaining)?;
+        }
+        if path.is_file() {
+            fs::remove_file(&path).with_context(|| format!("couldn't delete ref {}", target))?;
+        }
+        drop(lock);
+
+        let log = self.reflog_path(&target)?;
+        if log.exists() {
+            fs::remove_file(&log)
+                .with_context(|| format!("couldn't delete reflog of {}", target))?;
+        }
+        Ok(())
+    }
+
+    /// Renames the ref `old` to `new`, carrying its reflog along and moving
+    /// `HEAD` with it if it pointed at `old`. `new` must not exist yet.
+    pub fn rename(&self, old: &str, new: &str, message: &str) -> Result<()> {
+        check_ref_format(old)?;
+        check_ref_format(new)?;
+        let oid = match self.read_raw(old)? {
+            Some(RefTarget::Direct(oid)) => oid,
+            Some(RefTarget::Symbolic(_)) => anyhow::bail!("can't rename symbolic ref {}", old),
+            None => anyhow::bail!("ref {} does not exist", old),
+        };
+        anyhow::ensure!(
+            self.read_raw(new)?.is_none(),
+            "cannot rename '{}': '{}' already exists",
+            old,
+            new
+        );
+        let head_follows = self.read_raw("HEAD")? == Some(RefTarget::Symbolic(old.to_string()));
+
+        let old_log = self.reflog_path(old)?;
+        let new_log = self.reflog_path(new)?;
+        if old_log.exists() {
+            if let Some(dir) = new_log.parent() {
+                fs::create_dir_all(dir).context("couldn't create reflog directory")?;
+            }
+            fs::rename(&old_log, &new_log)
+                .with_context(|| format!("couldn't move reflog of {}", old))?;
+        }
+
+        let written = LockFile::acquire(&self.ref_path(new)?)
+            .and_then(|lock| lock.commit(format!("{}\n", oid).as_bytes()));
+        if let Err(err) = written {
+            if new_log.exists() {
+                let _ = fs::rename(&new_log, &old_log);
+            }
+            return Err(err);
+        }
+        self.append_reflog(new, oid, oid, message)?;
+        self.delete(old, Some(oid), false)?;
+
+        if head_follows {
+            let lock = LockFile::acquire(&self.ref_path("HEAD")?)?;
+            lock.commit(format!("ref: {}\n", new).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_packed(&self, refs:&[(String, ObjectId, Option<ObjectId>)]) -> Result<()> {
+        let lock = LockFile::acquire(&self.git_dir.join("packed-refs"))?;
+        let mut contents = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+        for (name, oid, peeled) in refs {
+            contents.push_str(&format!("{} {}\n", oid, name));
+            if let Some(peeled) = peeled {
+                contents.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        lock.commit(contents.as_bytes())
+    }
+
+    /// Points the symbolic ref `name` (usually `HEAD`) at the ref `target`.
+    pub fn set_symbolic(&self, name: &str, target: &str, message: &str) -> Result<()> {
+        check_ref_format(name)?;
+        check_ref_format(target)?;
+        let old = self.resolve(name)?.unwrap_or(ObjectId::NULL);
+        let lock = LockFile::acquire(&self.ref_path(name)?)?;
+        lock.commit(format!("ref: {}\n", target).as_bytes())?;
+
+        let new = self.resolve(name)?.unwrap_or(ObjectId::NULL);
+        let unborn = old.is_null() && new.is_null();
+        if !message.is_empty() && !unborn {
+            self.append_reflog(name, old, new, message)?;
+        }
+        Ok(())
+    }
+
+    /// Appends `<old> <new> <committer>\t<message>` to `logs/<name>`.
+    pub fn append_reflog(
+        &self,
+        name: &str,
+        old: ObjectId,
+        new: ObjectId,
+        message: &str,
+    ) -> Result<()> {
+        let path = self.reflog_path(name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("couldn't create reflog directory")?;
+        }
+        let config = Config::load()?;
+        let who = Signature::from_env("COMMITTER", &config)
+            .unwrap_or_else(|_| Signature::now("unknown", "unknown"));
+        let message = message.lines().next().unwrap_or_default();
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("couldn't open reflog {}", path.display()))?;
+        writeln!(file, "{} {} {}\t{}", old, new, who, message)
+            .with_context(|| format!("couldn't write reflog {}", path.display()))?;
+        Ok(())
+    }
+}
+
+fn check_expected(name: &str, old: Option<ObjectId>, expected: Option<ObjectId>) -> Result<()> {
+    match expected {
+        Some(expected) if expected.is_null() && old.is_some() => {
+            anyhow::bail!("cannot lock ref '{}': reference already exists", name)
+        }
+        Some(expected) if !expected.is_null() && old != Some(expected) => anyhow::bail!(
+            "cannot lock ref '{}': is at {} but expected {}",
+            name,
+            old.map(|oid| oid.to_string())
+                .unwrap_or_else(|| "nothing".to_string()),
+            expected
+        ),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+use super::{Index, Object, ObjectDatabase, ObjectId, ObjectKind, RefStore};
+
+/// Resolves a revision expression to an object id.
+///
+/// Supported syntax:
+/// - `HEAD`, `@`, branch/tag/ref names and full or abbreviated hex ids
+/// - `@{-N}`: the N-th branch checked out before the current one
+/// - `<rev>~N` (N-th first-parent ancestor) and `<rev>^N` (N-th parent, `^0` peels to the commit)
+/// - `<rev>^{type}`: peel tags/commits until an object of `type` is reached
+/// - `<rev>^{}`: peel tags until something that isn't a tag is reached
+/// - `:<path>`: the blob staged for `path` in the index
+/// - `<rev>:<path>`: the blob or tree at `path` in the tree of `rev`
+pub fn resolve_revision(odb: &ObjectDatabase, rev: &str) -> Result<ObjectId> {
+    // `:path` looks the path up in the index
+    if let Some(path) = rev.strip_prefix(':') {
+        let index = Index::default().read()?;
+        let entry = index
+            .entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .with_context(|| format!("path '{}' is not in the index", path))?;
+        return Ok(entry.sha1);
+    }
+
+    // `<rev>:<path>` looks the path up in the revision's tree
+    if let Some((rev, path)) = split_tree_path(rev) {
+        let tree = peel(odb, resolve_revision(odb, rev)?, Some(ObjectKind::Tree))
+            .with_context(|| format!("'{}' is not a tree-ish", rev))?;
+        return lookup_path(odb, tree, path)
+            .with_context(|| format!("path '{}' does not exist in '{}'", path, rev));
+    }
+
+    let base_end = rev.find(['~', '^']).unwrap_or(rev.len());
+    let (base, mut suffixes) = rev.split_at(base_end);
+    let mut oid = resolve_base(odb, base)?;
+
+    while !suffixes.is_empty() {
+        if let Some(rest) = suffixes.strip_prefix("^{") {
+            let close = rest
+                .find('}')
+                .with_context(|| format!("unterminated '^{{' in '{}'", rev))?;
+            oid = match &rest[..close] {
+                "" => peel_tags(odb, oid)?,
+                "object" => peel(odb, oid, None)?,
+                kind => {
+                    let kind = kind
+                        .parse()
+                        .with_context(|| format!("invalid peel type in '{}'", rev))?;
+                    peel(odb, oid, Some(kind))?
+                }
+            };
+            suffixes = &rest[close + 1..];
+        } else {
+            let op = suffixes.as_bytes()[0];
+            let rest = &suffixes[1..];
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let n: usize = match digits {
+                0 => 1,
+                _ => rest[..digits]
+                    .parse()
+                    .context("revision number is too large")?,
+            };
+            suffixes = &rest[digits..];
+
+            let commit = peel(odb, oid, Some(ObjectKind::Commit))?;
+            oid = if op == b'~' {
+                nth_ancestor(odb, commit, n)?
+            } else if n == 0 {
+                commit
+            } else {
+                let parents = odb.read_commit(&commit)?.parent_commits;
+                *parents
+                    .get(n - 1)
+                    .with_context(|| format!("{} has no parent {}", commit, n))?
+            };
+        }
+    }
+
+    Ok(oid)
+}
+
+/// Splits `<rev>:<path>`, ignoring colons inside `^{...}`.
+fn split_tree_path(rev: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in rev.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some((&rev[..i], &rev[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn resolve_base(odb: &ObjectDatabase, base: &str) -> Result<ObjectId> {
+    let base = match base {
+        "" | "@" => "HEAD",
+        base => base,
+    };
+
+    if let Some(n) = base.strip_prefix("@{-").and_then(|b| b.strip_suffix('}')) {
+        let n: usize = n
+            .parse()
+            .with_context(|| format!("invalid previous branch: '{}'", base))?;
+        let branch = previous_branch(n)?;
+        return resolve_base(odb, &branch);
+    }
+
+    if base.len() == 40 {
+        if let Ok(oid) = ObjectId::from_hex(base) {
+            return Ok(oid);
+        }
+    }
+
+    // refs win over abbreviated ids, as in git
+    let refs = RefStore::default();
+    if let Some(name) = refs.dwim(base)? {
+        if let Some(oid) = refs.resolve(&name)? {
+            return Ok(oid);
+        }
+    }
+
+    odb.resolve(base)
+        .with_context(|| format!("unknown revision: '{}'", base))
+}
+
+/// The branch checked out `n` switches ago, from HEAD's reflog.
+pub fn previous_branch(n: usize) -> Result<String> {
+    anyhow::ensure!(n > 0, "@{{-0}} is not a valid previous branch");
+    let log = fs::read_to_string("./ugit/logs/HEAD").unwrap_or_default();
+    log.lines()
+        .rev()
+        .filter_map(|line| {
+            let (_, message) = line.split_once('\t')?;
+            let moves = message.strip_prefix("checkout: moving from ")?;
+            let (from, _) = moves.split_once(" to ")?;
+            Some(from.to_string())
+        })
+        .nth(n - 1)
+        .with_context(|| format!("@{{-{}}}: no such previous branch", n))
+}
+
+/// Peels tags (and commits, down to their tree) until an object of `kind`
+/// is reached. `None` only checks that the object exists.
+pub fn peel(odb: &ObjectDatabase, mut oid: ObjectId, kind: Option<ObjectKind>) -> Result<ObjectId> {
+    loop {
+        let (current, content) = odb.read_raw(&oid)?;
+        if kind.is_none() || kind == Some(current) {
+            return Ok(oid);
+        }
+        oid = match Object::parse(current, content)? {
+            Object::Tag(tag) => tag.object,
+            Object::Commit(commit) if kind == Some(ObjectKind::Tree) => commit.tree,
+            object => anyhow::bail!(
+                "{} is a {}, which can't be peeled to a {}",
+                oid,
+                object.kind(),
+                kind.unwrap()
+            ),
+        };
+    }
+}
+
+/// Follows tags, and tags of tags, to the object they finally point at.
+/// Anything that isn't a tag is returned as is.
+pub fn peel_tags(odb: &ObjectDatabase, mut oid: ObjectId) -> Result<ObjectId> {
+    while odb.read_header(&oid)?.0 == ObjectKind::Tag {
+        oid = odb.read_tag(&oid)?.object;
+    }
+    Ok(oid)
+}
+
+fn nth_ancestor(odb: &ObjectDatabase, mut commit: ObjectId, n: usize) -> Result<ObjectId> {
+    for generation in 0..n {
+        let parents = odb.read_commit(&commit)?.parent_commits;
+        commit = *parents
+            .first()
+            .with_context(|| format!("{} has only {} ancestors", commit, generation))?;
+    }
+    Ok(commit)
+}
+
+/// Walks `path` from the root tree `tree`. An empty path is the tree itself.
+fn lookup_path(odb: &ObjectDatabase, tree: ObjectId, path: &str) -> Result<ObjectId> {
+    find_in_tree(odb, tree, path)?.context("no such path")
+}
+
+/// The object at `path` inside `tree`, or `None` if there is nothing there.
+pub fn find_in_tree(odb: &ObjectDatabase, tree: ObjectId, path: &str) -> Result<Option<ObjectId>> {
+    let mut oid = tree;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let tree = match odb.read(&oid)? {
+            Object::Tree(tree) => tree,
+            _ => return Ok(None), // a file where a directory was expected
+        };
+        match tree.entries.iter().find(|entry| entry.name == name) {
+            Some(entry) => oid = entry.hash,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(oid))
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use ignore::WalkBuilder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use super::{check_file, Conflict, FileState, Index, IndexEntry, ObjectId};
+
+/// How a path differs between two of HEAD, the index and the work tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+    /// The path changed between a file, a symlink and a submodule.
+    TypeChanged,
+    /// The file was moved, possibly with some changes.
+    Renamed,
+    /// The file was copied from another one that is still there.
+    Copied,
+}
+
+impl Change {
+    /// The letter used by `status --short` and `diff --name-status`.
+    pub fn letter(&self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::TypeChanged => 'T',
+            Change::Renamed => 'R',
+            Change::Copied => 'C',
+        }
+    }
+
+    /// The label used by the long `status` format.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Change::Added => "new file",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+            Change::TypeChanged => "typechange",
+            Change::Renamed => "renamed",
+            Change::Copied => "copied",
+        }
+    }
+}
+
+/// Regular files (of either mode), symlinks and submodules are different types.
+pub fn same_type(a: u32, b: u32) -> bool {
+    a & 0o170000 == b & 0o170000
+}
+
+/// The state of the work tree: what is staged relative to HEAD, what is
+/// changed on disk relative to the index, and which files aren't tracked.
+#[derive(Debug, Default)]
+pub struct Status {
+    pub staged: BTreeMap<String, Change>,
+    pub unstaged: BTreeMap<String, Change>,
+    /// Paths with merge conflicts, which count as neither staged nor unstaged.
+    pub unmerged: Vec<Conflict>,
+    /// Untracked files, or `dir/` for directories holding no tracked files.
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    /// Compares `head` (the flattened HEAD tree, empty before the first
+    /// commit) with `index`, and `index` with the files on disk.
+    pub fn collect(head: &BTreeMap<String, (u32, ObjectId)>, index: &Index) -> Result<Status> {
+        let mut status = Status {
+            unmerged: index.conflicts(),
+            ..Status::default()
+        };
+        let unmerged: BTreeSet<&str> = status
+            .unmerged
+            .iter()
+            .map(|conflict| conflict.path.as_str())
+            .collect();
+
+        let staged: BTreeMap<&str, &IndexEntry> = index
+            .entries
+            .iter()
+            .filter(|entry| entry.stage() == 0)
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let paths: BTreeSet<&str> = head
+            .keys()
+            .map(String::as_str)
+            .chain(staged.keys().copied())
+            .filter(|path| !unmerged.contains(path))
+            .collect();
+        for path in paths {
+            let change = match (head.get(path), staged.get(path)) {
+                (None, Some(_)) => Change::Added,
+                (Some(_), None) => Change::Deleted,
+                (Some(&(mode, _)), Some(entry)) if !same_type(mode, entry.mode) => {
+                    Change::TypeChanged
+                }
+                (Some(&(mode, oid)), Some(entry)) if mode != entry.mode || oid != entry.sha1 => {
+                    Change::Modified
+                }
+                _ => continue,
+            };
+            status.staged.insert(path.to_string(), change);
+        }
+
+        for entry in staged.values() {
+            let change = match check_file(entry)? {
+                FileState::Unchanged => continue,
+                FileState::Deleted => Change::Deleted,
+                FileState::Modified => match fs::symlink_metadata(&entry.path) {
+                    Ok(metadata)
+                        if !same_type(IndexEntry::mode_from_metadata(&metadata), entry.mode) =>
+                    {
+                        Change::TypeChanged
+                    }
+                    _ => Change::Modified,
+                },
+            };
+            status.unstaged.insert(entry.path.clone(), change);
+        }
+
+        status.untracked = untracked_files(index)?;
+        Ok(status)
+    }
+
+    /// Whether nothing is staged or unmerged and no tracked file is changed on disk.
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.unmerged.is_empty()
+    }
+}
+
+/// Files on disk that aren't in the index, skipping anything `.gitignore`
+/// excludes. A directory without any tracked file is listed once, as `dir/`.
+fn untracked_files(index: &Index) -> Result<Vec<String>> {
+    let tracked: BTreeSet<&str> = index.entries.iter().map(|e| e.path.as_str()).collect();
+    let tracked_dirs: BTreeSet<&str> = tracked
+        .iter()
+        .flat_map(|path| path.match_indices('/').map(move |(i, _)| &path[..i]))
+        .collect();
+
+    let mut untracked = BTreeSet::new();
+    let walker = WalkBuilder::new(".")
+        .hidden(false)
+        .git_ignore(true)
+        .require_git(false) // honor .gitignore even though there is no .git
+        .filter_entry(|entry| entry.file_name() != "ugit" && entry.file_name() != ".git")
+        .build();
+    for entry in walker {
+        let entry = entry.context("couldn't walk the work tree")?;
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            continue;
+        }
+        let path = entry.path().strip_prefix("./").unwrap_or(entry.path());
+        let path = path.to_str().context("couldn't convert path to string")?;
+        if tracked.contains(path) {
+            continue;
+        }
+
+        let untracked_dir = path
+            .match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .find(|dir| !tracked_dirs.contains(dir));
+        match untracked_dir {
+            Some(dir) => untracked.insert(format!("{}/", dir)),
+            None => untracked.insert(path.to_string()),
+        };
+    }
+    Ok(untracked.into_iter().collect())
+}
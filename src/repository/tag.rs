@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+
+use super::{ObjectId, ObjectKind, Signature};
+
+/// An annotated tag: a named, signed-off pointer to another object.
+pub struct Tag {
+    pub object: ObjectId, // the object tagged
+    pub kind: ObjectKind, // its type, recorded so it can be peeled without reading it
+    pub name: String,
+    pub tagger: Option<Signature>, // very old tags have no tagger
+    pub message: String,
+}
+
+impl Tag {
+    /// The first line of the message.
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+
+    /// Serializes the tag body, without the `tag <size>\0` header.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = String::new();
+        out.push_str(&format!("object {}\n", self.object));
+        out.push_str(&format!("type {}\n", self.kind));
+        out.push_str(&format!("tag {}\n", self.name));
+        if let Some(tagger) = &self.tagger {
+            out.push_str(&format!("tagger {}\n", tagger));
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        out.into_bytes()
+    }
+
+    /// Parses a tag body, without the `tag <size>\0` header.
+    pub fn parse(content: &[u8]) -> Result<Tag> {
+        let content = std::str::from_utf8(content).context("tag isn't valid UTF-8")?;
+        let (headers, message) = content
+            .split_once("\n\n")
+            .unwrap_or((content.trim_end_matches('\n'), ""));
+
+        let mut object = None;
+        let mut kind = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("malformed tag header: '{}'", line))?;
+            match key {
+                "object" => object = Some(ObjectId::from_hex(value)?),
+                "type" => kind = Some(value.parse()?),
+                "tag" => name = Some(value.to_string()),
+                "tagger" => tagger = Some(Signature::parse(value)?),
+                _ => {} // unknown headers are tolerated, as git does
+            }
+        }
+
+        Ok(Tag {
+            object: object.context("tag has no object")?,
+            kind: kind.context("tag has no type")?,
+            name: name.context("tag has no name")?,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+}
@@ -1,9 +1,139 @@
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use super::{ObjectDatabase, ObjectId, ObjectKind};
+
+/// A parsed tree object.
+///
+/// On disk each entry is `<mode> <name>\0<20-byte sha>`, with the mode in
+/// octal without leading zeros and entries in git's name order.
+#[derive(Debug, Default, Clone)]
 pub struct Tree {
-    entries: Vec<TreeEntry>,
+    pub entries: Vec<TreeEntry>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TreeEntry {
-    mode: String, // e.g., "100644" for file, "040000" for directory
-    name: String,
-    hash: String, // SHA-1 hash of the blob or tree object
+    pub mode: u32, // e.g., 0o100644 for file, 0o040000 for directory
+    pub name: String,
+    pub hash: ObjectId, // SHA-1 hash of the blob or tree object
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == 0o040000
+    }
+
+    /// The type of object the entry points at: `blob`, `tree` or `commit` (submodules).
+    pub fn kind(&self) -> &'static str {
+        match self.mode {
+            0o040000 => "tree",
+            0o160000 => "commit",
+            _ => "blob",
+        }
+    }
+
+    /// Compares entries the way git sorts them: by name bytes, with
+    /// directories compared as if their name had a trailing `/`.
+    pub fn git_cmp(&self, other: &TreeEntry) -> Ordering {
+        let a = self
+            .name
+            .as_bytes()
+            .iter()
+            .chain(self.is_tree().then_some(&b'/'));
+        let b = other
+            .name
+            .as_bytes()
+            .iter()
+            .chain(other.is_tree().then_some(&b'/'));
+        a.cmp(b)
+    }
+}
+
+impl Tree {
+    /// Parses the body of a tree object, without the `tree <size>\0` header.
+    pub fn parse(content: &[u8]) -> Result<Tree> {
+        let mut entries = Vec::new();
+        let mut rest = content;
+
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .context("tree entry is missing its mode")?;
+            let mode =
+                std::str::from_utf8(&rest[..space]).context("tree mode isn't valid UTF-8")?;
+            let mode = u32::from_str_radix(mode, 8)
+                .with_context(|| format!("invalid tree entry mode: '{}'", mode))?;
+            rest = &rest[space + 1..];
+
+            let nul = rest
+                .iter()
+                .position(|&b| b == 0)
+                .context("tree entry is missing its name terminator")?;
+            let name = String::from_utf8(rest[..nul].to_vec())
+                .context("tree entry name isn't valid UTF-8")?;
+            rest = &rest[nul + 1..];
+
+            anyhow::ensure!(
+                rest.len() >= 20,
+                "tree entry '{}' has a truncated hash",
+                name
+            );
+            let hash = ObjectId::from_bytes(&rest[..20])?;
+            rest = &rest[20..];
+
+            entries.push(TreeEntry { mode, name, hash });
+        }
+
+        Ok(Tree { entries })
+    }
+
+    /// Serializes the tree body in git's binary format, sorting entries into
+    /// git's name order.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut entries: Vec<&TreeEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.git_cmp(b));
+
+        let mut out = Vec::new();
+        for entry in entries {
+            out.extend_from_slice(format!("{:o} {}\0", entry.mode, entry.name).as_bytes());
+            out.extend_from_slice(entry.hash.as_bytes());
+        }
+        out
+    }
+}
+
+/// Writes the tree objects holding `files`, keyed by their `/`-separated
+/// paths with their modes, and returns the root tree. The inverse of
+/// `flatten_tree`.
+pub fn write_tree_from_paths(
+    odb: &ObjectDatabase,
+    files: &BTreeMap<String, (u32, ObjectId)>,
+) -> Result<ObjectId> {
+    let mut tree = Tree::default();
+    let mut dirs: BTreeMap<&str, BTreeMap<String, (u32, ObjectId)>> = BTreeMap::new();
+    for (path, &(mode, hash)) in files {
+        match path.split_once('/') {
+            Some((dir, rest)) => {
+                dirs.entry(dir)
+                    .or_default()
+                    .insert(rest.to_string(), (mode, hash));
+            }
+            None => tree.entries.push(TreeEntry {
+                mode,
+                name: path.clone(),
+                hash,
+            }),
+        }
+    }
+    for (name, children) in dirs {
+        tree.entries.push(TreeEntry {
+            mode: 0o040000,
+            name: name.to_string(),
+            hash: write_tree_from_paths(odb, &children)?,
+        });
+    }
+    odb.write(ObjectKind::Tree, &tree.serialize())
 }
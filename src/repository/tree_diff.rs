@@ -0,0 +1,351 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use super::status::same_type;
+use super::{Change, ObjectDatabase, ObjectId, ObjectKind, TreeEntry};
+
+/// A path that differs between two trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub change: Change,
+    /// Where the file was on the old side. Only differs from `path` for
+    /// renames and copies.
+    pub old_path: String,
+    pub path: String,
+    /// The mode and object on each side, `None` where the path doesn't exist.
+    pub old: Option<(u32, ObjectId)>,
+    pub new: Option<(u32, ObjectId)>,
+    /// How alike the old and new content are, in percent, for renames and copies.
+    pub similarity: Option<u8>,
+}
+
+impl DiffEntry {
+    fn new(path: String, old: Option<(u32, ObjectId)>, new: Option<(u32, ObjectId)>) -> DiffEntry {
+        let change = match (old, new) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Deleted,
+            (Some((a, _)), Some((b, _))) if !same_type(a, b) => Change::TypeChanged,
+            _ => Change::Modified,
+        };
+        DiffEntry {
+            change,
+            old_path: path.clone(),
+            path,
+            old,
+            new,
+            similarity: None,
+        }
+    }
+}
+
+/// The differences between two trees, `None` standing for an empty tree.
+/// With `recursive`, changed subtrees are compared entry by entry; otherwise
+/// they are reported as changed paths of their own.
+pub fn diff_trees(
+    odb: &ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    recursive: bool,
+) -> Result<Vec<DiffEntry>> {
+    let mut entries = Vec::new();
+    diff_into(odb, old, new, "", recursive, &mut entries)?;
+    Ok(entries)
+}
+
+fn diff_into(
+    odb: &ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    prefix: &str,
+    recursive: bool,
+    entries: &mut Vec<DiffEntry>,
+) -> Result<()> {
+    if old == new {
+        return Ok(()); // identical subtrees need no reading
+    }
+    let read = |tree: Option<ObjectId>| -> Result<Vec<TreeEntry>> {
+        match tree {
+            Some(tree) => Ok(odb.read_tree(&tree)?.entries),
+            None => Ok(Vec::new()),
+        }
+    };
+    let (old, new) = (read(old)?, read(new)?);
+
+    // both lists are in git's order, so equal names line up in one pass
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let order = match (old.get(i), new.get(j)) {
+            (Some(a), Some(b)) => a.git_cmp(b),
+            (Some(_), None) => Ordering::Less,
+            _ => Ordering::Greater,
+        };
+        let (a, b) = match order {
+            Ordering::Less => (old.get(i), None),
+            Ordering::Greater => (None, new.get(j)),
+            Ordering::Equal => (old.get(i), new.get(j)),
+        };
+        i += a.is_some() as usize;
+        j += b.is_some() as usize;
+
+        let side = |entry: Option<&TreeEntry>| entry.map(|entry| (entry.mode, entry.hash));
+        if side(a) == side(b) {
+            continue;
+        }
+        let entry = a.or(b).expect("one side has the entry");
+        let path = format!("{}{}", prefix, entry.name);
+        // entries only pair up when both or neither are trees
+        if recursive && entry.is_tree() {
+            let subtree = |entry: Option<&TreeEntry>| entry.map(|entry| entry.hash);
+            diff_into(
+                odb,
+                subtree(a),
+                subtree(b),
+                &format!("{}/", path),
+                true,
+                entries,
+            )?;
+        } else {
+            entries.push(DiffEntry::new(path, side(a), side(b)));
+        }
+    }
+    Ok(())
+}
+
+/// Like `diff_trees`, for flattened lists of files such as the index.
+pub fn diff_files(
+    old: &BTreeMap<String, (u32, ObjectId)>,
+    new: &BTreeMap<String, (u32, ObjectId)>,
+) -> Vec<DiffEntry> {
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (a, b) = (old.get(path).copied(), new.get(path).copied());
+            (a != b).then(|| DiffEntry::new(path.clone(), a, b))
+        })
+        .collect()
+}
+
+/// Similarity thresholds, in percent, for pairing up files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenameOptions {
+    /// Pair deleted files with added files at least this similar (`-M`).
+    pub renames: Option<u8>,
+    /// Also find added files copied from a changed or deleted one (`-C`).
+    pub copies: Option<u8>,
+}
+
+impl RenameOptions {
+    /// Parses a `-M`/`-C` threshold the way git does: `90%` is a percentage,
+    /// while plain digits are a fraction, so `9` and `90` both mean 90%.
+    pub fn parse_threshold(value: &str) -> Result<u8> {
+        let invalid = || anyhow::anyhow!("invalid similarity threshold: '{}'", value);
+        if let Some(percent) = value.strip_suffix('%') {
+            let percent: u8 = percent.parse().map_err(|_| invalid())?;
+            anyhow::ensure!(percent <= 100, invalid());
+            return Ok(percent);
+        }
+        if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let digits = format!("{:0<2}", &value[..value.len().min(2)]);
+        Ok(digits.parse().expect("two digits"))
+    }
+}
+
+/// Git gives up on inexact renames beyond this many sources or destinations.
+const RENAME_LIMIT: usize = 1000;
+
+/// Turns deleted and added files that are alike into renames, and with
+/// `copies` added files that are alike an existing one into copies. The
+/// rename or copy takes the place of the added file. `read` loads blobs.
+pub fn detect_renames(
+    entries: Vec<DiffEntry>,
+    options: RenameOptions,
+    read: impl Fn(&ObjectId) -> Result<Vec<u8>>,
+) -> Result<Vec<DiffEntry>> {
+    let Some(threshold) = options.renames.or(options.copies) else {
+        return Ok(entries);
+    };
+    let is_blob = |side: Option<(u32, ObjectId)>| {
+        side.is_some_and(|(mode, _)| mode & 0o170000 == 0o100000 || mode == 0o120000)
+    };
+    let deleted: Vec<usize> = (0..entries.len())
+        .filter(|&i| entries[i].change == Change::Deleted && is_blob(entries[i].old))
+        .collect();
+    let added: Vec<usize> = (0..entries.len())
+        .filter(|&i| entries[i].change == Change::Added && is_blob(entries[i].new))
+        .collect();
+
+    let mut signatures = Signatures::new(read);
+    // destination -> (source, similarity, whether the source goes away)
+    let mut pairs: HashMap<usize, (usize, u8, bool)> = HashMap::new();
+    let mut used: HashSet<usize> = HashSet::new();
+
+    let (old, new) = (
+        |i: usize| entries[i].old.expect("sources have an old side"),
+        |i: usize| entries[i].new.expect("destinations have a new side"),
+    );
+    let file_name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+
+    // exact renames first, preferring a source with the same file name;
+    // empty files are all alike, so they are never paired up
+    let empty = ObjectDatabase::hash(ObjectKind::Blob, &[]);
+    for &dst in &added {
+        let (mode, oid) = new(dst);
+        if oid == empty {
+            continue;
+        }
+        let candidates: Vec<usize> = deleted
+            .iter()
+            .copied()
+            .filter(|&src| !used.contains(&src))
+            .filter(|&src| old(src).1 == oid && same_type(old(src).0, mode))
+            .collect();
+        let name = file_name(&entries[dst].path);
+        let src = candidates
+            .iter()
+            .find(|&&src| file_name(&entries[src].path) == name)
+            .or(candidates.first());
+        if let Some(&src) = src {
+            pairs.insert(dst, (src, 100, true));
+            used.insert(src);
+        }
+    }
+
+    // then the most similar remaining pairs
+    let sources: Vec<usize> = deleted
+        .iter()
+        .copied()
+        .filter(|s| !used.contains(s))
+        .collect();
+    let targets: Vec<usize> = added
+        .iter()
+        .copied()
+        .filter(|d| !pairs.contains_key(d))
+        .collect();
+    if sources.len() <= RENAME_LIMIT && targets.len() <= RENAME_LIMIT {
+        let mut scored = Vec::new();
+        for &dst in &targets {
+            for &src in &sources {
+                if same_type(old(src).0, new(dst).0) {
+                    let score = signatures.similarity(&old(src).1, &new(dst).1)?;
+                    if score >= threshold {
+                        scored.push((score, dst, src));
+                    }
+                }
+            }
+        }
+        scored.sort_by_key(|&(score, _, _)| std::cmp::Reverse(score));
+        for (score, dst, src) in scored {
+            if !pairs.contains_key(&dst) && used.insert(src) {
+                pairs.insert(dst, (src, score, true));
+            }
+        }
+    }
+
+    // copies can come from any changed or deleted file, which stays
+    if let Some(threshold) = options.copies {
+        let sources: Vec<usize> = (0..entries.len())
+            .filter(|&i| entries[i].change != Change::Added && is_blob(entries[i].old))
+            .collect();
+        let targets: Vec<usize> = added
+            .iter()
+            .copied()
+            .filter(|d| !pairs.contains_key(d))
+            .collect();
+        for dst in targets {
+            let mut best: Option<(u8, usize)> = None;
+            for &src in &sources {
+                if !same_type(old(src).0, new(dst).0) {
+                    continue;
+                }
+                let score = signatures.similarity(&old(src).1, &new(dst).1)?;
+                if score >= threshold && best.is_none_or(|(best, _)| score > best) {
+                    best = Some((score, src));
+                }
+            }
+            if let Some((score, src)) = best {
+                pairs.insert(dst, (src, score, false));
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        if used.contains(&i) {
+            continue; // shown as the rename
+        }
+        match pairs.get(&i) {
+            Some(&(src, score, moved)) => result.push(DiffEntry {
+                change: if moved {
+                    Change::Renamed
+                } else {
+                    Change::Copied
+                },
+                old_path: entries[src].path.clone(),
+                path: entry.path.clone(),
+                old: entries[src].old,
+                new: entry.new,
+                similarity: Some(score),
+            }),
+            None => result.push(entry.clone()),
+        }
+    }
+    Ok(result)
+}
+
+/// Blob contents cut into chunks, for estimating how alike two blobs are.
+struct Signatures<F> {
+    read: F,
+    /// For each blob, its size and the bytes it has in each chunk.
+    cache: HashMap<ObjectId, (usize, HashMap<u64, usize>)>,
+}
+
+impl<F: Fn(&ObjectId) -> Result<Vec<u8>>> Signatures<F> {
+    fn new(read: F) -> Signatures<F> {
+        Signatures {
+            read,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, oid: &ObjectId) -> Result<()> {
+        if self.cache.contains_key(oid) {
+            return Ok(());
+        }
+        let content = (self.read)(oid)?;
+        let mut chunks: HashMap<u64, usize> = HashMap::new();
+        // like git, a chunk is a line, or 64 bytes of a longer one
+        for line in content.split_inclusive(|&byte| byte == b'\n') {
+            for chunk in line.chunks(64) {
+                let mut hasher = DefaultHasher::new();
+                chunk.hash(&mut hasher);
+                *chunks.entry(hasher.finish()).or_default() += chunk.len();
+            }
+        }
+        self.cache.insert(*oid, (content.len(), chunks));
+        Ok(())
+    }
+
+    /// The share of the larger blob's bytes that the two have in common, in
+    /// percent. Empty blobs are never alike.
+    fn similarity(&mut self, a: &ObjectId, b: &ObjectId) -> Result<u8> {
+        self.load(a)?;
+        self.load(b)?;
+        let (a_size, a_chunks) = &self.cache[a];
+        let (b_size, b_chunks) = &self.cache[b];
+        let largest = *a_size.max(b_size);
+        if *a_size == 0 || *b_size == 0 {
+            return Ok(0);
+        }
+        let common: usize = a_chunks
+            .iter()
+            .map(|(chunk, &bytes)| bytes.min(b_chunks.get(chunk).copied().unwrap_or(0)))
+            .sum();
+        Ok((common * 100 / largest) as u8)
+    }
+}
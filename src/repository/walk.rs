@@ -0,0 +1,376 @@
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use super::{
+    find_in_tree, merge_bases, peel, resolve_revision, Commit, ObjectDatabase, ObjectId,
+    ObjectKind,
+};
+
+/// The order `RevWalk::run` returns commits in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    /// Newest commit date first, in the order the walk reaches commits.
+    #[default]
+    Chronological,
+    /// No parent before its children; otherwise by commit date (`--date-order`).
+    Date,
+    /// No parent before its children; otherwise by author date (`--author-date-order`).
+    AuthorDate,
+    /// No parent before its children, and each line of history shown in one
+    /// go instead of interleaved by date (`--topo-order`).
+    Topo,
+}
+
+/// A commit produced by the walk.
+pub struct WalkedCommit {
+    pub oid: ObjectId,
+    pub commit: Commit,
+    /// The commit's parents as far as the walk is concerned: rewritten to
+    /// the nearest ancestors that are also shown, so filtered-out and hidden
+    /// commits never appear here.
+    pub parents: Vec<ObjectId>,
+}
+
+/// Walks commit history from a set of tips, like `git rev-list`.
+///
+/// Commits reachable from hidden commits (`^A`, the `A` of `A..B`) are left
+/// out; they are found while walking, so a range only costs as much history
+/// as lies between its ends. With `paths`, only commits that change one of the paths are shown
+/// and merges that took a path from one parent unchanged are followed down
+/// that parent only, as in git's default history simplification.
+pub struct RevWalk<'a> {
+    odb: &'a ObjectDatabase,
+    tips: Vec<ObjectId>,
+    hidden: Vec<ObjectId>,
+    pub sort: Sort,
+    /// Only follow the first parent of merges.
+    pub first_parent: bool,
+    pub max_count: Option<usize>,
+    /// Only show commits committed at or after this time.
+    pub since: Option<i64>,
+    /// Only show commits committed at or before this time.
+    pub until: Option<i64>,
+    /// Only show commits whose `Name <email>` author contains this.
+    pub author: Option<String>,
+    /// Only show commits whose message contains this.
+    pub grep: Option<String>,
+    pub paths: Vec<String>,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(odb: &'a ObjectDatabase) -> RevWalk<'a> {
+        RevWalk {
+            odb,
+            tips: Vec::new(),
+            hidden: Vec::new(),
+            sort: Sort::default(),
+            first_parent: false,
+            max_count: None,
+            since: None,
+            until: None,
+            author: None,
+            grep: None,
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, oid: ObjectId) {
+        self.tips.push(oid);
+    }
+
+    /// Leaves `oid` and everything reachable from it out of the walk.
+    pub fn hide(&mut self, oid: ObjectId) {
+        self.hidden.push(oid);
+    }
+
+    /// Adds a revision argument: `B`, `^A`, `A..B` (in B but not A) or
+    /// `A...B` (in either but not both). An empty side means `HEAD`.
+    pub fn push_revision(&mut self, spec: &str) -> Result<()> {
+        if let Some((a, b)) = spec.split_once("...") {
+            let a = self.resolve_commit(a)?;
+            let b = self.resolve_commit(b)?;
+            // what both have in common is what their merge bases lead to
+            for base in merge_bases(self.odb, a, &[b])? {
+                self.hide(base);
+            }
+            self.push(a);
+            self.push(b);
+        } else if let Some((a, b)) = spec.split_once("..") {
+            let a = self.resolve_commit(a)?;
+            let b = self.resolve_commit(b)?;
+            self.hide(a);
+            self.push(b);
+        } else if let Some(rev) = spec.strip_prefix('^') {
+            let oid = self.resolve_commit(rev)?;
+            self.hide(oid);
+        } else {
+            let oid = self.resolve_commit(spec)?;
+            self.push(oid);
+        }
+        Ok(())
+    }
+
+    fn resolve_commit(&self, rev: &str) -> Result<ObjectId> {
+        let rev = if rev.is_empty() { "HEAD" } else { rev };
+        let oid = resolve_revision(self.odb, rev)?;
+        peel(self.odb, oid, Some(ObjectKind::Commit))
+            .with_context(|| format!("'{}' is not a commit", rev))
+    }
+
+    /// Walks the history and returns the commits to show, in `sort` order.
+    pub fn run(&self) -> Result<Vec<WalkedCommit>> {
+        // walk newest-first, remembering which parents each commit leads to
+        let mut commits: HashMap<ObjectId, Commit> = HashMap::new();
+        let mut followed: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+        let mut touches_paths: HashSet<ObjectId> = HashSet::new();
+        let mut walk_order = Vec::new();
+        // reachable from a hidden commit, as far as the walk has seen
+        let mut uninteresting: HashSet<ObjectId> = self.hidden.iter().copied().collect();
+
+        let mut queue = BinaryHeap::new();
+        let mut queued = HashSet::new();
+        let mut sequence = 0u64;
+        let mut enqueue = |oid: ObjectId, queue: &mut BinaryHeap<_>| -> Result<()> {
+            if queued.insert(oid) {
+                let time = self.odb.read_commit(&oid)?.committer.time;
+                queue.push((time, Reverse(sequence), oid));
+                sequence += 1;
+            }
+            Ok(())
+        };
+        for &tip in self.hidden.iter().chain(&self.tips) {
+            enqueue(tip, &mut queue)?;
+        }
+        // when every commit walked is shown, there's no need to go further
+        // back than the commits asked for
+        let stop_at = match (self.sort, self.paths.is_empty(), self.has_filters()) {
+            (Sort::Chronological, true, false) => self.max_count,
+            _ => None,
+        };
+
+        while let Some((_, _, oid)) = queue.pop() {
+            let commit = self.odb.read_commit(&oid)?;
+            if uninteresting.contains(&oid) {
+                for &parent in &commit.parent_commits {
+                    mark_uninteresting(parent, &commits, &mut uninteresting);
+                    enqueue(parent, &mut queue)?;
+                }
+            } else {
+                let mut parents = commit.parent_commits.clone();
+                if self.first_parent {
+                    parents.truncate(1);
+                }
+                if self.paths.is_empty() {
+                    touches_paths.insert(oid);
+                } else {
+                    let (touches, simplified) = self.simplify(&commit, parents)?;
+                    if touches {
+                        touches_paths.insert(oid);
+                    }
+                    parents = simplified;
+                }
+
+                for &parent in &parents {
+                    enqueue(parent, &mut queue)?;
+                }
+                walk_order.push(oid);
+                followed.insert(oid, parents);
+                commits.insert(oid, commit);
+                if stop_at.is_some_and(|max| walk_order.len() >= max) {
+                    break;
+                }
+            }
+            // what's left can only lead to more hidden commits
+            if queue.iter().all(|(_, _, oid)| uninteresting.contains(oid)) {
+                break;
+            }
+        }
+
+        // commits found to be hidden after they were walked (clock skew)
+        walk_order.retain(|oid| !uninteresting.contains(oid));
+        commits.retain(|oid, _| !uninteresting.contains(oid));
+        followed.retain(|oid, _| !uninteresting.contains(oid));
+
+        let order = match self.sort {
+            Sort::Chronological => walk_order,
+            sort => topo_sort(&commits, &followed, sort),
+        };
+        let shown: HashSet<ObjectId> = order
+            .iter()
+            .filter(|oid| touches_paths.contains(oid) && self.matches(&commits[oid]))
+            .copied()
+            .collect();
+
+        // rewrite parents to the nearest shown ancestors; parents come before
+        // their children when walking a topological order backwards. Commits
+        // the walk stopped before reaching are left as they are.
+        let mut nearest_shown: HashMap<ObjectId, Vec<ObjectId>> = queue
+            .iter()
+            .map(|(_, _, oid)| *oid)
+            .filter(|oid| !uninteresting.contains(oid))
+            .map(|oid| (oid, vec![oid]))
+            .collect();
+        let topo = match self.sort {
+            Sort::Chronological => topo_sort(&commits, &followed, Sort::Date),
+            _ => order.clone(),
+        };
+        for oid in topo.iter().rev() {
+            let nearest = match shown.contains(oid) {
+                true => vec![*oid],
+                false => rewrite_parents(&followed[oid], &nearest_shown),
+            };
+            nearest_shown.insert(*oid, nearest);
+        }
+
+        let mut result = Vec::new();
+        for oid in order {
+            if !shown.contains(&oid) {
+                continue;
+            }
+            if self.max_count.is_some_and(|max| result.len() >= max) {
+                break;
+            }
+            let parents = rewrite_parents(&followed[&oid], &nearest_shown);
+            let commit = commits.remove(&oid).expect("walked commits are loaded");
+            result.push(WalkedCommit {
+                oid,
+                commit,
+                parents,
+            });
+        }
+        Ok(result)
+    }
+
+    fn has_filters(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || self.author.is_some() || self.grep.is_some()
+    }
+
+    /// Whether the commit passes the date, author and message filters.
+    fn matches(&self, commit: &Commit) -> bool {
+        let time = commit.committer.time;
+        let author = format!("{} <{}>", commit.author.name, commit.author.email);
+        self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+            && self.author.as_ref().is_none_or(|a| author.contains(a.as_str()))
+            && self.grep.as_ref().is_none_or(|g| commit.message.contains(g.as_str()))
+    }
+
+    /// Whether `commit` changes any of the walk's paths, and the parents to
+    /// follow: a commit that is TREESAME to one of its parents (the paths
+    /// are identical there) only leads to that parent.
+    fn simplify(&self, commit: &Commit, parents: Vec<ObjectId>) -> Result<(bool, Vec<ObjectId>)> {
+        let ours = self.path_ids(commit.tree)?;
+        if parents.is_empty() {
+            return Ok((ours.iter().any(Option::is_some), parents));
+        }
+        for &parent in &parents {
+            let tree = self.odb.read_commit(&parent)?.tree;
+            if self.path_ids(tree)? == ours {
+                return Ok((false, vec![parent]));
+            }
+        }
+        Ok((true, parents))
+    }
+
+    /// The object at each of the walk's paths in `tree`.
+    fn path_ids(&self, tree: ObjectId) -> Result<Vec<Option<ObjectId>>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                let path = path.trim_start_matches("./").trim_end_matches('/');
+                let path = if path == "." { "" } else { path };
+                find_in_tree(self.odb, tree, path)
+            })
+            .collect()
+    }
+}
+
+/// Marks `oid` as reachable from a hidden commit, along with whatever the
+/// walk already went past beyond it.
+fn mark_uninteresting(
+    oid: ObjectId,
+    commits: &HashMap<ObjectId, Commit>,
+    uninteresting: &mut HashSet<ObjectId>,
+) {
+    let mut stack = vec![oid];
+    while let Some(oid) = stack.pop() {
+        if uninteresting.insert(oid) {
+            if let Some(commit) = commits.get(&oid) {
+                stack.extend(&commit.parent_commits);
+            }
+        }
+    }
+}
+
+fn rewrite_parents(
+    parents: &[ObjectId],
+    nearest_shown: &HashMap<ObjectId, Vec<ObjectId>>,
+) -> Vec<ObjectId> {
+    let mut rewritten: Vec<ObjectId> = Vec::new();
+    for parent in parents {
+        for &oid in nearest_shown.get(parent).into_iter().flatten() {
+            if !rewritten.contains(&oid) {
+                rewritten.push(oid);
+            }
+        }
+    }
+    rewritten
+}
+
+/// Orders commits so that no parent comes before any of its children.
+fn topo_sort(
+    commits: &HashMap<ObjectId, Commit>,
+    followed: &HashMap<ObjectId, Vec<ObjectId>>,
+    sort: Sort,
+) -> Vec<ObjectId> {
+    let mut children: HashMap<ObjectId, usize> = HashMap::new();
+    for parents in followed.values() {
+        for parent in parents.iter().filter(|p| commits.contains_key(p)) {
+            *children.entry(*parent).or_default() += 1;
+        }
+    }
+    let key = |oid: &ObjectId| match sort {
+        Sort::AuthorDate => commits[oid].author.time,
+        _ => commits[oid].committer.time,
+    };
+    let mut ready: Vec<ObjectId> = commits
+        .keys()
+        .filter(|oid| !children.contains_key(oid))
+        .copied()
+        .collect();
+    ready.sort_by_key(|oid| (key(oid), *oid));
+
+    let mut order = Vec::with_capacity(commits.len());
+    if sort == Sort::Topo {
+        // depth first: finish a line of history before going back to the
+        // next tip, starting (like git) with the last parent of a merge
+        let mut stack = ready;
+        while let Some(oid) = stack.pop() {
+            order.push(oid);
+            for parent in &followed[&oid] {
+                if let Some(count) = children.get_mut(parent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        stack.push(*parent);
+                    }
+                }
+            }
+        }
+    } else {
+        let mut heap: BinaryHeap<(i64, ObjectId)> =
+            ready.into_iter().map(|oid| (key(&oid), oid)).collect();
+        while let Some((_, oid)) = heap.pop() {
+            order.push(oid);
+            for parent in &followed[&oid] {
+                if let Some(count) = children.get_mut(parent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        heap.push((key(parent), *parent));
+                    }
+                }
+            }
+        }
+    }
+    order
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use super::{IndexEntry, ObjectDatabase, ObjectId, ObjectKind};
+
+/// How a tracked file on disk compares to its index entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Unchanged,
+    Modified,
+    Deleted,
+}
+
+/// Every blob (and submodule) in `tree`, keyed by its full path, with its mode.
+pub fn flatten_tree(
+    odb: &ObjectDatabase,
+    tree: &ObjectId,
+) -> Result<BTreeMap<String, (u32, ObjectId)>> {
+    let mut files = BTreeMap::new();
+    flatten_into(odb, tree, "", &mut files)?;
+    Ok(files)
+}
+
+fn flatten_into(
+    odb: &ObjectDatabase,
+    tree: &ObjectId,
+    prefix: &str,
+    files: &mut BTreeMap<String, (u32, ObjectId)>,
+) -> Result<()> {
+    for entry in odb.read_tree(tree)?.entries {
+        let path = format!("{}{}", prefix, entry.name);
+        if entry.is_tree() {
+            flatten_into(odb, &entry.hash, &format!("{}/", path), files)?;
+        } else {
+            files.insert(path, (entry.mode, entry.hash));
+        }
+    }
+    Ok(())
+}
+
+/// Refuses paths that would reach outside the work tree or into a
+/// repository when written: empty, `.` and `..` components, `.git` anywhere,
+/// and our own `ugit` directory at the top.
+pub fn check_path(path: &str) -> Result<()> {
+    let unsafe_path = path.contains('\0')
+        || path.split('/').enumerate().any(|(depth, name)| {
+            matches!(name, "" | "." | "..")
+                || name.eq_ignore_ascii_case(".git")
+                || (depth == 0 && name.eq_ignore_ascii_case("ugit"))
+        });
+    anyhow::ensure!(!unsafe_path, "invalid path '{}'", path);
+    Ok(())
+}
+
+/// What a file on disk would be stored as: the link target for symlinks,
+/// the file content otherwise.
+pub fn read_worktree_file(path: &str, metadata: &fs::Metadata) -> Result<Vec<u8>> {
+    if metadata.file_type().is_symlink() {
+        let target =
+            fs::read_link(path).with_context(|| format!("couldn't read symlink {}", path))?;
+        Ok(target.as_os_str().as_bytes().to_vec())
+    } else {
+        fs::read(path).with_context(|| format!("couldn't read {}", path))
+    }
+}
+
+/// Compares the file at `entry.path` with the entry, only rehashing it when
+/// its stat data no longer matches.
+pub fn check_file(entry: &IndexEntry) -> Result<FileState> {
+    if entry.mode == 0o160000 {
+        return Ok(FileState::Unchanged); // submodules aren't checked out
+    }
+    let metadata = match fs::symlink_metadata(&entry.path) {
+        Ok(metadata) if metadata.is_dir() => return Ok(FileState::Deleted),
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(FileState::Deleted),
+        Err(err) => return Err(err).with_context(|| format!("couldn't stat {}", entry.path)),
+    };
+    if entry.stat_matches(&metadata) {
+        return Ok(FileState::Unchanged);
+    }
+
+    let content = read_worktree_file(&entry.path, &metadata)?;
+    let unchanged = IndexEntry::mode_from_metadata(&metadata) == entry.mode
+        && ObjectDatabase::hash(ObjectKind::Blob, &content) == entry.sha1;
+    Ok(match unchanged {
+        true => FileState::Unchanged,
+        false => FileState::Modified,
+    })
+}
+
+/// Writes the blob `oid` to `path` as a regular file, an executable or a
+/// symlink depending on `mode`, and returns its new index entry.
+pub fn checkout_file(
+    odb: &ObjectDatabase,
+    path: &str,
+    mode: u32,
+    oid: &ObjectId,
+) -> Result<IndexEntry> {
+    check_path(path)?;
+    let file = Path::new(path);
+    if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't create directory {}", dir.display()))?;
+    }
+    // whatever is in the way (a file of another type, an empty directory) goes
+    match fs::symlink_metadata(file) {
+        Ok(metadata) if metadata.is_dir() => {
+            if mode != 0o160000 {
+                fs::remove_dir(file)
+                    .with_context(|| format!("'{}' is a directory in the way", path))?;
+            }
+        }
+        Ok(_) => fs::remove_file(file).with_context(|| format!("couldn't replace {}", path))?,
+        Err(_) => {}
+    }
+
+    match mode {
+        0o160000 => {
+            fs::create_dir_all(file).with_context(|| format!("couldn't create {}", path))?
+        }
+        0o120000 => {
+            let target = odb.read_raw(oid)?.1;
+            let target = std::ffi::OsStr::from_bytes(&target);
+            std::os::unix::fs::symlink(target, file)
+                .with_context(|| format!("couldn't create symlink {}", path))?;
+        }
+        _ => {
+            let content = odb.read_raw(oid)?.1;
+            fs::write(file, content).with_context(|| format!("couldn't write {}", path))?;
+            let permissions = if mode == 0o100755 { 0o755 } else { 0o644 };
+            fs::set_permissions(file, fs::Permissions::from_mode(permissions))
+                .with_context(|| format!("couldn't set the mode of {}", path))?;
+        }
+    }
+
+    let metadata = fs::symlink_metadata(file).with_context(|| format!("couldn't stat {}", path))?;
+    Ok(IndexEntry::from_metadata(
+        path.to_string(),
+        mode,
+        *oid,
+        &metadata,
+    ))
+}
+
+/// Removes a tracked file and any directories that are left empty.
+pub fn remove_file(path: &str) -> Result<()> {
+    let file = Path::new(path);
+    match fs::symlink_metadata(file) {
+        Ok(metadata) if !metadata.is_dir() => {
+            fs::remove_file(file).with_context(|| format!("couldn't remove {}", path))?
+        }
+        _ => return Ok(()),
+    }
+    for dir in file.ancestors().skip(1) {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break; // not empty (or the top of the work tree)
+        }
+    }
+    Ok(())
+}
@@ -1,25 +1,10 @@
-use anyhow::{Context, Result};
-use std::fs;
+use anyhow::Result;
 
-/// Given a hash, return the full path to the object file.
-pub fn get_full_path_from_hash(hash: &str) -> Result<String> {
-    let prefix = &hash[..2];
-    let suffix = &hash[2..];
-
-    let object_dir = format!("./ugit/objects/{}", prefix);
-
-    // Search for a matching file in the directory
-    let entries = fs::read_dir(object_dir).context("Couldn't read the object directory")?;
+use crate::repository::ObjectDatabase;
 
-    for entry in entries {
-        let entry = entry.context("Couldn't get entry in object directory")?;
-        let file_name = entry.file_name();
-        let file_name = file_name.to_string_lossy();
-
-        if file_name.starts_with(suffix) {
-            return Ok(format!("{}/{}", prefix, file_name));
-        }
-    }
-
-    anyhow::bail!("No matching object found for the given hash prefix.")
+/// Given a (possibly abbreviated) hash, return the path of its loose object
+/// file relative to `ugit/objects`. Ambiguous or too short prefixes are errors.
+pub fn get_full_path_from_hash(hash: &str) -> Result<String> {
+    let hash = ObjectDatabase::default().resolve(hash)?.to_hex();
+    Ok(format!("{}/{}", &hash[..2], &hash[2..]))
 }